@@ -80,30 +80,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let sin_sum_source = sin_sum.wavetable(1024).source(44100).with_frequency(100.0);
 
-    // Estimates the amplitude and pitch of the sin wave contribution of the given
-    // frequency
-    // I'm not showing how the phase can be instead be estimated by brute force,
-    // because that's less cool
-    let calculate_amp_phase = |freq: f32, dim| {
-        let sin = (|t: f32| (t * TAU).sin()).source(44100);
-        let cos = (|t: f32| (t * TAU).cos()).source(44100);
-        let sin_contribution = 2.0
-            * dot_prod_samples(sin_sum_source.clone(), sin.with_frequency(freq), dim)
-            / dim as f32;
-        let cos_contribution = 2.0
-            * dot_prod_samples(sin_sum_source.clone(), cos.with_frequency(freq), dim)
-            / dim as f32;
-
-        let amplitude =
-            (sin_contribution * sin_contribution + cos_contribution * cos_contribution).sqrt();
-        let phase = cos_contribution.atan2(sin_contribution).rem_euclid(TAU);
-
-        (amplitude, phase)
-    };
-
-    let (approx_amp1, approx_phase1) = calculate_amp_phase(100.0, 10000);
-    let (approx_amp2, approx_phase2) = calculate_amp_phase(200.0, 10000);
-    let (approx_amp3, approx_phase3) = calculate_amp_phase(300.0, 10000);
+    // Estimates the amplitude and phase of the sin wave contribution of each frequency.
+    // A rectangular window over a whole number of periods gives exact results here.
+    let analysis = SinusoidalAnalysis::new(44100).with_window(Window::Rectangular);
+    let estimates = analysis.estimate(sin_sum_source.clone(), &[100.0, 200.0, 300.0]);
+    let (approx_amp1, approx_phase1) = (estimates[0].amplitude, estimates[0].phase);
+    let (approx_amp2, approx_phase2) = (estimates[1].amplitude, estimates[1].phase);
+    let (approx_amp3, approx_phase3) = (estimates[2].amplitude, estimates[2].phase);
 
     println!(
         "Initial amplitudes:   {amp1}, {amp2}, {amp3} \n\
@@ -113,6 +96,15 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
          Estimated phases:     {approx_phase1}, {approx_phase2}, {approx_phase3}"
     );
 
+    // Without knowing the frequencies ahead of time, look for peaks in the spectrum
+    let found = SinusoidalAnalysis::new(8192).find_partials(sin_sum_source.clone(), 3, 60.0);
+    for partial in &found {
+        println!(
+            "Found partial:        {:.2}Hz, amplitude {:.4}",
+            partial.frequency, partial.amplitude
+        );
+    }
+
     // Play audio
     let (_stream, stream_handle) = OutputStream::try_default().unwrap();
     let sink = Sink::try_new(&stream_handle).unwrap();
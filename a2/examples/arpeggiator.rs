@@ -1,8 +1,9 @@
-use crossbeam_channel::select;
+use a2::bevy_midi::clock::{ClockFollower, PPQN};
+use a2::bevy_midi::message::MidiMessage;
+use crossbeam_channel::RecvTimeoutError;
 use midir::{MidiInput, MidiOutput, MidiOutputPort};
 use rand::Rng;
-use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::{
     error::Error,
     io::{stdin, stdout, Write},
@@ -11,7 +12,6 @@ use std::{
 
 fn main() {
     let (sender, receiver) = crossbeam_channel::unbounded::<Event>();
-    let (sender_play, receiver_play) = crossbeam_channel::unbounded::<()>();
     let mut input = String::new();
 
     let mut midi_in = MidiInput::new("Arpeggiator-in").unwrap();
@@ -42,7 +42,6 @@ fn main() {
     };
 
     let s = sender.clone();
-    let s_play = sender_play.clone();
     let conn_in = midi_in
         .connect(
             in_port,
@@ -50,11 +49,15 @@ fn main() {
             move |_, message, _| match message[0] & 0b11110000 {
                 0b10010000 => {
                     s.send(Event::NoteAdd(message[1]));
-                    s_play.send(());
                 }
                 0b10000000 => {
                     s.send(Event::NoteRemove(message[1]));
-                    s_play.send(());
+                }
+                // The clock to follow as a slave
+                0b11110000 => {
+                    if let Ok(msg) = MidiMessage::from_bytes(message) {
+                        let _ = s.send(Event::Clock(msg, Instant::now()));
+                    }
                 }
                 _ => {}
             },
@@ -144,8 +147,24 @@ fn main() {
 
                     sender.send(Event::ModeChange(mode));
                 }
+                Some("sync") => {
+                    let clock_mode = match iter.next().map(|m| m.trim()) {
+                        Some("master") => ClockMode::Master,
+                        Some("slave") => ClockMode::Slave,
+                        Some(_) => {
+                            println!("Invalid argument: expected \"master\" or \"slave\"");
+                            continue;
+                        }
+                        None => {
+                            println!("Too few arguments: expected \"master\" or \"slave\"");
+                            continue;
+                        }
+                    };
+
+                    let _ = sender.send(Event::ClockModeChange(clock_mode));
+                }
                 Some("help") => {
-                    println!("Valid commands are \"help\", \"exit\", \"tempo <f32>\", and \"mode <up|down|up_down|random>\"");
+                    println!("Valid commands are \"help\", \"exit\", \"tempo <f32>\", \"mode <up|down|up_down|random>\", and \"sync <master|slave>\"");
                 }
                 _ => {}
             }
@@ -155,37 +174,96 @@ fn main() {
     let mut notes = Vec::<u8>::new();
     let mut tempo = 240.0;
     let mut mode = Mode::Up;
+    let mut clock_mode = ClockMode::Master;
+    let mut follower = ClockFollower::new();
     let mut index = 0;
     let mut rng = rand::thread_rng();
     let mut is_up = true;
+    // The note to turn off at the next step
+    let mut playing: Option<u8> = None;
+    // As the master, the next clock to send and when
+    let mut pulse = 0;
+    let mut next_pulse = Instant::now();
+
+    // Each beat (every 24 clocks) is a step, whether the clocks are sent or followed
+    let _ = conn_out.send(&MidiMessage::Start.to_bytes());
     loop {
-        while let Ok(event) = receiver.try_recv() {
-            match event {
-                Event::Exit => break,
-                Event::NoteAdd(new_note) => {
-                    if notes.len() == 0 {
-                        notes.push(new_note);
-                    } else {
-                        let i = notes.partition_point(|x| *x < new_note);
-
-                        if i == notes.len() || notes[i] != new_note {
-                            notes.insert(i, new_note);
-                        }
+        let event = match clock_mode {
+            ClockMode::Master => match receiver.recv_deadline(next_pulse) {
+                Ok(event) => Some(event),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            },
+            ClockMode::Slave => match receiver.recv() {
+                Ok(event) => Some(event),
+                Err(_) => break,
+            },
+        };
+        let step = match event {
+            None => {
+                let _ = conn_out.send(&MidiMessage::TimingClock.to_bytes());
+                let step = pulse % PPQN == 0;
+                pulse += 1;
+                next_pulse += Duration::from_secs_f32(60.0 / tempo / PPQN as f32);
+                step
+            }
+            Some(Event::Clock(msg, at)) => {
+                follower.update(&msg, at);
+                clock_mode == ClockMode::Slave
+                    && msg == MidiMessage::TimingClock
+                    && follower.is_playing()
+                    && follower.pulse().map_or(false, |pulse| pulse % PPQN == 0)
+            }
+            Some(Event::Exit) => break,
+            Some(Event::NoteAdd(new_note)) => {
+                if notes.len() == 0 {
+                    notes.push(new_note);
+                } else {
+                    let i = notes.partition_point(|x| *x < new_note);
+
+                    if i == notes.len() || notes[i] != new_note {
+                        notes.insert(i, new_note);
                     }
                 }
-                Event::NoteRemove(old_note) => {
-                    if let Ok(index) = notes.binary_search(&old_note) {
-                        notes.remove(index);
+                false
+            }
+            Some(Event::NoteRemove(old_note)) => {
+                if let Ok(index) = notes.binary_search(&old_note) {
+                    notes.remove(index);
+                };
+                false
+            }
+            Some(Event::TempoChange(new_tempo)) => {
+                tempo = new_tempo;
+                false
+            }
+            Some(Event::ModeChange(new_mode)) => {
+                mode = new_mode;
+                false
+            }
+            Some(Event::ClockModeChange(new_clock_mode)) => {
+                if new_clock_mode != clock_mode {
+                    let msg = match new_clock_mode {
+                        ClockMode::Master => MidiMessage::Start,
+                        ClockMode::Slave => MidiMessage::Stop,
                     };
+                    let _ = conn_out.send(&msg.to_bytes());
+                    clock_mode = new_clock_mode;
+                    pulse = 0;
+                    next_pulse = Instant::now();
                 }
-                Event::TempoChange(new_tempo) => tempo = new_tempo,
-                Event::ModeChange(new_mode) => mode = new_mode,
+                false
             }
-        }
-
-        receiver_play.recv();
+        };
 
-        if notes.len() == 0 {
+        // Stop the note playing when stepping, or when the clock followed stops
+        let stopped = clock_mode == ClockMode::Slave && !follower.is_playing();
+        if step || stopped {
+            if let Some(note) = playing.take() {
+                let _ = conn_out.send(&[0b10000000, note, 0x64]);
+            }
+        }
+        if !step || notes.len() == 0 {
             continue;
         }
         index = index.min(notes.len() - 1);
@@ -220,11 +298,15 @@ fn main() {
         let note = notes[index];
 
         let _ = conn_out.send(&[0b10010000, note, 0x64]);
-        std::thread::sleep(Duration::from_secs_f32(60.0 / tempo));
-        let _ = conn_out.send(&[0b10000000, note, 0x64]);
-        sender_play.send(());
+        playing = Some(note);
     }
 
+    if let Some(note) = playing {
+        let _ = conn_out.send(&[0b10000000, note, 0x64]);
+    }
+    if clock_mode == ClockMode::Master {
+        let _ = conn_out.send(&MidiMessage::Stop.to_bytes());
+    }
     conn_out.close();
 }
 
@@ -234,6 +316,9 @@ enum Event {
     NoteRemove(u8),
     TempoChange(f32),
     ModeChange(Mode),
+    ClockModeChange(ClockMode),
+    // A clock message from the input, and when it arrived
+    Clock(MidiMessage, Instant),
 }
 
 #[derive(Clone, Copy)]
@@ -243,3 +328,10 @@ enum Mode {
     UpDown,
     Random,
 }
+
+// Whether the arpeggiator sends the midi clock, or follows the input's
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ClockMode {
+    Master,
+    Slave,
+}
@@ -1,6 +1,7 @@
 use std::f32::consts::{FRAC_PI_2, TAU};
 
 use a2::complex::*;
+use a2::plot::{arrow, circle};
 use plotters::coord::types::RangedCoordf32;
 use plotters::prelude::*;
 
@@ -23,6 +24,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         (0..300, 0..300),
     ));
 
+    // The clock face, and its hands
+    root.draw(&circle(Complex::ZERO, 3.5, BLACK))?;
+    root.draw(&arrow(Complex::ZERO, z_hour, BLACK))?;
+    root.draw(&arrow(Complex::ZERO, z_min, BLACK))?;
+    root.draw(&arrow(Complex::ZERO, z_sec, RED))?;
+
     root.draw(&Complex::default().as_labelled_point(BLACK, "Origin"))?;
     root.draw(&z_hour.as_labelled_point(BLACK, "Hour"))?;
     root.draw(&z_min.as_labelled_point(BLACK, "Minute"))?;
@@ -0,0 +1,33 @@
+use std::time::Duration;
+
+use a2::prelude::*;
+use rodio::{OutputStream, Sink, Source};
+
+// Prints the harmonics of the sawtooth table, then plays it next to a band-limited
+// sawtooth built from the same harmonics, cut off at 20
+fn main() {
+    let (_stream, stream_handle) = OutputStream::try_default().unwrap();
+    let sink = Sink::try_new(&stream_handle).unwrap();
+
+    let sawtooth = wave::sawtooth.wavetable(1024);
+    let harmonics = sawtooth.harmonics();
+    for (k, (amplitude, phase)) in harmonics.iter().take(8).enumerate() {
+        println!(
+            "Harmonic {}: amplitude {amplitude:.4}, phase {phase:.4}",
+            k + 1
+        );
+    }
+
+    let band_limited = WaveTable::from_harmonics(1024, &harmonics[..20]);
+
+    let adsr = Envelope::adsr(0.8, 0.1, 0.1, 0.5, 0.5, 0.3);
+    for table in [sawtooth, band_limited] {
+        let source = table.source(44100).with_frequency(220.0);
+        sink.append(
+            adsr.source_from(source)
+                .amplify(0.2)
+                .take_duration(Duration::from_secs(1)),
+        );
+    }
+    sink.sleep_until_end();
+}
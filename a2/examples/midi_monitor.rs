@@ -0,0 +1,46 @@
+use a2::bevy_midi::input::*;
+use bevy::prelude::*;
+
+// Prints every message arriving on the first available midi input port
+fn main() {
+    App::new()
+        .add_plugins(MinimalPlugins)
+        .insert_resource(MidiInputSettings {
+            port_name: "midi_monitor",
+            ..default()
+        })
+        .add_plugin(MidiInputPlugin)
+        .add_system(connect_to_first_port)
+        .add_system(show_connection)
+        .add_system(print_messages)
+        .run()
+}
+
+fn connect_to_first_port(input: Res<MidiInput>, conn: Res<MidiInputConnection>) {
+    if input.is_changed() && !conn.is_connected() {
+        match input.ports().first() {
+            Some((name, port)) => {
+                println!("Connecting to {}", name);
+                if let Err(e) = input.connect(port.clone()) {
+                    println!("Error: {}", e);
+                }
+            }
+            None => println!("No input ports found"),
+        }
+    }
+}
+
+fn show_connection(conn: Res<MidiInputConnection>) {
+    if conn.is_changed() {
+        println!("Connected: {}", conn.is_connected());
+    }
+}
+
+fn print_messages(mut midi: EventReader<MidiData>, mut errors: EventReader<MidiInputError>) {
+    for data in midi.iter() {
+        println!("{:>12}us: {:02x?}", data.stamp, data.message);
+    }
+    for error in errors.iter() {
+        println!("Error: {}", error);
+    }
+}
@@ -0,0 +1,31 @@
+use a2::plot::animate_phasors;
+use a2::prelude::*;
+
+// Renders the first few harmonics of a sawtooth wave as rotating phasors to phasors.gif,
+// and the poles and zeros of the subtractive synth's lowpass filter to pole_zero.png
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let partials: Vec<Partial> = wave::sawtooth
+        .wavetable(1024)
+        .harmonics()
+        .into_iter()
+        .take(4)
+        .enumerate()
+        .map(|(k, (amplitude, phase))| Partial {
+            frequency: (k + 1) as f32,
+            amplitude,
+            phase,
+        })
+        .collect();
+    animate_phasors("phasors.gif", &partials, (400, 400), 1.0, 60, 50)?;
+
+    let lowpass_biquad = BiQuad::new(
+        -7.17336609e-17,
+        0.17149959,
+        0.29287490,
+        0.58574979,
+        0.29287490,
+    );
+    lowpass_biquad.render_pole_zero_png("pole_zero.png", (400, 400), "Lowpass BiQuad")?;
+
+    Ok(())
+}
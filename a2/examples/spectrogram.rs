@@ -0,0 +1,41 @@
+use a2::prelude::*;
+use rodio::Source;
+use std::time::Duration;
+
+// Renders the spectrograms of a sawtooth wave, before and after a lowpass filter, to
+// spectrogram_saw.png and spectrogram_lowpass.png.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let lowpass_biquad = BiQuad::new(
+        -7.17336609e-17,
+        0.17149959,
+        0.29287490,
+        0.58574979,
+        0.29287490,
+    );
+    let adsr = Envelope::adsr(1.0, 0.05, 0.2, 0.6, 1.0, 0.5);
+    let saw = wave::sawtooth
+        .wavetable(1024)
+        .source(44100)
+        .with_frequency(220.0);
+
+    let stft = Stft::new(2048, 512)
+        .with_window(Window::BlackmanHarris)
+        .with_fft_len(4096);
+
+    let pure_source = adsr
+        .source_from(saw.clone())
+        .take_duration(Duration::from_secs(2));
+    stft.analyze(pure_source)
+        .render_png("spectrogram_saw.png", (1024, 768), "Sawtooth")?;
+
+    let lowpass_source = adsr
+        .source_from(lowpass_biquad.source_from(saw))
+        .take_duration(Duration::from_secs(2));
+    stft.analyze(lowpass_source).render_png(
+        "spectrogram_lowpass.png",
+        (1024, 768),
+        "Lowpassed Sawtooth",
+    )?;
+
+    Ok(())
+}
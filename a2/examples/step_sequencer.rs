@@ -1,7 +1,20 @@
+use a2::bevy_midi::clock::ClockFollower;
+use a2::bevy_midi::input::{MidiInput, MidiInputPlugin, MidiInputSettings};
+use a2::bevy_midi::message::MidiMessage;
 use a2::bevy_midi::output::*;
+use a2::bevy_midi::player::{MidiPlayer, MidiPlayerPlugin};
+use a2::bevy_midi::recorder::{MidiRecorder, MidiRecorderPlugin};
+use a2::bevy_midi::schedule::BeatClock;
+use a2::bevy_midi::smf::Smf;
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContext, EguiPlugin};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+// How far ahead of time steps are scheduled, which needs to cover the longest frame
+const LOOKAHEAD: Duration = Duration::from_millis(100);
+
+// Where recordings are saved to and played back from
+const RECORDING_PATH: &str = "step_sequencer.mid";
 
 fn main() {
     App::new()
@@ -9,13 +22,27 @@ fn main() {
         .add_plugin(EguiPlugin)
         .insert_resource(MidiOutputSettings {
             port_name: "interactive_example",
+            // Lets synths connect to the sequencer directly
+            virtual_port: Some("step_sequencer"),
+            ..default()
         })
         .add_plugin(MidiOutputPlugin)
+        .insert_resource(MidiInputSettings {
+            port_name: "interactive_example",
+            // Lets a DAW or drum machine send its clock to the sequencer directly
+            virtual_port: Some("step_sequencer"),
+            ..default()
+        })
+        .add_plugin(MidiInputPlugin)
+        .add_plugin(MidiRecorderPlugin)
+        .add_plugin(MidiPlayerPlugin)
         .init_resource::<StepSequencer>()
-        .add_system(play)
+        .add_system(follow_clock)
+        .add_system(play.after(follow_clock))
         .add_system(step_sequencer_windows.after(play))
         .add_system(midi_port_window.after(step_sequencer_windows))
         .add_system(main_window.after(midi_port_window))
+        .add_system(recording_window.after(main_window))
         .run()
 }
 
@@ -93,31 +120,39 @@ fn main_window(
 ) {
     egui::Window::new("").show(egui_context.ctx_mut(), |ui| {
         ui.horizontal(|ui| {
-            if ui.button("Play").clicked() {
-                commands.insert_resource(StepSequencePlayer {
-                    timer: Timer::new(
-                        Duration::from_secs_f32(60.0 / step_sequencer.tempo),
-                        TimerMode::Repeating,
-                    ),
-                    index: None,
-                });
-            }
-            if ui.button("Stop").clicked() {
-                commands.remove_resource::<StepSequencePlayer>();
-
-                // Stop all currently playing notes
-                if let Some(player) = player {
-                    if let Some(index) = player.index {
-                        for layer in step_sequencer.layers.iter() {
-                            for ev in layer.note_offs_at(index) {
-                                output.send(ev);
-                            }
-                        }
+            ui.label("Clock: ");
+            let current = step_sequencer.clock_mode;
+            for (mode, label) in [(ClockMode::Master, "Master"), (ClockMode::Slave, "Slave")] {
+                if ui.selectable_label(current == mode, label).clicked() && current != mode {
+                    step_sequencer.clock_mode = mode;
+                    if player.is_some() {
+                        stop(&mut commands, &output, current);
                     }
                 }
             }
         });
 
+        // As a slave, playing starts and stops with the clock being followed
+        if step_sequencer.clock_mode == ClockMode::Master {
+            ui.horizontal(|ui| {
+                if ui.button("Play").clicked() {
+                    let clock = BeatClock::new(step_sequencer.tempo as f64);
+                    let started = output.set_clock(clock).and_then(|()| output.start_clock());
+                    match started {
+                        Ok(()) => commands.insert_resource(StepSequencePlayer {
+                            clock,
+                            next_step: 0,
+                            index: None,
+                        }),
+                        Err(e) => error!("{}", e),
+                    }
+                }
+                if ui.button("Stop").clicked() && player.is_some() {
+                    stop(&mut commands, &output, ClockMode::Master);
+                }
+            });
+        }
+
         let mut s = format!("{:?}", step_sequencer.tempo);
         ui.label("Tempo (bpm)");
         ui.add(egui::TextEdit::singleline(&mut s));
@@ -133,14 +168,130 @@ fn main_window(
     });
 }
 
-fn midi_port_window(mut egui_context: ResMut<EguiContext>, output: Res<MidiOutput>) {
-    egui::Window::new("Port").show(egui_context.ctx_mut(), |ui| {
+// Records everything sent and received, saving it when recording stops, and plays the
+// last recording back
+fn recording_window(
+    mut egui_context: ResMut<EguiContext>,
+    step_sequencer: Res<StepSequencer>,
+    mut recorder: ResMut<MidiRecorder>,
+    mut midi_player: ResMut<MidiPlayer>,
+    output: Res<MidiOutput>,
+) {
+    egui::Window::new("Recording").show(egui_context.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            if !recorder.is_recording() {
+                if ui.button("Record").clicked() {
+                    recorder.start();
+                }
+            } else if ui.button("Stop Recording").clicked() {
+                recorder.stop();
+                if let Err(e) = recorder.save(RECORDING_PATH, step_sequencer.tempo as f64) {
+                    error!("{}", e);
+                }
+            }
+
+            if !midi_player.is_playing() {
+                if ui.button("Play Recording").clicked() {
+                    match Smf::read(RECORDING_PATH) {
+                        Ok(smf) => {
+                            if let Err(e) = midi_player.play(&smf, &output) {
+                                error!("{}", e);
+                            }
+                        }
+                        Err(e) => error!("{}", e),
+                    }
+                }
+            } else if ui.button("Stop Playing").clicked() {
+                if let Err(e) = midi_player.stop(&output) {
+                    error!("{}", e);
+                }
+            }
+        });
+    });
+}
+
+// Stops playing, dropping the steps scheduled ahead and stopping all currently playing
+// notes. As the master, the clock is stopped too.
+fn stop(commands: &mut Commands, output: &MidiOutput, mode: ClockMode) {
+    commands.remove_resource::<StepSequencePlayer>();
+    let mut stopped = output.all_notes_off();
+    if mode == ClockMode::Master {
+        stopped = stopped.and_then(|()| output.stop_clock());
+    }
+    if let Err(e) = stopped {
+        error!("{}", e);
+    }
+}
+
+// As a slave, plays along with the clock being followed
+fn follow_clock(
+    follower: Res<ClockFollower>,
+    step_sequencer: Res<StepSequencer>,
+    player: Option<ResMut<StepSequencePlayer>>,
+    output: Res<MidiOutput>,
+    mut commands: Commands,
+) {
+    if step_sequencer.clock_mode != ClockMode::Slave || !follower.is_changed() {
+        return;
+    }
+    match (follower.beat_clock(), player) {
+        (Some(clock), player) => {
+            if let Err(e) = output.set_clock(clock) {
+                error!("{}", e);
+                return;
+            }
+            match player {
+                Some(mut player) => player.clock = clock,
+                // Start from the next step, which may be part way into the song
+                None => commands.insert_resource(StepSequencePlayer {
+                    clock,
+                    next_step: clock.beat_at(Instant::now()).ceil().max(0.0) as u64,
+                    index: None,
+                }),
+            }
+        }
+        (None, Some(_)) if !follower.is_playing() => stop(&mut commands, &output, ClockMode::Slave),
+        (None, _) => {}
+    }
+}
+
+// Clicking a port connects to it alongside the others, or disconnects from it. Only one
+// input port, which the clock is followed from, can be connected to at a time.
+fn midi_port_window(
+    mut egui_context: ResMut<EguiContext>,
+    output: Res<MidiOutput>,
+    conns: Res<MidiOutputConnections>,
+    input: Res<MidiInput>,
+) {
+    egui::Window::new("Ports").show(egui_context.ctx_mut(), |ui| {
         if ui.button("Refresh Ports").clicked() {
-            output.refresh_ports();
+            if let Err(e) = output.refresh_ports() {
+                error!("{}", e);
+            }
+            if let Err(e) = input.refresh_ports() {
+                error!("{}", e);
+            }
         }
-        for (label, port) in output.ports() {
+        ui.label("Input");
+        for (label, port) in input.ports() {
             if ui.button(label).clicked() {
-                output.connect(port.clone());
+                if let Err(e) = input.connect(port.clone()) {
+                    error!("{}", e);
+                }
+            }
+        }
+        ui.label("Output");
+        for (label, port) in output.ports() {
+            let connected = conns.status(label).is_some();
+            if ui.selectable_label(connected, label).clicked() {
+                let result = if connected {
+                    output.disconnect(label)
+                } else {
+                    output.connect(port.clone())
+                };
+                if let Err(e) = result {
+                    error!("{}", e);
+                }
             }
         }
     });
@@ -150,35 +301,44 @@ fn play(
     player: Option<ResMut<StepSequencePlayer>>,
     step_sequencer: Res<StepSequencer>,
     output: Res<MidiOutput>,
-    time: Res<Time>,
+    mut commands: Commands,
 ) {
-    if let Some(mut player) = player {
-        match player.index {
-            Some(index) => {
-                if player.timer.tick(time.delta()).just_finished() {
-                    let next_index = (index + 1) % 16;
-                    for layer in step_sequencer.layers.iter() {
-                        for ev in layer.note_offs_at(index) {
-                            output.send(ev);
-                        }
-                        for ev in layer.note_ons_at(next_index) {
-                            output.send(ev);
-                        }
-                    }
+    let Some(mut player) = player else { return };
+    let now = Instant::now();
 
-                    player.index = Some(next_index);
-                }
-            }
-            None => {
-                player.index = Some(0);
-                for layer in step_sequencer.layers.iter() {
-                    for ev in layer.note_ons_at(0) {
-                        output.send(ev);
-                    }
-                }
+    // Each step is a beat long; schedule those starting before the lookahead runs out
+    let horizon = player.clock.beat_at(now + LOOKAHEAD);
+    while (player.next_step as f64) < horizon {
+        if let Err(e) = schedule_step(&step_sequencer, &output, player.next_step) {
+            error!("{}", e);
+            commands.remove_resource::<StepSequencePlayer>();
+            return;
+        }
+        player.next_step += 1;
+    }
+
+    let beat = player.clock.beat_at(now);
+    player.index = (beat >= 0.0).then_some((beat as u64 % 16) as usize);
+}
+
+// Schedules the note offs for the step before `step`, and the note ons for `step`
+fn schedule_step(
+    step_sequencer: &StepSequencer,
+    output: &MidiOutput,
+    step: u64,
+) -> Result<(), MidiOutputError> {
+    let index = (step % 16) as usize;
+    for layer in step_sequencer.layers.iter() {
+        if step > 0 {
+            for ev in layer.note_offs_at((index + 15) % 16) {
+                output.schedule_beat(ev, step as f64)?;
             }
         }
+        for ev in layer.note_ons_at(index) {
+            output.schedule_beat(ev, step as f64)?;
+        }
     }
+    Ok(())
 }
 
 #[derive(Clone)]
@@ -201,31 +361,21 @@ impl Default for StepSequenceLayer {
 }
 
 impl StepSequenceLayer {
-    fn note_offs_at(&self, index: usize) -> Vec<[u8; 3]> {
-        let mut v = Vec::new();
-        for j in 0..16 {
-            if self.data[j][index] {
-                v.push([
-                    0b10000000 | (self.channel & 0b00001111),
-                    (((15 - j) + self.lowest_value as usize) % 256) as u8,
-                    self.velocity,
-                ]);
-            }
-        }
-        v
+    // The notes of the active steps at `index`, skipping any above the midi range
+    fn notes_at(&self, index: usize) -> impl Iterator<Item = u8> + '_ {
+        (0..16)
+            .filter(move |j| self.data[*j][index])
+            .filter_map(move |j| u8::try_from(15 - j + self.lowest_value as usize).ok())
     }
-    fn note_ons_at(&self, index: usize) -> Vec<[u8; 3]> {
-        let mut v = Vec::new();
-        for j in 0..16 {
-            if self.data[j][index] {
-                v.push([
-                    0b10010000 | (self.channel & 0b00001111),
-                    (((15 - j) + self.lowest_value as usize) % 256) as u8,
-                    self.velocity,
-                ]);
-            }
-        }
-        v
+    fn note_offs_at(&self, index: usize) -> Vec<MidiMessage> {
+        self.notes_at(index)
+            .filter_map(|note| MidiMessage::note_off(self.channel, note, self.velocity).ok())
+            .collect()
+    }
+    fn note_ons_at(&self, index: usize) -> Vec<MidiMessage> {
+        self.notes_at(index)
+            .filter_map(|note| MidiMessage::note_on(self.channel, note, self.velocity).ok())
+            .collect()
     }
 }
 
@@ -233,6 +383,7 @@ impl StepSequenceLayer {
 struct StepSequencer {
     layers: Vec<StepSequenceLayer>,
     tempo: f32,
+    clock_mode: ClockMode,
 }
 
 impl Default for StepSequencer {
@@ -240,12 +391,23 @@ impl Default for StepSequencer {
         StepSequencer {
             layers: Vec::new(),
             tempo: 240.0,
+            clock_mode: ClockMode::Master,
         }
     }
 }
 
+// Whether the sequencer sends the midi clock, or follows another device's
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ClockMode {
+    Master,
+    Slave,
+}
+
 #[derive(Resource, Clone)]
 struct StepSequencePlayer {
-    timer: Timer,
+    clock: BeatClock,
+    // The first step which hasn't been scheduled yet
+    next_step: u64,
+    // The step currently playing
     index: Option<usize>,
 }
@@ -0,0 +1,364 @@
+use midir::{ConnectErrorKind, InitError, SendError};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Which midi API [`MidiOutput`](super::output::MidiOutput) talks to.
+#[derive(Clone, Debug, Default)]
+pub enum MidiOutputBackend {
+    /// The platform's midi API, through midir.
+    #[default]
+    Midir,
+    /// An in-memory [Loopback], for running without any midi hardware.
+    Loopback(Loopback),
+}
+
+impl MidiOutputBackend {
+    pub(crate) fn open(&self, name: &str) -> Result<Box<dyn OutputBackend>, InitError> {
+        match self {
+            MidiOutputBackend::Midir => Ok(Box::new(midir::MidiOutput::new(name)?)),
+            MidiOutputBackend::Loopback(loopback) => {
+                #[cfg(any(test, feature = "test-util"))]
+                {
+                    let mut state = loopback.state();
+                    if state.open_failures > 0 {
+                        state.open_failures -= 1;
+                        return Err(InitError);
+                    }
+                }
+                Ok(Box::new(LoopbackOutput(loopback.clone())))
+            }
+        }
+    }
+}
+
+/// An output port of a [`MidiOutputBackend`].
+#[derive(Clone, PartialEq)]
+pub struct MidiOutputPort(Port);
+
+#[derive(Clone, PartialEq)]
+enum Port {
+    Midir(midir::MidiOutputPort),
+    Loopback(u64),
+}
+
+// A midi API which isn't connected to a port, shaped after `midir::MidiOutput`
+pub(crate) trait OutputBackend: Send {
+    fn ports(&self) -> Vec<MidiOutputPort>;
+
+    // None if the port has gone away
+    fn port_name(&self, port: &MidiOutputPort) -> Option<String>;
+
+    #[allow(clippy::type_complexity)]
+    fn connect(
+        self: Box<Self>,
+        port: &MidiOutputPort,
+        name: &str,
+    ) -> Result<Box<dyn OutputConnection>, (ConnectErrorKind, Box<dyn OutputBackend>)>;
+
+    // Opens a port named `name` which other programs can connect to
+    #[allow(clippy::type_complexity)]
+    fn create_virtual(
+        self: Box<Self>,
+        name: &str,
+    ) -> Result<Box<dyn OutputConnection>, (ConnectErrorKind, Box<dyn OutputBackend>)>;
+}
+
+// A connection to a port, shaped after `midir::MidiOutputConnection`
+pub(crate) trait OutputConnection: Send {
+    fn send(&mut self, msg: &[u8]) -> Result<(), SendError>;
+
+    fn close(self: Box<Self>) -> Box<dyn OutputBackend>;
+}
+
+impl OutputBackend for midir::MidiOutput {
+    fn ports(&self) -> Vec<MidiOutputPort> {
+        midir::MidiOutput::ports(self)
+            .into_iter()
+            .map(|port| MidiOutputPort(Port::Midir(port)))
+            .collect()
+    }
+
+    fn port_name(&self, port: &MidiOutputPort) -> Option<String> {
+        let MidiOutputPort(Port::Midir(port)) = port else { return None };
+        midir::MidiOutput::port_name(self, port).ok()
+    }
+
+    fn connect(
+        self: Box<Self>,
+        port: &MidiOutputPort,
+        name: &str,
+    ) -> Result<Box<dyn OutputConnection>, (ConnectErrorKind, Box<dyn OutputBackend>)> {
+        let MidiOutputPort(Port::Midir(port)) = port else {
+            return Err((ConnectErrorKind::InvalidPort, self));
+        };
+        match midir::MidiOutput::connect(*self, port, name) {
+            Ok(conn) => Ok(Box::new(conn)),
+            Err(e) => Err((e.kind(), Box::new(e.into_inner()))),
+        }
+    }
+
+    #[cfg(unix)]
+    fn create_virtual(
+        self: Box<Self>,
+        name: &str,
+    ) -> Result<Box<dyn OutputConnection>, (ConnectErrorKind, Box<dyn OutputBackend>)> {
+        use midir::os::unix::VirtualOutput;
+        match VirtualOutput::create_virtual(*self, name) {
+            Ok(conn) => Ok(Box::new(conn)),
+            Err(e) => Err((e.kind(), Box::new(e.into_inner()))),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn create_virtual(
+        self: Box<Self>,
+        _name: &str,
+    ) -> Result<Box<dyn OutputConnection>, (ConnectErrorKind, Box<dyn OutputBackend>)> {
+        let kind = ConnectErrorKind::Other("virtual ports aren't supported on this platform");
+        Err((kind, self))
+    }
+}
+
+impl OutputConnection for midir::MidiOutputConnection {
+    fn send(&mut self, msg: &[u8]) -> Result<(), SendError> {
+        midir::MidiOutputConnection::send(self, msg)
+    }
+
+    fn close(self: Box<Self>) -> Box<dyn OutputBackend> {
+        Box::new(midir::MidiOutputConnection::close(*self))
+    }
+}
+
+/// An in-memory midi backend which records everything sent to it.
+///
+/// Clones share the same ports and recordings, so a clone kept outside of Bevy can add
+/// and remove ports, make connecting or sending fail, and check what was sent.
+#[derive(Clone, Debug, Default)]
+pub struct Loopback(Arc<Mutex<LoopbackState>>);
+
+#[derive(Debug, Default)]
+struct LoopbackState {
+    // The ports which currently exist, with their ids
+    ports: Vec<(u64, String)>,
+    next_port: u64,
+    // The ports which are connected to, in the order they were connected to
+    connected: Vec<u64>,
+    // The virtual ports which are open, with their ids
+    virtual_ports: Vec<(u64, String)>,
+    // Every message sent, with the name of the port it was sent to
+    sent: Vec<(String, Vec<u8>)>,
+    // Errors to fail the next connects and sends with
+    connect_errors: VecDeque<ConnectErrorKind>,
+    send_errors: VecDeque<SendError>,
+    // Faults which only tests need
+    #[cfg(any(test, feature = "test-util"))]
+    open_failures: usize,
+    #[cfg(any(test, feature = "test-util"))]
+    panic_on_send: bool,
+}
+
+impl Loopback {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, LoopbackState> {
+        // The state is always left consistent, so a panic elsewhere doesn't matter
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Simulates a port named `name` appearing.
+    pub fn add_port(&self, name: &str) {
+        let mut state = self.state();
+        let id = state.new_port();
+        state.ports.push((id, name.to_string()));
+    }
+
+    /// Simulates every port named `name` disappearing. Sending to a connection to one of
+    /// them fails from then on.
+    pub fn remove_port(&self, name: &str) {
+        self.state().ports.retain(|(_, n)| n != name);
+    }
+
+    /// Makes the next attempt to connect or create a virtual port fail with `kind`.
+    pub fn fail_next_connect(&self, kind: ConnectErrorKind) {
+        self.state().connect_errors.push_back(kind);
+    }
+
+    /// Makes the next attempt to send fail with `error`.
+    pub fn fail_next_send(&self, error: SendError) {
+        self.state().send_errors.push_back(error);
+    }
+
+    /// Makes the next attempt to open the backend fail, whether for the midi thread
+    /// starting up or for a connection. Only with the `test-util` feature.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn fail_next_open(&self) {
+        self.state().open_failures += 1;
+    }
+
+    /// Makes the next attempt to send panic, taking down the midi thread. Only with the
+    /// `test-util` feature.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn panic_next_send(&self) {
+        self.state().panic_on_send = true;
+    }
+
+    /// The names of the ports which are connected to, in the order they were connected
+    /// to.
+    pub fn connected_ports(&self) -> Vec<String> {
+        let state = self.state();
+        let names = state.connected.iter().filter_map(|id| state.port_name(*id));
+        names.map(|name| name.to_string()).collect()
+    }
+
+    /// The names of the virtual ports which are open.
+    pub fn virtual_ports(&self) -> Vec<String> {
+        let state = self.state();
+        state
+            .virtual_ports
+            .iter()
+            .map(|(_, name)| name.clone())
+            .collect()
+    }
+
+    /// Every message sent so far, to any port.
+    pub fn sent(&self) -> Vec<Vec<u8>> {
+        let state = self.state();
+        state.sent.iter().map(|(_, msg)| msg.clone()).collect()
+    }
+
+    /// Every message sent so far to the port or virtual port named `name`.
+    pub fn sent_to(&self, name: &str) -> Vec<Vec<u8>> {
+        let state = self.state();
+        state
+            .sent
+            .iter()
+            .filter(|(port, _)| port == name)
+            .map(|(_, msg)| msg.clone())
+            .collect()
+    }
+
+    /// Every message sent since the last call, to any port, clearing the recording.
+    pub fn take_sent(&self) -> Vec<Vec<u8>> {
+        let sent = std::mem::take(&mut self.state().sent);
+        sent.into_iter().map(|(_, msg)| msg).collect()
+    }
+}
+
+impl LoopbackState {
+    fn new_port(&mut self) -> u64 {
+        let id = self.next_port;
+        self.next_port += 1;
+        id
+    }
+
+    // The name of the port or virtual port `id`, if it's still there
+    fn port_name(&self, id: u64) -> Option<&str> {
+        let mut ports = self.ports.iter().chain(&self.virtual_ports);
+        let (_, name) = ports.find(|(i, _)| *i == id)?;
+        Some(name)
+    }
+}
+
+struct LoopbackOutput(Loopback);
+
+struct LoopbackConnection {
+    loopback: Loopback,
+    port: u64,
+}
+
+impl OutputBackend for LoopbackOutput {
+    fn ports(&self) -> Vec<MidiOutputPort> {
+        self.0
+            .state()
+            .ports
+            .iter()
+            .map(|(id, _)| MidiOutputPort(Port::Loopback(*id)))
+            .collect()
+    }
+
+    fn port_name(&self, port: &MidiOutputPort) -> Option<String> {
+        let MidiOutputPort(Port::Loopback(id)) = port else { return None };
+        let state = self.0.state();
+        let (_, name) = state.ports.iter().find(|(i, _)| i == id)?;
+        Some(name.clone())
+    }
+
+    fn connect(
+        self: Box<Self>,
+        port: &MidiOutputPort,
+        _name: &str,
+    ) -> Result<Box<dyn OutputConnection>, (ConnectErrorKind, Box<dyn OutputBackend>)> {
+        let mut state = self.0.state();
+        if let Some(kind) = state.connect_errors.pop_front() {
+            drop(state);
+            return Err((kind, self));
+        }
+        let id = match port {
+            MidiOutputPort(Port::Loopback(id)) if state.ports.iter().any(|(i, _)| i == id) => *id,
+            _ => {
+                drop(state);
+                return Err((ConnectErrorKind::InvalidPort, self));
+            }
+        };
+        state.connected.push(id);
+        drop(state);
+        Ok(Box::new(LoopbackConnection {
+            loopback: self.0,
+            port: id,
+        }))
+    }
+
+    fn create_virtual(
+        self: Box<Self>,
+        name: &str,
+    ) -> Result<Box<dyn OutputConnection>, (ConnectErrorKind, Box<dyn OutputBackend>)> {
+        let mut state = self.0.state();
+        if let Some(kind) = state.connect_errors.pop_front() {
+            drop(state);
+            return Err((kind, self));
+        }
+        let id = state.new_port();
+        state.virtual_ports.push((id, name.to_string()));
+        drop(state);
+        Ok(Box::new(LoopbackConnection {
+            loopback: self.0,
+            port: id,
+        }))
+    }
+}
+
+impl OutputConnection for LoopbackConnection {
+    fn send(&mut self, msg: &[u8]) -> Result<(), SendError> {
+        let mut state = self.loopback.state();
+        #[cfg(any(test, feature = "test-util"))]
+        if state.panic_on_send {
+            state.panic_on_send = false;
+            drop(state);
+            panic!("loopback was told to panic");
+        }
+        if let Some(error) = state.send_errors.pop_front() {
+            return Err(error);
+        }
+        let Some(name) = state.port_name(self.port) else {
+            return Err(SendError::Other("port was removed"));
+        };
+        let name = name.to_string();
+        state.sent.push((name, msg.to_vec()));
+        Ok(())
+    }
+
+    fn close(self: Box<Self>) -> Box<dyn OutputBackend> {
+        Box::new(LoopbackOutput(self.loopback.clone()))
+    }
+}
+
+// Like midir's connections, dropping one closes it
+impl Drop for LoopbackConnection {
+    fn drop(&mut self) {
+        let mut state = self.loopback.state();
+        state.connected.retain(|id| *id != self.port);
+        state.virtual_ports.retain(|(id, _)| *id != self.port);
+    }
+}
@@ -0,0 +1,174 @@
+use super::message::MidiMessage;
+use super::schedule::BeatClock;
+use bevy::prelude::Resource;
+use std::time::Instant;
+
+/// How many timing clock messages make up a beat (a quarter note).
+pub const PPQN: u64 = 24;
+
+/// How many timing clock messages make up a sixteenth note, the unit of song position
+/// pointers.
+pub const PULSES_PER_SIXTEENTH: u64 = PPQN / 4;
+
+/// Follows the midi clock of another device: counts its timing clocks, tracks its
+/// start, stop, continue and song position messages, and estimates its tempo.
+///
+/// Timing clocks arrive with jitter, so both the tempo and the position of the beats
+/// are smoothed. Each new interval between clocks moves the estimate by a fraction
+/// (see [`with_smoothing`](Self::with_smoothing)) of how far it's off. A jump of more
+/// than double or half the tempo, or of more than a clock from where a clock was
+/// expected, is taken as-is, so tempo changes and dropouts are followed at once.
+///
+/// [`MidiInputPlugin`](super::input::MidiInputPlugin) keeps one of these as a resource,
+/// updated from every clock message received.
+#[derive(Resource, Clone, Debug)]
+pub struct ClockFollower {
+    smoothing: f64,
+    playing: bool,
+    // The position of the next timing clock, in clocks since the start of the song
+    next: u64,
+    // The position of the last timing clock received while playing
+    pulse: Option<u64>,
+    // The instant of the last timing clock, whether playing or not
+    last: Option<Instant>,
+    // The smoothed length of a clock in seconds
+    period: Option<f64>,
+    // Maps the song's beats to instants, from the clocks received since starting
+    clock: Option<BeatClock>,
+}
+
+impl Default for ClockFollower {
+    fn default() -> Self {
+        ClockFollower {
+            smoothing: 0.1,
+            playing: false,
+            next: 0,
+            pulse: None,
+            last: None,
+            period: None,
+            clock: None,
+        }
+    }
+}
+
+impl ClockFollower {
+    /// A follower which is stopped at the start of the song, and smooths by `0.1`.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Smooths the tempo and beats by `smoothing`, the fraction of the error in the
+    /// estimate which each timing clock corrects. `1.0` follows every clock exactly.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `smoothing` isn't above `0` and at most `1`.
+    #[must_use]
+    pub fn with_smoothing(self, smoothing: f64) -> Self {
+        assert!(
+            smoothing > 0.0 && smoothing <= 1.0,
+            "smoothing must be above 0 and at most 1"
+        );
+        ClockFollower { smoothing, ..self }
+    }
+
+    /// Updates the follower with `msg`, received at `at`. Returns whether `msg` was a
+    /// clock message: a timing clock, start, stop, continue or song position pointer.
+    pub fn update(&mut self, msg: &MidiMessage, at: Instant) -> bool {
+        match *msg {
+            MidiMessage::TimingClock => self.pulse_at(at),
+            MidiMessage::Start => {
+                self.playing = true;
+                self.next = 0;
+                self.restart();
+            }
+            MidiMessage::Continue => {
+                self.playing = true;
+                self.restart();
+            }
+            MidiMessage::Stop => {
+                self.playing = false;
+                self.clock = None;
+            }
+            MidiMessage::SongPosition(sixteenths) => {
+                self.next = sixteenths as u64 * PULSES_PER_SIXTEENTH;
+                self.restart();
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    // Forgets where the beats were, since the song has moved
+    fn restart(&mut self) {
+        self.pulse = None;
+        self.clock = None;
+        // The gap before the next clock isn't a clock's length
+        self.last = None;
+    }
+
+    fn pulse_at(&mut self, at: Instant) {
+        if let Some(last) = self.last {
+            let interval = at.saturating_duration_since(last).as_secs_f64();
+            self.period = match self.period {
+                Some(period) if interval > period / 2.0 && interval < period * 2.0 => {
+                    Some(period + (interval - period) * self.smoothing)
+                }
+                _ if interval > 0.0 => Some(interval),
+                period => period,
+            };
+        }
+        self.last = Some(at);
+        if !self.playing {
+            return;
+        }
+
+        let pulse = self.next;
+        self.next += 1;
+        self.pulse = Some(pulse);
+        let Some(tempo) = self.tempo() else { return };
+        let beat = pulse as f64 / PPQN as f64;
+        let estimate = match self.clock {
+            // Nudge the beat towards where the clock says it is
+            Some(clock) if (beat - clock.beat_at(at)).abs() < 1.0 / PPQN as f64 => {
+                let expected = clock.beat_at(at);
+                expected + (beat - expected) * self.smoothing
+            }
+            _ => beat,
+        };
+        let now = BeatClock::starting_at(at, tempo);
+        self.clock = now
+            .instant_at(-estimate)
+            .map(|origin| BeatClock::starting_at(origin, tempo));
+    }
+
+    /// Whether the other device is playing.
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// The estimated tempo in beats per minute, once two timing clocks have arrived.
+    pub fn tempo(&self) -> Option<f64> {
+        self.period
+            .map(|period| 60.0 / (period * PPQN as f64))
+            .filter(|tempo| tempo.is_finite())
+    }
+
+    /// The position of the last timing clock received while playing, in clocks since
+    /// the start of the song, or `None` if none has been received since the song last
+    /// started, continued or moved.
+    pub fn pulse(&self) -> Option<u64> {
+        self.pulse
+    }
+
+    /// The song's beats while playing, smoothed from the timing clocks received, with
+    /// beat 0 at the start of the song. `None` while stopped, or until a timing clock
+    /// has been received since starting and the tempo is known.
+    ///
+    /// This can be set as the clock of a [`MidiOutput`](super::output::MidiOutput) to
+    /// schedule messages in time with the other device.
+    pub fn beat_clock(&self) -> Option<BeatClock> {
+        self.clock.filter(|_| self.playing)
+    }
+}
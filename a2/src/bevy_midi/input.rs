@@ -0,0 +1,400 @@
+use super::clock::ClockFollower;
+use super::message::{MidiMessage, MidiMessageError};
+use bevy::prelude::*;
+use crossbeam_channel::{Receiver, Sender, TryRecvError};
+use midir::ConnectErrorKind;
+pub use midir::{Ignore, MidiInputPort};
+use std::error::Error;
+use std::fmt::Display;
+use std::thread;
+use std::time::Instant;
+use MidiInputError::*;
+
+pub struct MidiInputPlugin;
+
+impl Plugin for MidiInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MidiInputSettings>()
+            .insert_resource(MidiInputConnection { connected: false })
+            .insert_resource(MidiInputStatus::Starting)
+            .init_resource::<ClockFollower>()
+            .add_event::<MidiInputError>()
+            .add_event::<MidiData>()
+            .add_startup_system(setup)
+            .add_system(on_reply);
+    }
+}
+
+/// Settings for [`MidiInputPlugin`].
+#[derive(Resource, Clone, Debug)]
+pub struct MidiInputSettings {
+    pub port_name: &'static str,
+    /// Which kinds of incoming messages to drop before they reach Bevy.
+    pub ignore: Ignore,
+    /// The name of a virtual port to open, which other programs can connect to in order
+    /// to send messages, alongside whichever port is connected to. Virtual ports aren't
+    /// supported on Windows.
+    pub virtual_port: Option<&'static str>,
+}
+
+impl Default for MidiInputSettings {
+    fn default() -> Self {
+        MidiInputSettings {
+            port_name: "bevy_midi",
+            ignore: Ignore::None,
+            virtual_port: None,
+        }
+    }
+}
+
+/// [`Resource`](bevy::ecs::system::Resource) for receiving midi events.
+///
+/// Incoming messages arrive as [`MidiData`] events, both from the connected port and
+/// from the virtual port opened with [`MidiInputSettings::virtual_port`]. Clock messages
+/// also update the [`ClockFollower`] resource, unless they're ignored with
+/// [`MidiInputSettings::ignore`].
+///
+/// Change detection will only fire on this resource when its input ports are
+/// refreshed.
+#[derive(Resource)]
+pub struct MidiInput {
+    sender: Sender<Message>,
+    receiver: Receiver<Reply>,
+    ports: Vec<(String, MidiInputPort)>,
+}
+
+impl MidiInput {
+    // Sends `msg` to the midi thread, which is only gone if it has stopped
+    fn message(&self, msg: Message) -> Result<(), MidiInputError> {
+        self.sender.send(msg).map_err(|_| ChannelClosed)
+    }
+
+    /// Update the available input ports.
+    ///
+    /// Change detection is fired when the ports are refreshed.
+    pub fn refresh_ports(&self) -> Result<(), MidiInputError> {
+        self.message(Message::RefreshPorts)
+    }
+
+    /// Connect to the given `port`.
+    pub fn connect(&self, port: MidiInputPort) -> Result<(), MidiInputError> {
+        self.message(Message::ConnectToPort(port))
+    }
+
+    /// Disconnect from the current midi port.
+    pub fn disconnect(&self) -> Result<(), MidiInputError> {
+        self.message(Message::DisconnectFromPort)
+    }
+
+    /// Get the current input ports.
+    pub fn ports(&self) -> &Vec<(String, MidiInputPort)> {
+        &self.ports
+    }
+}
+
+/// [`Resource`](bevy::ecs::system::Resource) for checking whether MidiInput is
+/// connected to any ports.
+///
+/// Change detection fires whenever the connection changes.
+#[derive(Resource)]
+pub struct MidiInputConnection {
+    connected: bool,
+}
+
+impl MidiInputConnection {
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+}
+
+/// [`Resource`](bevy::ecs::system::Resource) for checking whether the midi thread
+/// behind [`MidiInput`] is running.
+///
+/// Once it has stopped, every [`MidiInput`] method returns
+/// [`MidiInputError::ChannelClosed`].
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MidiInputStatus {
+    /// The midi thread hasn't opened midir yet.
+    Starting,
+    Running,
+    /// The midi thread has stopped, because midir couldn't be opened or it panicked.
+    Stopped,
+}
+
+/// An event for a midi message received by [`MidiInput`].
+#[derive(Clone, Debug)]
+pub struct MidiData {
+    /// When the message arrived, in microseconds since an arbitrary point in the past
+    /// (chosen by the platform's midi API). Only differences between stamps are
+    /// meaningful.
+    pub stamp: u64,
+    /// When the message arrived, for comparing with other instants.
+    pub received: Instant,
+    /// The raw bytes of the message.
+    pub message: Vec<u8>,
+}
+
+impl MidiData {
+    /// Parses the raw bytes into a [`MidiMessage`].
+    pub fn parse(&self) -> Result<MidiMessage, MidiMessageError> {
+        MidiMessage::from_bytes(&self.message)
+    }
+}
+
+/// Errors which [`MidiInput`] sends as events.
+#[derive(Clone, Debug)]
+pub enum MidiInputError {
+    ConnectionError(ConnectErrorKind),
+    PortRefreshError,
+    /// midir couldn't be opened.
+    InitError,
+    /// The midi thread has stopped, so no more messages will arrive.
+    ThreadStopped,
+    /// A message couldn't be passed to the midi thread, since it has stopped.
+    ChannelClosed,
+}
+
+impl Error for MidiInputError {}
+impl Display for MidiInputError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            ConnectionError(k) => match k {
+                ConnectErrorKind::InvalidPort => {
+                    write!(f, "Couldn't (re)connect to input port: invalid port")?
+                }
+                ConnectErrorKind::Other(s) => {
+                    write!(f, "Couldn't (re)connect to input port: {}", s)?
+                }
+            },
+            PortRefreshError => write!(f, "Couldn't refresh input ports")?,
+            InitError => write!(f, "Couldn't open midi input")?,
+            ThreadStopped => write!(f, "The midi input thread has stopped")?,
+            ChannelClosed => write!(f, "Couldn't reach the midi input thread")?,
+        }
+        Ok(())
+    }
+}
+
+fn setup(mut commands: Commands, settings: Res<MidiInputSettings>) {
+    let (m_sender, m_receiver) = crossbeam_channel::unbounded();
+    let (r_sender, r_receiver) = crossbeam_channel::unbounded();
+
+    // The midi thread blocks on the channel, so it gets a thread of its own. Failing to
+    // spawn it drops its end of the channel, which is reported as the thread stopping.
+    let settings = settings.clone();
+    let _ = thread::Builder::new()
+        .name("midi input".into())
+        .spawn(move || midi_input(m_receiver, r_sender, settings));
+
+    commands.insert_resource(MidiInput {
+        sender: m_sender,
+        receiver: r_receiver,
+        ports: Vec::new(),
+    });
+}
+
+fn on_reply(
+    mut input: ResMut<MidiInput>,
+    mut conn: ResMut<MidiInputConnection>,
+    mut status: ResMut<MidiInputStatus>,
+    mut err: EventWriter<MidiInputError>,
+    mut midi: EventWriter<MidiData>,
+    mut clock: ResMut<ClockFollower>,
+) {
+    loop {
+        let msg = match input.receiver.try_recv() {
+            Ok(msg) => msg,
+            Err(TryRecvError::Empty) => break,
+            Err(TryRecvError::Disconnected) => {
+                // The midi thread only drops its end of the channel when it stops
+                if *status != MidiInputStatus::Stopped {
+                    *status = MidiInputStatus::Stopped;
+                    conn.connected = false;
+                    err.send(ThreadStopped);
+                }
+                break;
+            }
+        };
+        match msg {
+            Reply::Started => {
+                *status = MidiInputStatus::Running;
+            }
+            Reply::AvailablePorts(ports) => {
+                input.ports = ports;
+            }
+            Reply::Error(e) => {
+                err.send(e);
+            }
+            Reply::Connected => {
+                conn.connected = true;
+            }
+            Reply::Disconnected => {
+                conn.connected = false;
+            }
+            Reply::Midi(data) => {
+                // Only clock messages count as a change to the follower
+                if let Ok(msg) = data.parse() {
+                    if clock.bypass_change_detection().update(&msg, data.received) {
+                        clock.set_changed();
+                    }
+                }
+                midi.send(data);
+            }
+        }
+    }
+}
+
+enum Message {
+    RefreshPorts,
+    ConnectToPort(MidiInputPort),
+    DisconnectFromPort,
+}
+
+enum Reply {
+    Started,
+    AvailablePorts(Vec<(String, MidiInputPort)>),
+    Error(MidiInputError),
+    Connected,
+    Disconnected,
+    Midi(MidiData),
+}
+
+// A callback for midir which forwards every message to `sender` as a `Reply::Midi`
+fn forward(sender: &Sender<Reply>) -> impl FnMut(u64, &[u8], &mut ()) + Send + 'static {
+    let sender = sender.clone();
+    move |stamp, message, _| {
+        // The receiving end only goes away when the app shuts down
+        let _ = sender.send(Reply::Midi(MidiData {
+            stamp,
+            received: Instant::now(),
+            message: message.to_vec(),
+        }));
+    }
+}
+
+// Opens a connection which forwards every message to `sender`.
+//
+// midir's connections use up the `MidiInput` they're made from, so each one opens its own.
+fn connect(
+    port: &MidiInputPort,
+    name: &str,
+    ignore: Ignore,
+    sender: &Sender<Reply>,
+) -> Result<midir::MidiInputConnection<()>, MidiInputError> {
+    let mut input = midir::MidiInput::new(name).map_err(|_| InitError)?;
+    input.ignore(ignore);
+    input
+        .connect(port, name, forward(sender), ())
+        .map_err(|e| ConnectionError(e.kind()))
+}
+
+// Opens a virtual port named `port_name` which forwards every message to `sender`
+#[cfg(unix)]
+fn create_virtual(
+    port_name: &str,
+    name: &str,
+    ignore: Ignore,
+    sender: &Sender<Reply>,
+) -> Result<midir::MidiInputConnection<()>, MidiInputError> {
+    use midir::os::unix::VirtualInput;
+    let mut input = midir::MidiInput::new(name).map_err(|_| InitError)?;
+    input.ignore(ignore);
+    input
+        .create_virtual(port_name, forward(sender), ())
+        .map_err(|e| ConnectionError(e.kind()))
+}
+
+#[cfg(not(unix))]
+fn create_virtual(
+    _port_name: &str,
+    _name: &str,
+    _ignore: Ignore,
+    _sender: &Sender<Reply>,
+) -> Result<midir::MidiInputConnection<()>, MidiInputError> {
+    Err(ConnectionError(ConnectErrorKind::Other(
+        "virtual ports aren't supported on this platform",
+    )))
+}
+
+fn midi_input(
+    receiver: Receiver<Message>,
+    sender: Sender<Reply>,
+    settings: MidiInputSettings,
+) -> Result<(), crossbeam_channel::SendError<Reply>> {
+    use Message::*;
+    let MidiInputSettings {
+        port_name: name,
+        ignore,
+        virtual_port,
+    } = settings;
+
+    // Lists ports without having to close the connection
+    let Ok(watcher) = midir::MidiInput::new(name) else {
+        return sender.send(Reply::Error(InitError));
+    };
+    sender.send(Reply::Started)?;
+    sender.send(get_available_ports(&watcher))?;
+
+    // Stays open until the thread stops
+    let _virtual_port = match virtual_port {
+        Some(port_name) => match create_virtual(port_name, name, ignore, &sender) {
+            Ok(conn) => Some(conn),
+            Err(e) => {
+                sender.send(Reply::Error(e))?;
+                None
+            }
+        },
+        None => None,
+    };
+
+    let mut connection: Option<midir::MidiInputConnection<()>> = None;
+
+    while let Ok(msg) = receiver.recv() {
+        match msg {
+            ConnectToPort(port) => {
+                let start_connected = connection.is_some();
+                connection = None;
+                match connect(&port, name, ignore, &sender) {
+                    Ok(conn) => {
+                        connection = Some(conn);
+                        sender.send(Reply::Connected)?;
+                    }
+                    Err(e) => {
+                        sender.send(Reply::Error(e))?;
+                        if start_connected {
+                            sender.send(Reply::Disconnected)?;
+                        }
+                    }
+                }
+            }
+            DisconnectFromPort => {
+                if connection.take().is_some() {
+                    sender.send(Reply::Disconnected)?;
+                }
+            }
+            RefreshPorts => {
+                sender.send(get_available_ports(&watcher))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Helper for above.
+//
+// Returns either Reply::AvailablePorts or Reply::PortRefreshError
+// If there's an error getting port names, it's because the available ports changed,
+// so it tries again (up to 10 times)
+fn get_available_ports(input: &midir::MidiInput) -> Reply {
+    for _ in 0..10 {
+        let ports = input.ports();
+        let ports: Result<Vec<_>, _> = ports
+            .into_iter()
+            .map(|p| input.port_name(&p).map(|n| (n, p)))
+            .collect();
+        if let Ok(ports) = ports {
+            return Reply::AvailablePorts(ports);
+        }
+    }
+    Reply::Error(PortRefreshError)
+}
@@ -0,0 +1,563 @@
+use std::error::Error;
+use std::fmt::Display;
+use MidiMessageError::*;
+
+/// A single midi message.
+///
+/// Channels run from `0` to `15`, and data values from `0` to `127` unless noted otherwise.
+/// The constructors check these ranges; messages built directly with out of range values
+/// have their values masked down when converted to bytes.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MidiMessage {
+    // Channel voice messages
+    NoteOff { channel: u8, note: u8, velocity: u8 },
+    /// A note on with a velocity of `0` is treated as a note off by most devices.
+    NoteOn { channel: u8, note: u8, velocity: u8 },
+    PolyPressure { channel: u8, note: u8, pressure: u8 },
+    /// A control change for controllers `0` to `119`; the rest are channel mode messages.
+    ControlChange { channel: u8, controller: u8, value: u8 },
+    ProgramChange { channel: u8, program: u8 },
+    ChannelPressure { channel: u8, pressure: u8 },
+    /// A pitch bend from `0` to `16383`, where `8192` is no bend.
+    PitchBend { channel: u8, value: u16 },
+
+    // Channel mode messages
+    AllSoundOff { channel: u8 },
+    ResetAllControllers { channel: u8 },
+    LocalControl { channel: u8, on: bool },
+    AllNotesOff { channel: u8 },
+    OmniOff { channel: u8 },
+    OmniOn { channel: u8 },
+    /// Mono mode, over `channels` channels (or as many as the receiver has, if `0`).
+    MonoOn { channel: u8, channels: u8 },
+    PolyOn { channel: u8 },
+
+    // System common messages
+    /// A system exclusive message, holding the data bytes between `0xF0` and `0xF7`.
+    SysEx(Vec<u8>),
+    TimeCodeQuarterFrame(u8),
+    /// The position in a song, in sixteenth notes from `0` to `16383`.
+    SongPosition(u16),
+    SongSelect(u8),
+    TuneRequest,
+
+    // System realtime messages
+    TimingClock,
+    Start,
+    Continue,
+    Stop,
+    ActiveSensing,
+    Reset,
+}
+
+/// Errors from building or parsing a [`MidiMessage`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MidiMessageError {
+    ChannelOutOfRange(u8),
+    /// A value which doesn't fit in its data bytes.
+    DataOutOfRange(u16),
+    /// A status byte which isn't defined by the midi spec.
+    UndefinedStatus(u8),
+    /// Data bytes with no status byte (or running status) before them.
+    MissingStatus(u8),
+    /// A status byte which came before its message had all of its data bytes.
+    Incomplete(u8),
+    /// Bytes left over after a complete message.
+    TrailingBytes(usize),
+    Empty,
+}
+
+impl Error for MidiMessageError {}
+impl Display for MidiMessageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            ChannelOutOfRange(c) => write!(f, "Midi channel {} is out of range 0-15", c),
+            DataOutOfRange(d) => write!(f, "Midi data value {} is out of range", d),
+            UndefinedStatus(s) => write!(f, "Undefined midi status byte {:#04x}", s),
+            MissingStatus(d) => write!(f, "Midi data byte {:#04x} has no status", d),
+            Incomplete(s) => write!(f, "Midi message with status {:#04x} is incomplete", s),
+            TrailingBytes(n) => write!(f, "{} bytes left over after midi message", n),
+            Empty => write!(f, "No midi message"),
+        }
+    }
+}
+
+fn channel(channel: u8) -> Result<u8, MidiMessageError> {
+    if channel > 15 {
+        return Err(ChannelOutOfRange(channel));
+    }
+    Ok(channel)
+}
+
+fn data(value: u8) -> Result<u8, MidiMessageError> {
+    if value > 127 {
+        return Err(DataOutOfRange(value as u16));
+    }
+    Ok(value)
+}
+
+fn data_14(value: u16) -> Result<u16, MidiMessageError> {
+    if value > 16383 {
+        return Err(DataOutOfRange(value));
+    }
+    Ok(value)
+}
+
+impl MidiMessage {
+    pub fn note_on(channel: u8, note: u8, velocity: u8) -> Result<Self, MidiMessageError> {
+        Ok(MidiMessage::NoteOn {
+            channel: self::channel(channel)?,
+            note: data(note)?,
+            velocity: data(velocity)?,
+        })
+    }
+
+    pub fn note_off(channel: u8, note: u8, velocity: u8) -> Result<Self, MidiMessageError> {
+        Ok(MidiMessage::NoteOff {
+            channel: self::channel(channel)?,
+            note: data(note)?,
+            velocity: data(velocity)?,
+        })
+    }
+
+    pub fn poly_pressure(channel: u8, note: u8, pressure: u8) -> Result<Self, MidiMessageError> {
+        Ok(MidiMessage::PolyPressure {
+            channel: self::channel(channel)?,
+            note: data(note)?,
+            pressure: data(pressure)?,
+        })
+    }
+
+    /// A control change, for controllers `0` to `119`.
+    pub fn control_change(
+        channel: u8,
+        controller: u8,
+        value: u8,
+    ) -> Result<Self, MidiMessageError> {
+        if controller >= 120 {
+            return Err(DataOutOfRange(controller as u16));
+        }
+        Ok(MidiMessage::ControlChange {
+            channel: self::channel(channel)?,
+            controller,
+            value: data(value)?,
+        })
+    }
+
+    pub fn program_change(channel: u8, program: u8) -> Result<Self, MidiMessageError> {
+        Ok(MidiMessage::ProgramChange {
+            channel: self::channel(channel)?,
+            program: data(program)?,
+        })
+    }
+
+    pub fn channel_pressure(channel: u8, pressure: u8) -> Result<Self, MidiMessageError> {
+        Ok(MidiMessage::ChannelPressure {
+            channel: self::channel(channel)?,
+            pressure: data(pressure)?,
+        })
+    }
+
+    /// A pitch bend from `0` to `16383`, where `8192` is no bend.
+    pub fn pitch_bend(channel: u8, value: u16) -> Result<Self, MidiMessageError> {
+        Ok(MidiMessage::PitchBend {
+            channel: self::channel(channel)?,
+            value: data_14(value)?,
+        })
+    }
+
+    pub fn all_sound_off(channel: u8) -> Result<Self, MidiMessageError> {
+        Ok(MidiMessage::AllSoundOff {
+            channel: self::channel(channel)?,
+        })
+    }
+
+    pub fn reset_all_controllers(channel: u8) -> Result<Self, MidiMessageError> {
+        Ok(MidiMessage::ResetAllControllers {
+            channel: self::channel(channel)?,
+        })
+    }
+
+    pub fn local_control(channel: u8, on: bool) -> Result<Self, MidiMessageError> {
+        Ok(MidiMessage::LocalControl {
+            channel: self::channel(channel)?,
+            on,
+        })
+    }
+
+    pub fn all_notes_off(channel: u8) -> Result<Self, MidiMessageError> {
+        Ok(MidiMessage::AllNotesOff {
+            channel: self::channel(channel)?,
+        })
+    }
+
+    pub fn omni_off(channel: u8) -> Result<Self, MidiMessageError> {
+        Ok(MidiMessage::OmniOff {
+            channel: self::channel(channel)?,
+        })
+    }
+
+    pub fn omni_on(channel: u8) -> Result<Self, MidiMessageError> {
+        Ok(MidiMessage::OmniOn {
+            channel: self::channel(channel)?,
+        })
+    }
+
+    pub fn mono_on(channel: u8, channels: u8) -> Result<Self, MidiMessageError> {
+        if channels > 16 {
+            return Err(DataOutOfRange(channels as u16));
+        }
+        Ok(MidiMessage::MonoOn {
+            channel: self::channel(channel)?,
+            channels,
+        })
+    }
+
+    pub fn poly_on(channel: u8) -> Result<Self, MidiMessageError> {
+        Ok(MidiMessage::PolyOn {
+            channel: self::channel(channel)?,
+        })
+    }
+
+    /// A system exclusive message, from the data bytes between `0xF0` and `0xF7`.
+    pub fn sysex(data: impl Into<Vec<u8>>) -> Result<Self, MidiMessageError> {
+        let data = data.into();
+        if let Some(byte) = data.iter().find(|b| **b > 127) {
+            return Err(DataOutOfRange(*byte as u16));
+        }
+        Ok(MidiMessage::SysEx(data))
+    }
+
+    pub fn time_code_quarter_frame(value: u8) -> Result<Self, MidiMessageError> {
+        Ok(MidiMessage::TimeCodeQuarterFrame(data(value)?))
+    }
+
+    /// The position in a song, in sixteenth notes from `0` to `16383`.
+    pub fn song_position(position: u16) -> Result<Self, MidiMessageError> {
+        Ok(MidiMessage::SongPosition(data_14(position)?))
+    }
+
+    pub fn song_select(song: u8) -> Result<Self, MidiMessageError> {
+        Ok(MidiMessage::SongSelect(data(song)?))
+    }
+
+    /// The channel of a channel voice or mode message.
+    pub fn channel(&self) -> Option<u8> {
+        use MidiMessage::*;
+        match *self {
+            NoteOff { channel, .. }
+            | NoteOn { channel, .. }
+            | PolyPressure { channel, .. }
+            | ControlChange { channel, .. }
+            | ProgramChange { channel, .. }
+            | ChannelPressure { channel, .. }
+            | PitchBend { channel, .. }
+            | AllSoundOff { channel }
+            | ResetAllControllers { channel }
+            | LocalControl { channel, .. }
+            | AllNotesOff { channel }
+            | OmniOff { channel }
+            | OmniOn { channel }
+            | MonoOn { channel, .. }
+            | PolyOn { channel } => Some(channel),
+            _ => None,
+        }
+    }
+
+    /// Whether this is a system realtime message, which can be sent in the middle of other
+    /// messages.
+    pub fn is_realtime(&self) -> bool {
+        use MidiMessage::*;
+        matches!(
+            self,
+            TimingClock | Start | Continue | Stop | ActiveSensing | Reset
+        )
+    }
+
+    /// The status byte this message starts with.
+    pub fn status(&self) -> u8 {
+        use MidiMessage::*;
+        let channel_status = |status: u8, channel: u8| status | (channel & 0x0F);
+        match *self {
+            NoteOff { channel, .. } => channel_status(0x80, channel),
+            NoteOn { channel, .. } => channel_status(0x90, channel),
+            PolyPressure { channel, .. } => channel_status(0xA0, channel),
+            ControlChange { channel, .. }
+            | AllSoundOff { channel }
+            | ResetAllControllers { channel }
+            | LocalControl { channel, .. }
+            | AllNotesOff { channel }
+            | OmniOff { channel }
+            | OmniOn { channel }
+            | MonoOn { channel, .. }
+            | PolyOn { channel } => channel_status(0xB0, channel),
+            ProgramChange { channel, .. } => channel_status(0xC0, channel),
+            ChannelPressure { channel, .. } => channel_status(0xD0, channel),
+            PitchBend { channel, .. } => channel_status(0xE0, channel),
+            SysEx(_) => 0xF0,
+            TimeCodeQuarterFrame(_) => 0xF1,
+            SongPosition(_) => 0xF2,
+            SongSelect(_) => 0xF3,
+            TuneRequest => 0xF6,
+            TimingClock => 0xF8,
+            Start => 0xFA,
+            Continue => 0xFB,
+            Stop => 0xFC,
+            ActiveSensing => 0xFE,
+            Reset => 0xFF,
+        }
+    }
+
+    // The data bytes following the status byte
+    pub(crate) fn data_bytes(&self) -> Vec<u8> {
+        use MidiMessage::*;
+        let lsb = |value: u16| (value & 0x7F) as u8;
+        let msb = |value: u16| ((value >> 7) & 0x7F) as u8;
+        let bytes = match self {
+            NoteOff { note, velocity, .. } | NoteOn { note, velocity, .. } => {
+                vec![*note, *velocity]
+            }
+            PolyPressure { note, pressure, .. } => vec![*note, *pressure],
+            ControlChange {
+                controller, value, ..
+            } => vec![*controller, *value],
+            ProgramChange { program, .. } => vec![*program],
+            ChannelPressure { pressure, .. } => vec![*pressure],
+            PitchBend { value, .. } => vec![lsb(*value), msb(*value)],
+            AllSoundOff { .. } => vec![120, 0],
+            ResetAllControllers { .. } => vec![121, 0],
+            LocalControl { on, .. } => vec![122, if *on { 127 } else { 0 }],
+            AllNotesOff { .. } => vec![123, 0],
+            OmniOff { .. } => vec![124, 0],
+            OmniOn { .. } => vec![125, 0],
+            MonoOn { channels, .. } => vec![126, *channels],
+            PolyOn { .. } => vec![127, 0],
+            SysEx(data) => {
+                // Only the end marker may have its top bit set
+                let mut bytes: Vec<_> = data.iter().map(|b| b & 0x7F).collect();
+                bytes.push(0xF7);
+                return bytes;
+            }
+            TimeCodeQuarterFrame(value) | SongSelect(value) => vec![*value],
+            SongPosition(position) => vec![lsb(*position), msb(*position)],
+            TuneRequest | TimingClock | Start | Continue | Stop | ActiveSensing | Reset => {
+                Vec::new()
+            }
+        };
+        bytes.into_iter().map(|b| b & 0x7F).collect()
+    }
+
+    /// The bytes of the message, starting with its status byte.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.status()];
+        bytes.extend(self.data_bytes());
+        bytes
+    }
+
+    /// Parses exactly one message from `bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MidiMessageError> {
+        let mut parser = MidiParser::new();
+        for (i, byte) in bytes.iter().enumerate() {
+            if let Some(message) = parser.push(*byte)? {
+                if i + 1 < bytes.len() {
+                    return Err(TrailingBytes(bytes.len() - i - 1));
+                }
+                return Ok(message);
+            }
+        }
+        match bytes.first() {
+            Some(status) => Err(Incomplete(*status)),
+            None => Err(Empty),
+        }
+    }
+
+    /// The bytes of a sequence of messages, leaving out repeated channel status bytes with
+    /// running status.
+    pub fn stream_to_bytes<'a>(messages: impl IntoIterator<Item = &'a MidiMessage>) -> Vec<u8> {
+        let mut running_status = None;
+        let mut bytes = Vec::new();
+        for message in messages {
+            let status = message.status();
+            if status >= 0xF0 {
+                // System common messages cancel running status; realtime ones leave it be
+                if !message.is_realtime() {
+                    running_status = None;
+                }
+                bytes.push(status);
+            } else if running_status != Some(status) {
+                running_status = Some(status);
+                bytes.push(status);
+            }
+            bytes.extend(message.data_bytes());
+        }
+        bytes
+    }
+
+    /// Parses every message in a stream of bytes, which may use running status.
+    pub fn stream_from_bytes(bytes: &[u8]) -> Result<Vec<Self>, MidiMessageError> {
+        let mut parser = MidiParser::new();
+        let mut messages = Vec::new();
+        for byte in bytes {
+            messages.extend(parser.push(*byte)?);
+        }
+        Ok(messages)
+    }
+}
+
+/// Parses midi messages from a stream of bytes, one byte at a time.
+///
+/// Supports running status, and realtime messages in the middle of other messages.
+#[derive(Clone, Debug, Default)]
+pub struct MidiParser {
+    running_status: Option<u8>,
+    // The status of the message being parsed, and its data so far
+    status: Option<u8>,
+    data: Vec<u8>,
+}
+
+impl MidiParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses the next byte, giving a message if it completes one.
+    ///
+    /// After an error, the parser drops the message in progress and carries on with the
+    /// next status byte.
+    pub fn push(&mut self, byte: u8) -> Result<Option<MidiMessage>, MidiMessageError> {
+        use MidiMessage::*;
+
+        if byte >= 0xF8 {
+            return match byte {
+                0xF8 => Ok(Some(TimingClock)),
+                0xFA => Ok(Some(Start)),
+                0xFB => Ok(Some(Continue)),
+                0xFC => Ok(Some(Stop)),
+                0xFE => Ok(Some(ActiveSensing)),
+                0xFF => Ok(Some(Reset)),
+                _ => Err(UndefinedStatus(byte)),
+            };
+        }
+
+        if byte >= 0x80 {
+            // The end of a SysEx, or a new message cutting off the last one
+            let interrupted = self.status.take();
+            let data = std::mem::take(&mut self.data);
+            if byte == 0xF7 {
+                return match interrupted {
+                    Some(0xF0) => Ok(Some(SysEx(data))),
+                    _ => Err(UndefinedStatus(byte)),
+                };
+            }
+            if let Some(status) = interrupted.filter(|s| *s == 0xF0 || !data.is_empty()) {
+                // The new message still gets parsed, except for single byte ones which
+                // are lost along with the interrupted message
+                let _ = self.start(byte);
+                return Err(Incomplete(status));
+            }
+            return self.start(byte);
+        }
+
+        let status = match (self.status, self.running_status) {
+            (Some(status), _) => status,
+            (None, Some(status)) => {
+                self.status = Some(status);
+                status
+            }
+            (None, None) => return Err(MissingStatus(byte)),
+        };
+        self.data.push(byte);
+        if status == 0xF0 || self.data.len() < data_len(status) {
+            return Ok(None);
+        }
+        self.status = None;
+        let data = std::mem::take(&mut self.data);
+        Ok(Some(message(status, &data)))
+    }
+
+    // Starts a message with `status`, which might need no data bytes at all
+    fn start(&mut self, status: u8) -> Result<Option<MidiMessage>, MidiMessageError> {
+        match status {
+            0x80..=0xEF => self.running_status = Some(status),
+            0xF4 | 0xF5 => {
+                self.running_status = None;
+                return Err(UndefinedStatus(status));
+            }
+            _ => self.running_status = None,
+        }
+        if data_len(status) == 0 && status != 0xF0 {
+            return Ok(Some(message(status, &[])));
+        }
+        self.status = Some(status);
+        Ok(None)
+    }
+}
+
+// The number of data bytes after a status byte (ignoring SysEx)
+pub(crate) fn data_len(status: u8) -> usize {
+    match status {
+        0xC0..=0xDF | 0xF1 | 0xF3 => 1,
+        0x80..=0xEF | 0xF2 => 2,
+        _ => 0,
+    }
+}
+
+// Builds a message from a status byte and all of its data bytes
+fn message(status: u8, data: &[u8]) -> MidiMessage {
+    use MidiMessage::*;
+    let channel = status & 0x0F;
+    let value_14 = || data[0] as u16 | (data[1] as u16) << 7;
+    match status & 0xF0 {
+        0x80 => NoteOff {
+            channel,
+            note: data[0],
+            velocity: data[1],
+        },
+        0x90 => NoteOn {
+            channel,
+            note: data[0],
+            velocity: data[1],
+        },
+        0xA0 => PolyPressure {
+            channel,
+            note: data[0],
+            pressure: data[1],
+        },
+        0xB0 => match (data[0], data[1]) {
+            (120, _) => AllSoundOff { channel },
+            (121, _) => ResetAllControllers { channel },
+            (122, value) => LocalControl {
+                channel,
+                on: value >= 64,
+            },
+            (123, _) => AllNotesOff { channel },
+            (124, _) => OmniOff { channel },
+            (125, _) => OmniOn { channel },
+            (126, channels) => MonoOn { channel, channels },
+            (127, _) => PolyOn { channel },
+            (controller, value) => ControlChange {
+                channel,
+                controller,
+                value,
+            },
+        },
+        0xC0 => ProgramChange {
+            channel,
+            program: data[0],
+        },
+        0xD0 => ChannelPressure {
+            channel,
+            pressure: data[0],
+        },
+        0xE0 => PitchBend {
+            channel,
+            value: value_14(),
+        },
+        _ => match status {
+            0xF1 => TimeCodeQuarterFrame(data[0]),
+            0xF2 => SongPosition(value_14()),
+            0xF3 => SongSelect(data[0]),
+            _ => TuneRequest,
+        },
+    }
+}
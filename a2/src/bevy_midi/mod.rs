@@ -1 +1,9 @@
+pub mod backend;
+pub mod clock;
+pub mod input;
+pub mod message;
 pub mod output;
+pub mod player;
+pub mod recorder;
+pub mod schedule;
+pub mod smf;
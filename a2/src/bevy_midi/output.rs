@@ -1,9 +1,17 @@
-use bevy::{prelude::*, tasks::IoTaskPool};
-use crossbeam_channel::{Receiver, Sender};
+pub use super::backend::{Loopback, MidiOutputBackend, MidiOutputPort};
+use super::backend::{OutputBackend, OutputConnection};
+use super::clock::{PPQN, PULSES_PER_SIXTEENTH};
+use super::message::{MidiMessage, MidiMessageError};
+use super::schedule::{BeatClock, Schedule, ScheduleId, ScheduleTime};
+use bevy::prelude::*;
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender, TryRecvError};
 use midir::ConnectErrorKind;
-pub use midir::MidiOutputPort;
+use std::collections::{HashSet, VecDeque};
 use std::error::Error;
 use std::fmt::Display;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 use MidiOutputError::*;
 
 pub struct MidiOutputPlugin;
@@ -11,8 +19,11 @@ pub struct MidiOutputPlugin;
 impl Plugin for MidiOutputPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<MidiOutputSettings>()
-            .insert_resource(MidiOutputConnection { connected: false })
+            .init_resource::<MidiOutputConnections>()
+            .insert_resource(MidiOutputStatus::Starting)
             .add_event::<MidiOutputError>()
+            .add_event::<MidiOutputEvent>()
+            .add_event::<MidiSent>()
             .add_startup_system(setup)
             .add_system(on_reply);
     }
@@ -22,18 +33,46 @@ impl Plugin for MidiOutputPlugin {
 #[derive(Resource, Clone, Debug)]
 pub struct MidiOutputSettings {
     pub port_name: &'static str,
+    pub backend: MidiOutputBackend,
+    /// How often to check for ports coming and going, or `None` to only check when
+    /// [`MidiOutput::refresh_ports`] is called.
+    pub poll_interval: Option<Duration>,
+    /// How many messages to hold onto while the connected port is gone, which are sent
+    /// once it's reconnected. The oldest messages are dropped first.
+    pub reconnect_buffer: usize,
+    /// The name of a virtual port to open, which other programs can connect to in order
+    /// to receive everything sent, alongside whichever port is connected to. Virtual
+    /// ports aren't supported on Windows.
+    pub virtual_port: Option<&'static str>,
 }
 
 impl Default for MidiOutputSettings {
     fn default() -> Self {
         MidiOutputSettings {
             port_name: "bevy_midi",
+            backend: MidiOutputBackend::Midir,
+            poll_interval: Some(Duration::from_secs(1)),
+            reconnect_buffer: 0,
+            virtual_port: None,
         }
     }
 }
 
 /// [`Resource`](bevy::ecs::system::Resource) for sending midi events.
 ///
+/// [`MidiOutput`] can be connected to several ports at once, and each message is routed
+/// to some or all of them with a [`MidiRoute`]. If a connected port goes away,
+/// [`MidiOutput`] reconnects to the next port with the same name.
+///
+/// Messages can also be scheduled ahead of time, and are then sent from the midi thread
+/// when they're due, without waiting on Bevy's frames. The midi thread can also act as
+/// a midi clock master, sending timing clocks in time with the beats of
+/// [`set_clock`](MidiOutput::set_clock).
+///
+/// With [`MidiOutputSettings::virtual_port`] set, everything sent also goes out of a
+/// virtual port, even while no port is connected to. Everything sent can also be tapped
+/// as [`MidiSent`] events with [`tap`](MidiOutput::tap), say to record it.
+///
 /// Change detection will only fire on this resource when its output ports are
 /// refreshed.
 #[derive(Resource)]
@@ -41,29 +80,144 @@ pub struct MidiOutput {
     sender: Sender<Message>,
     receiver: Receiver<Reply>,
     ports: Vec<(String, MidiOutputPort)>,
+    next_id: AtomicU64,
 }
 
 impl MidiOutput {
+    // Sends `msg` to the midi thread, which is only gone if it has stopped
+    fn message(&self, msg: Message) -> Result<(), MidiOutputError> {
+        self.sender.send(msg).map_err(|_| ChannelClosed)
+    }
+
     /// Update the available output ports.
     ///
     /// Change detection is fired when the ports are refreshed.
-    pub fn refresh_ports(&self) {
-        self.sender.send(Message::RefreshPorts).unwrap();
+    pub fn refresh_ports(&self) -> Result<(), MidiOutputError> {
+        self.message(Message::RefreshPorts)
+    }
+
+    /// Connect to the given `port`, alongside any ports already connected to. Connecting
+    /// to a port with the same name as a connected one replaces that connection.
+    pub fn connect(&self, port: MidiOutputPort) -> Result<(), MidiOutputError> {
+        self.message(Message::ConnectToPort(port))
+    }
+
+    /// Disconnect from the port named `name`.
+    pub fn disconnect(&self, name: &str) -> Result<(), MidiOutputError> {
+        self.message(Message::DisconnectFromPort(name.to_string()))
+    }
+
+    /// Disconnect from every port.
+    pub fn disconnect_all(&self) -> Result<(), MidiOutputError> {
+        self.message(Message::DisconnectAll)
+    }
+
+    /// Send a midi message, routed by its channel.
+    pub fn send(&self, msg: [u8; 3]) -> Result<(), MidiOutputError> {
+        self.message(Message::Midi(msg.to_vec(), MidiRoute::Channel))
+    }
+
+    /// Send a typed midi message, routed by its channel.
+    pub fn send_message(&self, msg: MidiMessage) -> Result<(), MidiOutputError> {
+        self.send_message_to(msg, MidiRoute::Channel)
+    }
+
+    /// Send a typed midi message to the ports picked by `route`.
+    pub fn send_message_to(
+        &self,
+        msg: MidiMessage,
+        route: MidiRoute,
+    ) -> Result<(), MidiOutputError> {
+        self.message(Message::Midi(msg.to_bytes(), route))
+    }
+
+    /// Route the messages on `channel` which are routed by channel to the ports named in
+    /// `ports`, rather than to every port. An empty `ports` routes them to every port
+    /// again. Fails with [`MidiOutputError::InvalidMessage`] if `channel` isn't from `0` to
+    /// `15`.
+    pub fn map_channel(&self, channel: u8, ports: &[&str]) -> Result<(), MidiOutputError> {
+        if channel > 15 {
+            return Err(InvalidMessage(MidiMessageError::ChannelOutOfRange(channel)));
+        }
+        let ports = ports.iter().map(|name| name.to_string()).collect();
+        self.message(Message::MapChannel(channel, ports))
+    }
+
+    /// Schedule a midi message to be sent at `at`, routed by its channel. Messages due at
+    /// the same time are sent in the order they were scheduled.
+    pub fn schedule(&self, msg: MidiMessage, at: Instant) -> Result<ScheduleId, MidiOutputError> {
+        self.schedule_time(msg, ScheduleTime::At(at))
     }
 
-    /// Connect to the given `port`.
-    pub fn connect(&self, port: MidiOutputPort) {
-        self.sender.send(Message::ConnectToPort(port)).unwrap();
+    /// Schedule a midi message to be sent on `beat` of the clock set with
+    /// [`set_clock`](Self::set_clock), routed by its channel.
+    ///
+    /// The message waits until a clock is set, and is moved if the clock changes before
+    /// it's sent.
+    pub fn schedule_beat(&self, msg: MidiMessage, beat: f64) -> Result<ScheduleId, MidiOutputError> {
+        self.schedule_time(msg, ScheduleTime::Beat(beat))
+    }
+
+    fn schedule_time(
+        &self,
+        msg: MidiMessage,
+        time: ScheduleTime,
+    ) -> Result<ScheduleId, MidiOutputError> {
+        let id = ScheduleId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        self.message(Message::ScheduleMidi(id, time, msg.to_bytes()))?;
+        Ok(id)
+    }
+
+    /// Set the clock which beats are scheduled by.
+    pub fn set_clock(&self, clock: BeatClock) -> Result<(), MidiOutputError> {
+        self.message(Message::SetClock(clock))
+    }
+
+    /// Cancel a scheduled message, if it hasn't been sent yet.
+    pub fn cancel(&self, id: ScheduleId) -> Result<(), MidiOutputError> {
+        self.message(Message::Cancel(id))
+    }
+
+    /// Cancel every scheduled message which hasn't been sent yet.
+    pub fn cancel_all(&self) -> Result<(), MidiOutputError> {
+        self.message(Message::CancelAll)
+    }
+
+    /// Start the midi clock: send a start to every port, then a timing clock on every
+    /// [`PPQN`]th of a beat of the clock set with [`set_clock`](Self::set_clock), from
+    /// beat 0.
+    ///
+    /// Devices following the clock count beats from the first timing clock, so beat 0
+    /// should be now or later. If it has already passed, timing clocks start from the
+    /// last one due. Timing clocks wait until a clock is set, and follow it when it
+    /// changes.
+    pub fn start_clock(&self) -> Result<(), MidiOutputError> {
+        self.message(Message::StartClock)
+    }
+
+    /// Continue the midi clock from the current beat of the clock set with
+    /// [`set_clock`](Self::set_clock): send a song position pointer for the next
+    /// sixteenth note and a continue to every port, then timing clocks from that
+    /// sixteenth note on.
+    pub fn continue_clock(&self) -> Result<(), MidiOutputError> {
+        self.message(Message::ContinueClock)
     }
 
-    /// Disconnect from the current midi port.
-    pub fn disconnect(&self) {
-        self.sender.send(Message::DisconnectFromPort).unwrap();
+    /// Stop the midi clock: stop sending timing clocks, and send a stop to every port.
+    pub fn stop_clock(&self) -> Result<(), MidiOutputError> {
+        self.message(Message::StopClock)
     }
 
-    /// Send a midi message.
-    pub fn send(&self, msg: [u8; 3]) {
-        self.sender.send(Message::Midi(msg)).unwrap();
+    /// Send a [`MidiSent`] event for every message sent from now on, including scheduled
+    /// messages and timing clocks, or stop sending them.
+    pub fn tap(&self, tap: bool) -> Result<(), MidiOutputError> {
+        self.message(Message::Tap(tap))
+    }
+
+    /// Cancel every scheduled message, then silence every port: send a note off for each
+    /// note still on, and an all notes off on every channel.
+    pub fn all_notes_off(&self) -> Result<(), MidiOutputError> {
+        self.message(Message::AllNotesOff)
     }
 
     /// Get the current output ports.
@@ -72,28 +226,117 @@ impl MidiOutput {
     }
 }
 
-/// [`Resource`](bevy::ecs::system::Resource) for checking whether MidiOutput is
-/// connected to any ports.
+/// Which of the connected ports a message is sent to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MidiRoute {
+    /// Every connected port.
+    All,
+    /// The connected port with this name.
+    Port(String),
+    /// The ports the message's channel is mapped to with [`MidiOutput::map_channel`].
+    /// Messages without a channel, or on a channel which isn't mapped, go to every port.
+    Channel,
+}
+
+/// [`Resource`](bevy::ecs::system::Resource) for checking which ports MidiOutput is
+/// connected to.
 ///
-/// Change detection fires whenever the connection changes.
-#[derive(Resource)]
-pub struct MidiOutputConnection {
-    connected: bool,
+/// Change detection fires whenever a connection changes.
+#[derive(Resource, Default)]
+pub struct MidiOutputConnections {
+    ports: Vec<(String, MidiConnectionStatus)>,
 }
 
-impl MidiOutputConnection {
+impl MidiOutputConnections {
+    /// Whether any port is connected to.
     pub fn is_connected(&self) -> bool {
-        self.connected
+        self.ports
+            .iter()
+            .any(|(_, status)| *status == MidiConnectionStatus::Connected)
     }
+
+    /// The status of the connection to the port named `name`, or `None` if it isn't
+    /// connected to.
+    pub fn status(&self, name: &str) -> Option<MidiConnectionStatus> {
+        let (_, status) = self.ports.iter().find(|(n, _)| n == name)?;
+        Some(*status)
+    }
+
+    /// The ports connected to, in the order they were connected to.
+    pub fn ports(&self) -> &[(String, MidiConnectionStatus)] {
+        &self.ports
+    }
+
+    fn set(&mut self, name: String, status: MidiConnectionStatus) {
+        match self.ports.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, s)) => *s = status,
+            None => self.ports.push((name, status)),
+        }
+    }
+}
+
+/// The status of a connection in [`MidiOutputConnections`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MidiConnectionStatus {
+    Connected,
+    /// The port has gone away. Messages sent to it are held onto (see
+    /// [`MidiOutputSettings::reconnect_buffer`]) until a port with its name comes back.
+    Lost,
+}
+
+/// [`Resource`](bevy::ecs::system::Resource) for checking whether the midi thread
+/// behind [`MidiOutput`] is running.
+///
+/// Once it has stopped, every [`MidiOutput`] method returns
+/// [`MidiOutputError::ChannelClosed`].
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MidiOutputStatus {
+    /// The midi thread hasn't opened the backend yet.
+    Starting,
+    Running,
+    /// The midi thread has stopped, because the backend couldn't be opened or it
+    /// panicked.
+    Stopped,
 }
 
-// XXX: give doc comment/implement Error trait
+/// Events for ports coming and going, found when [`MidiOutput`] checks its ports.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MidiOutputEvent {
+    PortAdded(String),
+    PortRemoved(String),
+    /// The connected port went away and a port with its name has been connected to.
+    Reconnected(String),
+}
+
+/// An event for a midi message sent by [`MidiOutput`] while it's tapped with
+/// [`MidiOutput::tap`], whether or not it reached a port.
+#[derive(Clone, Debug)]
+pub struct MidiSent {
+    /// When the message was sent.
+    pub sent: Instant,
+    /// The raw bytes of the message.
+    pub message: Vec<u8>,
+}
+
+/// Errors which [`MidiOutput`] sends as events.
 #[derive(Clone, Debug)]
 pub enum MidiOutputError {
+    /// Connecting to a port, or opening the virtual port, failed.
     ConnectionError(ConnectErrorKind),
     SendError(midir::SendError),
-    SendDisconnectedError([u8; 3]),
+    /// A message had no port to go to, or was dropped from the buffer of a lost port
+    /// before it came back.
+    SendDisconnectedError(Vec<u8>),
+    /// The ports couldn't be listed.
     PortRefreshError,
+    /// The backend couldn't be opened.
+    InitError,
+    /// The midi thread has stopped, so nothing more can be sent.
+    ThreadStopped,
+    /// A message couldn't be passed to the midi thread, since it has stopped.
+    ChannelClosed,
+    /// A midi message, or part of one such as its channel, was out of range.
+    InvalidMessage(MidiMessageError),
 }
 
 impl Error for MidiOutputError {}
@@ -115,6 +358,10 @@ impl Display for MidiOutputError {
                 }
             },
             PortRefreshError => write!(f, "Couldn't refresh output ports")?,
+            InitError => write!(f, "Couldn't open the midi output backend")?,
+            ThreadStopped => write!(f, "The midi output thread has stopped")?,
+            ChannelClosed => write!(f, "Couldn't reach the midi output thread")?,
+            InvalidMessage(e) => e.fmt(f)?,
         }
         Ok(())
     }
@@ -124,36 +371,68 @@ fn setup(mut commands: Commands, settings: Res<MidiOutputSettings>) {
     let (m_sender, m_receiver) = crossbeam_channel::unbounded();
     let (r_sender, r_receiver) = crossbeam_channel::unbounded();
 
-    let thread_pool = IoTaskPool::get();
-    thread_pool
-        .spawn(midi_output(m_receiver, r_sender, settings.port_name))
-        .detach();
+    // The midi thread spends its time blocked on the channel or spinning until a scheduled
+    // message is due, so it gets a thread of its own. Failing to spawn it drops its end of
+    // the channel, which is reported as the thread stopping.
+    let settings = settings.clone();
+    let _ = thread::Builder::new()
+        .name("midi output".into())
+        .spawn(move || midi_output(m_receiver, r_sender, settings));
 
     commands.insert_resource(MidiOutput {
         sender: m_sender,
         receiver: r_receiver,
         ports: Vec::new(),
+        next_id: AtomicU64::new(0),
     });
 }
 
 fn on_reply(
     mut output: ResMut<MidiOutput>,
-    mut conn: ResMut<MidiOutputConnection>,
+    mut conns: ResMut<MidiOutputConnections>,
+    mut status: ResMut<MidiOutputStatus>,
     mut err: EventWriter<MidiOutputError>,
+    mut events: EventWriter<MidiOutputEvent>,
+    mut sent: EventWriter<MidiSent>,
 ) {
-    while let Ok(msg) = output.receiver.try_recv() {
+    loop {
+        let msg = match output.receiver.try_recv() {
+            Ok(msg) => msg,
+            Err(TryRecvError::Empty) => break,
+            Err(TryRecvError::Disconnected) => {
+                // The midi thread only drops its end of the channel when it stops
+                if *status != MidiOutputStatus::Stopped {
+                    *status = MidiOutputStatus::Stopped;
+                    conns.ports.clear();
+                    err.send(ThreadStopped);
+                }
+                break;
+            }
+        };
         match msg {
+            Reply::Started => {
+                *status = MidiOutputStatus::Running;
+            }
             Reply::AvailablePorts(ports) => {
                 output.ports = ports;
             }
             Reply::Error(e) => {
                 err.send(e);
             }
-            Reply::Connected => {
-                conn.connected = true;
+            Reply::Connected(name) => {
+                conns.set(name, MidiConnectionStatus::Connected);
+            }
+            Reply::Lost(name) => {
+                conns.set(name, MidiConnectionStatus::Lost);
+            }
+            Reply::Disconnected(name) => {
+                conns.ports.retain(|(n, _)| *n != name);
+            }
+            Reply::Event(e) => {
+                events.send(e);
             }
-            Reply::Disconnected => {
-                conn.connected = false;
+            Reply::Sent(msg) => {
+                sent.send(msg);
             }
         }
     }
@@ -162,95 +441,510 @@ fn on_reply(
 enum Message {
     RefreshPorts,
     ConnectToPort(MidiOutputPort),
-    DisconnectFromPort,
-    Midi([u8; 3]),
+    DisconnectFromPort(String),
+    DisconnectAll,
+    Midi(Vec<u8>, MidiRoute),
+    MapChannel(u8, Vec<String>),
+    ScheduleMidi(ScheduleId, ScheduleTime, Vec<u8>),
+    SetClock(BeatClock),
+    Cancel(ScheduleId),
+    CancelAll,
+    AllNotesOff,
+    StartClock,
+    ContinueClock,
+    StopClock,
+    Tap(bool),
 }
 
 enum Reply {
+    Started,
     AvailablePorts(Vec<(String, MidiOutputPort)>),
     Error(MidiOutputError),
-    Connected,
-    Disconnected,
+    Connected(String),
+    // The port has gone away, but is still to be reconnected to
+    Lost(String),
+    Disconnected(String),
+    Event(MidiOutputEvent),
+    Sent(MidiSent),
 }
 
-async fn midi_output(
+type ReplyResult = Result<(), crossbeam_channel::SendError<Reply>>;
+
+fn midi_output(
     receiver: Receiver<Message>,
     sender: Sender<Reply>,
-    name: &str,
-) -> Result<(), crossbeam_channel::SendError<Reply>> {
-    use Message::*;
+    settings: MidiOutputSettings,
+) -> ReplyResult {
+    let name = settings.port_name;
+    let Ok(watcher) = settings.backend.open(name) else {
+        return sender.send(Reply::Error(InitError));
+    };
+    sender.send(Reply::Started)?;
+    let mut thread = OutputThread {
+        sender,
+        name,
+        backend: settings.backend,
+        watcher,
+        targets: Vec::new(),
+        virtual_port: None,
+        ports: Vec::new(),
+        buffer_len: settings.reconnect_buffer,
+        channels: Default::default(),
+        pulses: None,
+        tap: false,
+        schedule: Schedule::default(),
+        notes: Notes::default(),
+    };
+    if let Some(port_name) = settings.virtual_port {
+        thread.open_virtual(port_name)?;
+    }
+    let reply = get_available_ports(thread.watcher.as_ref());
+    if let Reply::AvailablePorts(ports) = &reply {
+        thread.ports = ports.clone();
+    }
+    thread.sender.send(reply)?;
+
+    let next_poll_after = |interval: Option<Duration>| {
+        interval.and_then(|interval| Instant::now().checked_add(interval))
+    };
+    let mut next_poll = next_poll_after(settings.poll_interval);
+    loop {
+        // Wait for the next message, or until just before the next scheduled one or
+        // timing clock is due, or until the next poll. Sleeping isn't precise enough for
+        // those, so the last stretch before them is spent spinning.
+        let due = [thread.schedule.next_due(), thread.next_pulse()]
+            .into_iter()
+            .flatten()
+            .min();
+        let spin_from = due.map(|due| due.checked_sub(SPIN).unwrap_or(due));
+        let wake = [spin_from, next_poll].into_iter().flatten().min();
+        let msg = match wake {
+            None => match receiver.recv() {
+                Ok(msg) => Some(msg),
+                Err(_) => break,
+            },
+            Some(wake) => match receiver.recv_deadline(wake) {
+                Ok(msg) => Some(msg),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            },
+        };
+        if let Some(msg) = msg {
+            thread.handle(msg)?;
+            continue;
+        }
+
+        if let (Some(due), Some(spin_from)) = (due, spin_from) {
+            if spin_from <= Instant::now() {
+                while Instant::now() < due {
+                    std::hint::spin_loop();
+                }
+                thread.send_pulses()?;
+                for msg in thread.schedule.pop_due(Instant::now()) {
+                    thread.send(msg, &MidiRoute::Channel)?;
+                }
+            }
+        }
+        if next_poll.filter(|poll| *poll <= Instant::now()).is_some() {
+            thread.poll(false)?;
+            next_poll = next_poll_after(settings.poll_interval);
+        }
+    }
+    Ok(())
+}
+
+// How long before a scheduled message is due to stop waiting on the channel and spin
+const SPIN: Duration = Duration::from_millis(2);
+
+// The state of the midi thread
+struct OutputThread {
+    sender: Sender<Reply>,
+    name: &'static str,
+    // Each connection opens the backend afresh, since midir's connections use it up
+    backend: MidiOutputBackend,
+    // Lists ports without having to close the connections, which midir needs for listing
+    watcher: Box<dyn OutputBackend>,
+    // The ports connected to, in the order they were connected to
+    targets: Vec<Target>,
+    // Receives everything sent, whether or not there are any connections
+    virtual_port: Option<Box<dyn OutputConnection>>,
+    // The ports as of the last poll
+    ports: Vec<(String, MidiOutputPort)>,
+    buffer_len: usize,
+    // The names of the ports each channel is routed to, where none means every port
+    channels: [Vec<String>; 16],
+    // The next timing clock to send, in clocks since beat 0, while the clock runs
+    pulses: Option<u64>,
+    // Whether to reply with every message sent
+    tap: bool,
+    schedule: Schedule,
+    notes: Notes,
+}
 
-    let output = midir::MidiOutput::new(name).unwrap();
-    sender.send(get_available_ports(&output))?;
+// A port to stay connected to, until disconnected on purpose
+struct Target {
+    name: String,
+    // None while the port is gone
+    connection: Option<(Box<dyn OutputConnection>, MidiOutputPort)>,
+    // Messages sent while the port is gone, up to `buffer_len` of them
+    buffer: VecDeque<Vec<u8>>,
+}
 
-    // Invariant: exactly one of `output` or `connection` is Some
-    let mut output: Option<midir::MidiOutput> = Some(output);
-    let mut connection: Option<(midir::MidiOutputConnection, MidiOutputPort)> = None;
+impl Target {
+    // Closes the connection, returning whether there was one
+    fn close(&mut self) -> bool {
+        let Some((conn, _)) = self.connection.take() else { return false };
+        conn.close();
+        true
+    }
+}
 
-    while let Ok(msg) = receiver.recv() {
+impl OutputThread {
+    fn reply(&self, reply: Reply) -> ReplyResult {
+        self.sender.send(reply)
+    }
+
+    fn handle(&mut self, msg: Message) -> ReplyResult {
+        use Message::*;
         match msg {
-            ConnectToPort(port) => {
-                let start_connected = output.is_none();
-                let out = output.unwrap_or_else(|| connection.unwrap().0.close());
-                match out.connect(&port, name) {
-                    Ok(conn) => {
-                        connection = Some((conn, port));
-                        output = None;
-                        sender.send(Reply::Connected)?;
-                    }
-                    Err(conn_err) => {
-                        sender.send(Reply::Error(ConnectionError(conn_err.kind())))?;
-                        if start_connected {
-                            sender.send(Reply::Disconnected)?;
-                        }
-                        connection = None;
-                        output = Some(conn_err.into_inner());
-                    }
+            ConnectToPort(port) => self.connect(port)?,
+            DisconnectFromPort(name) => {
+                let Some(i) = self.position(&name) else { return Ok(()) };
+                self.targets.remove(i).close();
+                self.reply(Reply::Disconnected(name))?;
+            }
+            DisconnectAll => {
+                for mut target in std::mem::take(&mut self.targets) {
+                    target.close();
+                    self.reply(Reply::Disconnected(target.name))?;
                 }
             }
-            DisconnectFromPort => {
-                if let Some((conn, _)) = connection {
-                    output = Some(conn.close());
-                    connection = None;
-                    sender.send(Reply::Disconnected)?;
+            RefreshPorts => self.poll(true)?,
+            Midi(msg, route) => self.send(msg, &route)?,
+            MapChannel(channel, ports) => self.channels[channel as usize] = ports,
+            ScheduleMidi(id, time, msg) => self.schedule.push(id, time, msg),
+            SetClock(clock) => self.schedule.set_clock(clock),
+            Cancel(id) => self.schedule.cancel(id),
+            CancelAll => self.schedule.clear(),
+            AllNotesOff => {
+                self.schedule.clear();
+                for target in &mut self.targets {
+                    target.buffer.clear();
                 }
+                for msg in self.notes.all_off() {
+                    self.send(msg, &MidiRoute::All)?;
+                }
+            }
+            StartClock => {
+                self.send(MidiMessage::Start.to_bytes(), &MidiRoute::All)?;
+                let pulse = (self.beat() * PPQN as f64).floor().max(0.0);
+                self.pulses = Some(pulse as u64);
+            }
+            ContinueClock => {
+                let sixteenth = (self.beat() * 4.0).ceil().clamp(0.0, 16383.0) as u16;
+                let position = MidiMessage::SongPosition(sixteenth);
+                self.send(position.to_bytes(), &MidiRoute::All)?;
+                self.send(MidiMessage::Continue.to_bytes(), &MidiRoute::All)?;
+                self.pulses = Some(sixteenth as u64 * PULSES_PER_SIXTEENTH);
+            }
+            StopClock => {
+                self.pulses = None;
+                self.send(MidiMessage::Stop.to_bytes(), &MidiRoute::All)?;
+            }
+            Tap(tap) => self.tap = tap,
+        }
+        Ok(())
+    }
+
+    // The current beat of the clock, or 0 if there isn't one
+    fn beat(&self) -> f64 {
+        let clock = self.schedule.clock();
+        clock.map_or(0.0, |clock| clock.beat_at(Instant::now()))
+    }
+
+    // The instant of the next timing clock, while the clock runs
+    fn next_pulse(&self) -> Option<Instant> {
+        let pulse = self.pulses?;
+        self.schedule
+            .clock()?
+            .instant_at(pulse as f64 / PPQN as f64)
+    }
+
+    // Sends the timing clocks which are due. Ones which are late are still sent, to keep
+    // the count right, unless a whole beat has been missed (say the clock was moved).
+    fn send_pulses(&mut self) -> ReplyResult {
+        let now = Instant::now();
+        if let (Some(pulse), Some(clock)) = (self.pulses, self.schedule.clock()) {
+            let current = (clock.beat_at(now) * PPQN as f64).floor();
+            if current - pulse as f64 > PPQN as f64 {
+                self.pulses = Some(current as u64);
             }
-            RefreshPorts => match &output {
-                Some(out) => {
-                    sender.send(get_available_ports(out))?;
+        }
+        while matches!(self.next_pulse(), Some(pulse) if pulse <= now) {
+            self.send(MidiMessage::TimingClock.to_bytes(), &MidiRoute::All)?;
+            self.pulses = self.pulses.map(|pulse| pulse + 1);
+        }
+        Ok(())
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.targets.iter().position(|target| target.name == name)
+    }
+
+    // Connects to `port`, replacing any connection to a port with the same name
+    fn connect(&mut self, port: MidiOutputPort) -> ReplyResult {
+        let Some(name) = self.watcher.port_name(&port) else {
+            return self.reply(Reply::Error(ConnectionError(ConnectErrorKind::InvalidPort)));
+        };
+        let existing = self.position(&name);
+        if let Some(i) = existing {
+            self.targets[i].close();
+        }
+        match self.open(&port) {
+            Ok(conn) => {
+                let target = Target {
+                    name: name.clone(),
+                    connection: Some((conn, port)),
+                    buffer: VecDeque::new(),
+                };
+                match existing {
+                    Some(i) => self.targets[i] = target,
+                    None => self.targets.push(target),
                 }
-                None => {
-                    let (conn, port) = connection.unwrap();
-                    let out = conn.close();
-
-                    sender.send(get_available_ports(&out))?;
-
-                    match out.connect(&port, name) {
-                        Ok(conn) => {
-                            connection = Some((conn, port));
-                            output = None;
-                        }
-                        Err(conn_err) => {
-                            sender.send(Reply::Error(ConnectionError(conn_err.kind())))?;
-                            sender.send(Reply::Disconnected)?;
-                            connection = None;
-                            output = Some(conn_err.into_inner());
-                        }
-                    }
+                self.reply(Reply::Connected(name))
+            }
+            Err(e) => {
+                self.reply(Reply::Error(e))?;
+                let Some(i) = existing else { return Ok(()) };
+                self.targets.remove(i);
+                self.reply(Reply::Disconnected(name))
+            }
+        }
+    }
+
+    fn open(&self, port: &MidiOutputPort) -> Result<Box<dyn OutputConnection>, MidiOutputError> {
+        let output = self.backend.open(self.name).map_err(|_| InitError)?;
+        output
+            .connect(port, self.name)
+            .map_err(|(kind, _)| ConnectionError(kind))
+    }
+
+    fn open_virtual(&mut self, port_name: &str) -> ReplyResult {
+        let Ok(output) = self.backend.open(self.name) else {
+            return self.reply(Reply::Error(InitError));
+        };
+        match output.create_virtual(port_name) {
+            Ok(conn) => self.virtual_port = Some(conn),
+            Err((kind, _)) => self.reply(Reply::Error(ConnectionError(kind)))?,
+        }
+        Ok(())
+    }
+
+    // Lists the ports, replying with the ports which came and went. Connections are
+    // closed if their port went, and reopened if a port with their name came back.
+    fn poll(&mut self, always_reply: bool) -> ReplyResult {
+        let ports = match get_available_ports(self.watcher.as_ref()) {
+            Reply::AvailablePorts(ports) => ports,
+            reply => return self.reply(reply),
+        };
+        let has_name = |ports: &[(String, MidiOutputPort)], name: &str| {
+            ports.iter().any(|(n, _)| n == name)
+        };
+        for (name, _) in ports.iter().filter(|(n, _)| !has_name(&self.ports, n)) {
+            self.reply(Reply::Event(MidiOutputEvent::PortAdded(name.clone())))?;
+        }
+        for (name, _) in self.ports.iter().filter(|(n, _)| !has_name(&ports, n)) {
+            self.reply(Reply::Event(MidiOutputEvent::PortRemoved(name.clone())))?;
+        }
+        let changed = ports.len() != self.ports.len()
+            || ports.iter().zip(&self.ports).any(|(a, b)| a != b);
+        self.ports = ports;
+        if changed || always_reply {
+            self.reply(Reply::AvailablePorts(self.ports.clone()))?;
+        }
+
+        for i in 0..self.targets.len() {
+            let gone = match &self.targets[i].connection {
+                Some((_, port)) => !self.ports.iter().any(|(_, p)| p == port),
+                None => false,
+            };
+            if gone {
+                self.targets[i].close();
+                self.reply(Reply::Lost(self.targets[i].name.clone()))?;
+            }
+            self.reconnect(i)?;
+        }
+        Ok(())
+    }
+
+    // Reconnects the target `i` if its port is gone and a port with its name is there
+    fn reconnect(&mut self, i: usize) -> ReplyResult {
+        let target = &self.targets[i];
+        if target.connection.is_some() {
+            return Ok(());
+        }
+        let Some((_, port)) = self.ports.iter().find(|(n, _)| *n == target.name) else {
+            return Ok(());
+        };
+        let port = port.clone();
+        match self.open(&port) {
+            Ok(conn) => {
+                let target = &mut self.targets[i];
+                target.connection = Some((conn, port));
+                let name = target.name.clone();
+                let buffer = std::mem::take(&mut target.buffer);
+                self.reply(Reply::Connected(name.clone()))?;
+                self.reply(Reply::Event(MidiOutputEvent::Reconnected(name)))?;
+                for msg in buffer {
+                    self.send_to(i, msg)?;
                 }
-            },
-            Midi(msg) => {
-                if let Some((conn, _)) = &mut connection {
-                    if let Err(e) = conn.send(&msg) {
-                        sender.send(Reply::Error(SendError(e)))?;
-                    }
-                } else {
-                    sender.send(Reply::Error(SendDisconnectedError(msg)))?;
+                Ok(())
+            }
+            Err(e) => self.reply(Reply::Error(e)),
+        }
+    }
+
+    // The targets `msg` goes to along `route`
+    fn route(&self, msg: &[u8], route: &MidiRoute) -> Vec<usize> {
+        let named = |names: &[String]| {
+            (0..self.targets.len())
+                .filter(|i| names.contains(&self.targets[*i].name))
+                .collect()
+        };
+        match route {
+            MidiRoute::All => (0..self.targets.len()).collect(),
+            MidiRoute::Port(name) => named(std::slice::from_ref(name)),
+            MidiRoute::Channel => {
+                let channel = MidiMessage::from_bytes(msg)
+                    .ok()
+                    .and_then(|msg| msg.channel());
+                match channel.map(|channel| &self.channels[channel as usize]) {
+                    Some(names) if !names.is_empty() => named(names),
+                    _ => (0..self.targets.len()).collect(),
                 }
             }
         }
     }
-    Ok(())
+
+    // Sends `msg` to the virtual port, if there is one, and to the targets along `route`
+    fn send(&mut self, msg: Vec<u8>, route: &MidiRoute) -> ReplyResult {
+        if self.tap {
+            self.reply(Reply::Sent(MidiSent {
+                sent: Instant::now(),
+                message: msg.clone(),
+            }))?;
+        }
+        if let Some(port) = &mut self.virtual_port {
+            match port.send(&msg) {
+                Ok(()) => self.notes.update(&msg),
+                Err(e) => self.reply(Reply::Error(SendError(e)))?,
+            }
+        }
+        let targets = self.route(&msg, route);
+        if targets.is_empty() {
+            // Without a port to connect to, the virtual port is the whole output, and
+            // realtime messages have nowhere they need to go, unless the message was meant
+            // for a particular port
+            if (self.virtual_port.is_some() || is_realtime(&msg))
+                && !matches!(route, MidiRoute::Port(_))
+            {
+                return Ok(());
+            }
+            return self.reply(Reply::Error(SendDisconnectedError(msg)));
+        }
+        for i in targets {
+            self.send_to(i, msg.clone())?;
+        }
+        Ok(())
+    }
+
+    // Sends `msg` to the target `i`, holding onto it if the port has gone away
+    fn send_to(&mut self, i: usize, msg: Vec<u8>) -> ReplyResult {
+        let Some((conn, port)) = &mut self.targets[i].connection else {
+            return self.hold(i, msg);
+        };
+        let Err(e) = conn.send(&msg) else {
+            self.notes.update(&msg);
+            return Ok(());
+        };
+        // The port may have gone away since the last poll, so check now rather than
+        // reporting an error for every message until then
+        if self.watcher.port_name(port).is_some() {
+            return self.reply(Reply::Error(SendError(e)));
+        }
+        self.poll(false)?;
+        match self.targets[i].connection {
+            Some(_) => self.reply(Reply::Error(SendError(e))),
+            None => self.hold(i, msg),
+        }
+    }
+
+    fn hold(&mut self, i: usize, msg: Vec<u8>) -> ReplyResult {
+        // Realtime messages only mean anything as they're sent, so a lost port misses them
+        if is_realtime(&msg) {
+            return Ok(());
+        }
+        if self.buffer_len == 0 {
+            return self.reply(Reply::Error(SendDisconnectedError(msg)));
+        }
+        let buffer = &mut self.targets[i].buffer;
+        let dropped = if buffer.len() == self.buffer_len {
+            buffer.pop_front()
+        } else {
+            None
+        };
+        buffer.push_back(msg);
+        match dropped {
+            Some(dropped) => self.reply(Reply::Error(SendDisconnectedError(dropped))),
+            None => Ok(()),
+        }
+    }
+}
+
+fn is_realtime(msg: &[u8]) -> bool {
+    matches!(msg.first(), Some(0xF8..=0xFF))
+}
+
+// The notes which have been sent a note on but no note off yet
+#[derive(Default)]
+struct Notes(HashSet<(u8, u8)>);
+
+impl Notes {
+    fn update(&mut self, msg: &[u8]) {
+        use MidiMessage::*;
+        match MidiMessage::from_bytes(msg) {
+            Ok(NoteOn {
+                channel,
+                note,
+                velocity,
+            }) if velocity > 0 => {
+                self.0.insert((channel, note));
+            }
+            Ok(NoteOn { channel, note, .. } | NoteOff { channel, note, .. }) => {
+                self.0.remove(&(channel, note));
+            }
+            Ok(AllNotesOff { channel } | AllSoundOff { channel }) => {
+                self.0.retain(|(c, _)| *c != channel);
+            }
+            _ => {}
+        }
+    }
+
+    // The messages which turn every note off
+    fn all_off(&self) -> Vec<Vec<u8>> {
+        let mut notes: Vec<_> = self.0.iter().copied().collect();
+        notes.sort_unstable();
+        let note_offs = notes.into_iter().map(|(channel, note)| MidiMessage::NoteOff {
+            channel,
+            note,
+            velocity: 0,
+        });
+        let all_notes_off = (0..16).map(|channel| MidiMessage::AllNotesOff { channel });
+        note_offs
+            .chain(all_notes_off)
+            .map(|msg| msg.to_bytes())
+            .collect()
+    }
 }
 
 // Helper for above.
@@ -258,14 +952,14 @@ async fn midi_output(
 // Returns either Reply::AvailablePorts or Reply::PortRefreshError
 // If there's an error getting port names, it's because the available ports changed,
 // so it tries again (up to 10 times)
-fn get_available_ports(output: &midir::MidiOutput) -> Reply {
+fn get_available_ports(output: &dyn OutputBackend) -> Reply {
     for _ in 0..10 {
         let ports = output.ports();
-        let ports: Result<Vec<_>, _> = ports
+        let ports: Option<Vec<_>> = ports
             .into_iter()
             .map(|p| output.port_name(&p).map(|n| (n, p)))
             .collect();
-        if let Ok(ports) = ports {
+        if let Some(ports) = ports {
             return Reply::AvailablePorts(ports);
         }
     }
@@ -0,0 +1,191 @@
+use super::message::MidiMessage;
+use super::output::{MidiOutput, MidiOutputError};
+use super::schedule::ScheduleId;
+use super::smf::{Smf, TempoMap, TrackEventKind};
+use bevy::prelude::*;
+use std::collections::{HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+pub struct MidiPlayerPlugin;
+
+impl Plugin for MidiPlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MidiPlayer>().add_system(play);
+    }
+}
+
+/// [`Resource`](bevy::ecs::system::Resource) for playing a Standard MIDI File through
+/// [`MidiOutput`], which [`MidiPlayerPlugin`] needs alongside it.
+///
+/// The file's messages are scheduled with [`MidiOutput::schedule`] a little ahead of when
+/// they're due, at the times given by its tempo events, so they're sent from the midi
+/// thread without waiting on Bevy's frames. Meta events aren't sent, and neither are
+/// system exclusive messages split over several events.
+///
+/// Change detection fires when playing starts or stops.
+#[derive(Resource)]
+pub struct MidiPlayer {
+    /// How far ahead of time messages are scheduled, which needs to cover the longest
+    /// frame.
+    pub lookahead: Duration,
+    playing: Option<Playing>,
+}
+
+impl Default for MidiPlayer {
+    fn default() -> Self {
+        MidiPlayer {
+            lookahead: Duration::from_millis(100),
+            playing: None,
+        }
+    }
+}
+
+impl MidiPlayer {
+    /// Play `smf` from now, stopping whatever was playing.
+    pub fn play(&mut self, smf: &Smf, output: &MidiOutput) -> Result<(), MidiOutputError> {
+        self.play_at(smf, Instant::now(), output)
+    }
+
+    /// Play `smf` with its start at `start`, stopping whatever was playing. Messages due
+    /// before now are sent straight away.
+    pub fn play_at(
+        &mut self,
+        smf: &Smf,
+        start: Instant,
+        output: &MidiOutput,
+    ) -> Result<(), MidiOutputError> {
+        self.stop(output)?;
+        let tempo_map = TempoMap::new(smf);
+        let messages = smf
+            .timeline()
+            .into_iter()
+            .filter_map(|(tick, kind)| {
+                let msg = match kind {
+                    TrackEventKind::Midi(msg) => msg.clone(),
+                    TrackEventKind::Escape(bytes) => MidiMessage::from_bytes(bytes).ok()?,
+                    TrackEventKind::Tempo(_) | TrackEventKind::Meta(..) => return None,
+                };
+                let seconds = tempo_map.seconds_at(tick);
+                // Well beyond anything an `Instant` can reach
+                if seconds > 1e15 {
+                    return None;
+                }
+                Some((start.checked_add(Duration::from_secs_f64(seconds))?, msg))
+            })
+            .collect();
+        self.playing = Some(Playing {
+            messages,
+            next: 0,
+            scheduled: VecDeque::new(),
+            notes: HashSet::new(),
+        });
+        Ok(())
+    }
+
+    /// Stop playing: cancel the messages scheduled which haven't been sent yet, and send a
+    /// note off for each note left on.
+    pub fn stop(&mut self, output: &MidiOutput) -> Result<(), MidiOutputError> {
+        let Some(mut playing) = self.playing.take() else { return Ok(()) };
+        playing.forget_sent(Instant::now());
+        let mut notes = playing.notes;
+        for (_, id, msg) in playing.scheduled {
+            output.cancel(id)?;
+            // The note on may be sent before it's cancelled, in which case the note off
+            // following the cancel still turns it off
+            if let MidiMessage::NoteOn { channel, note, .. } = msg {
+                notes.insert((channel, note));
+            }
+        }
+        let mut notes: Vec<_> = notes.into_iter().collect();
+        notes.sort_unstable();
+        for (channel, note) in notes {
+            output.send_message(MidiMessage::NoteOff {
+                channel,
+                note,
+                velocity: 0,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Whether a file is playing, until its last message has been sent.
+    pub fn is_playing(&self) -> bool {
+        self.playing.is_some()
+    }
+}
+
+// A file being played
+struct Playing {
+    // Every message to send and when, in order
+    messages: Vec<(Instant, MidiMessage)>,
+    // The first message which hasn't been scheduled yet
+    next: usize,
+    // The messages scheduled which may not have been sent yet, in order
+    scheduled: VecDeque<(Instant, ScheduleId, MidiMessage)>,
+    // The notes which have been sent a note on but no note off yet
+    notes: HashSet<(u8, u8)>,
+}
+
+impl Playing {
+    // Forgets the scheduled messages due by `now`, taking them as sent
+    fn forget_sent(&mut self, now: Instant) {
+        while let Some((_, _, msg)) = self.scheduled.front().filter(|(at, _, _)| *at <= now) {
+            use MidiMessage::*;
+            match *msg {
+                NoteOn {
+                    channel,
+                    note,
+                    velocity,
+                } if velocity > 0 => {
+                    self.notes.insert((channel, note));
+                }
+                NoteOn { channel, note, .. } | NoteOff { channel, note, .. } => {
+                    self.notes.remove(&(channel, note));
+                }
+                AllNotesOff { channel } | AllSoundOff { channel } => {
+                    self.notes.retain(|(c, _)| *c != channel);
+                }
+                _ => {}
+            }
+            self.scheduled.pop_front();
+        }
+    }
+
+    // Schedules the messages due before `horizon`, returning whether every message has
+    // been scheduled and sent
+    fn schedule_until(
+        &mut self,
+        output: &MidiOutput,
+        horizon: Instant,
+    ) -> Result<bool, MidiOutputError> {
+        while let Some((at, msg)) = self.messages.get(self.next).filter(|(at, _)| *at < horizon) {
+            let id = output.schedule(msg.clone(), *at)?;
+            self.scheduled.push_back((*at, id, msg.clone()));
+            self.next += 1;
+        }
+        Ok(self.next == self.messages.len() && self.scheduled.is_empty())
+    }
+}
+
+// Schedules the messages due before the lookahead runs out, stopping once they've all been
+// sent
+fn play(
+    mut player: ResMut<MidiPlayer>,
+    output: Res<MidiOutput>,
+    mut err: EventWriter<MidiOutputError>,
+) {
+    let lookahead = player.lookahead;
+    let Some(playing) = &mut player.bypass_change_detection().playing else { return };
+    let now = Instant::now();
+    playing.forget_sent(now);
+    let done = match playing.schedule_until(&output, now + lookahead) {
+        Ok(done) => done,
+        Err(e) => {
+            err.send(e);
+            true
+        }
+    };
+    if done {
+        player.playing = None;
+    }
+}
@@ -0,0 +1,184 @@
+use super::input::MidiData;
+use super::message::MidiMessage;
+use super::output::{MidiOutput, MidiSent};
+use super::smf::{Smf, SmfError, TrackEvent, TrackEventKind};
+use bevy::ecs::event::ManualEventReader;
+use bevy::prelude::*;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// How many ticks per beat recordings are written with.
+pub const TICKS_PER_BEAT: u16 = 480;
+
+pub struct MidiRecorderPlugin;
+
+impl Plugin for MidiRecorderPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MidiRecorder>().add_system(record);
+    }
+}
+
+/// [`Resource`](bevy::ecs::system::Resource) for recording the midi messages sent through
+/// [`MidiOutput`] and received by [`MidiInput`](super::input::MidiInput), to save as a
+/// Standard MIDI File.
+///
+/// [`MidiOutput`] is tapped with [`MidiOutput::tap`] while recording, from the update
+/// after [`start`](Self::start), so messages sent before then are missed. Either plugin
+/// can be left out, in which case its messages aren't recorded.
+///
+/// Change detection fires when recording starts or stops, and when messages are recorded.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct MidiRecorder {
+    // When the recording started, while recording
+    start: Option<Instant>,
+    recorded: Vec<RecordedMidi>,
+}
+
+/// A midi message recorded by [`MidiRecorder`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordedMidi {
+    /// When the message was sent or received, since the recording started.
+    pub at: Duration,
+    pub source: MidiSource,
+    /// The raw bytes of the message.
+    pub message: Vec<u8>,
+}
+
+/// Where a [`RecordedMidi`] came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MidiSource {
+    /// Sent through [`MidiOutput`].
+    Output,
+    /// Received by [`MidiInput`](super::input::MidiInput).
+    Input,
+}
+
+impl MidiRecorder {
+    /// Start a new recording from now, dropping the last one.
+    pub fn start(&mut self) {
+        self.start = Some(Instant::now());
+        self.recorded.clear();
+    }
+
+    /// Stop recording, keeping what was recorded.
+    pub fn stop(&mut self) {
+        self.start = None;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.start.is_some()
+    }
+
+    /// The messages recorded, in the order they were sent or received.
+    pub fn recorded(&self) -> &[RecordedMidi] {
+        &self.recorded
+    }
+
+    // Records `message` from `source` at `at`, unless it's from before the recording,
+    // returning whether it was recorded
+    fn push(&mut self, source: MidiSource, at: Instant, message: &[u8]) -> bool {
+        let Some(start) = self.start else { return false };
+        let Some(at) = at.checked_duration_since(start) else { return false };
+        // Messages sent and received arrive separately, so keep them in order of time
+        let i = self.recorded.partition_point(|recorded| recorded.at <= at);
+        let recorded = RecordedMidi {
+            at,
+            source,
+            message: message.to_vec(),
+        };
+        self.recorded.insert(i, recorded);
+        true
+    }
+
+    /// The recording as a multi-track [`Smf`] at `tempo` beats per minute, with
+    /// [`TICKS_PER_BEAT`] ticks per beat. The first track holds the tempo, followed by a
+    /// track of the messages sent and a track of the messages received.
+    ///
+    /// Realtime messages, such as timing clocks, are left out, since a file's timing comes
+    /// from its tempo instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tempo` isn't positive.
+    pub fn to_smf(&self, tempo: f64) -> Smf {
+        assert!(tempo > 0.0, "tempo must be positive");
+        // Tempo events only go down to 60e6 / 0xFFFFFF, about 3.6 beats per minute
+        let micros = (60e6 / tempo).round().clamp(1.0, 0xFF_FFFF as f64) as u32;
+        let mut smf = Smf::new(TICKS_PER_BEAT);
+        smf.tracks.push(vec![TrackEvent {
+            tick: 0,
+            kind: TrackEventKind::Tempo(micros),
+        }]);
+
+        let tick = |at: Duration| {
+            let beats = at.as_secs_f64() * 1e6 / micros as f64;
+            (beats * TICKS_PER_BEAT as f64).round() as u64
+        };
+        for (source, name) in [(MidiSource::Output, "output"), (MidiSource::Input, "input")] {
+            let mut track = vec![TrackEvent {
+                tick: 0,
+                kind: TrackEventKind::Meta(0x03, name.into()),
+            }];
+            for recorded in self.recorded.iter().filter(|r| r.source == source) {
+                let kind = match MidiMessage::from_bytes(&recorded.message) {
+                    Ok(msg) if msg.is_realtime() => continue,
+                    Ok(msg) => TrackEventKind::Midi(msg),
+                    // Kept as they were, such as a system exclusive message in parts
+                    Err(_) => TrackEventKind::Escape(recorded.message.clone()),
+                };
+                track.push(TrackEvent {
+                    tick: tick(recorded.at),
+                    kind,
+                });
+            }
+            smf.tracks.push(track);
+        }
+        smf
+    }
+
+    /// Writes the recording to `path` as a Standard MIDI File, as laid out by
+    /// [`to_smf`](Self::to_smf).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tempo` isn't positive.
+    pub fn save(&self, path: impl AsRef<Path>, tempo: f64) -> Result<(), SmfError> {
+        self.to_smf(tempo).write(path)
+    }
+}
+
+// Records the messages sent and received since the last update, keeping MidiOutput tapped
+// only while recording
+fn record(
+    mut recorder: ResMut<MidiRecorder>,
+    output: Option<Res<MidiOutput>>,
+    sent: Option<Res<Events<MidiSent>>>,
+    received: Option<Res<Events<MidiData>>>,
+    mut sent_reader: Local<ManualEventReader<MidiSent>>,
+    mut received_reader: Local<ManualEventReader<MidiData>>,
+    mut tapped: Local<bool>,
+) {
+    let recording = recorder.is_recording();
+    if let Some(output) = output.filter(|_| recording != *tapped) {
+        // This only fails once the midi thread has stopped, which MidiOutput reports
+        let _ = output.tap(recording);
+        *tapped = recording;
+    }
+    // Messages go on arriving while not recording, which aren't a change
+    let mut changed = false;
+    if let Some(sent) = sent {
+        for msg in sent_reader.iter(&sent) {
+            let recorder = recorder.bypass_change_detection();
+            changed |= recorder.push(MidiSource::Output, msg.sent, &msg.message);
+        }
+    }
+    if let Some(received) = received {
+        for data in received_reader.iter(&received) {
+            let recorder = recorder.bypass_change_detection();
+            changed |= recorder.push(MidiSource::Input, data.received, &data.message);
+        }
+    }
+    if changed {
+        recorder.set_changed();
+    }
+}
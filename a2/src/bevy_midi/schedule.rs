@@ -0,0 +1,190 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::time::{Duration, Instant};
+
+/// Maps beats to instants at a fixed tempo, for scheduling midi messages by beat.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BeatClock {
+    // The instant of beat 0
+    origin: Instant,
+    tempo: f64,
+}
+
+impl BeatClock {
+    /// A clock at `tempo` beats per minute, with beat 0 now.
+    #[must_use]
+    pub fn new(tempo: f64) -> Self {
+        BeatClock::starting_at(Instant::now(), tempo)
+    }
+
+    /// A clock at `tempo` beats per minute, with beat 0 at `origin`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tempo` isn't positive.
+    #[must_use]
+    pub fn starting_at(origin: Instant, tempo: f64) -> Self {
+        assert!(tempo > 0.0, "tempo must be positive");
+        BeatClock { origin, tempo }
+    }
+
+    /// Changes the tempo from `at` onwards, keeping the beat at `at` where it was.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tempo` isn't positive.
+    #[must_use]
+    pub fn with_tempo(self, tempo: f64, at: Instant) -> Self {
+        let beat = self.beat_at(at);
+        let clock = BeatClock::starting_at(at, tempo);
+        // Only an origin further back than an Instant can go would fail, in which case
+        // the beats restart at `at`
+        BeatClock::starting_at(clock.instant_at(-beat).unwrap_or(at), tempo)
+    }
+
+    /// The tempo in beats per minute.
+    #[must_use]
+    pub fn tempo(&self) -> f64 {
+        self.tempo
+    }
+
+    /// The length of a beat.
+    #[must_use]
+    pub fn beat_duration(&self) -> Duration {
+        Duration::from_secs_f64(60.0 / self.tempo)
+    }
+
+    /// The beat at `instant`, which is negative before beat 0.
+    #[must_use]
+    pub fn beat_at(&self, instant: Instant) -> f64 {
+        let seconds = match instant.checked_duration_since(self.origin) {
+            Some(after) => after.as_secs_f64(),
+            None => -self.origin.duration_since(instant).as_secs_f64(),
+        };
+        seconds * self.tempo / 60.0
+    }
+
+    /// The instant of `beat`, or `None` if it isn't finite or is too far from beat 0 for
+    /// an [Instant] to represent.
+    #[must_use]
+    pub fn instant_at(&self, beat: f64) -> Option<Instant> {
+        let seconds = beat * 60.0 / self.tempo;
+        // Well short of where `Duration` overflows, and of anything an `Instant` can reach
+        if !seconds.is_finite() || seconds.abs() > 1e15 {
+            return None;
+        }
+        let offset = Duration::from_secs_f64(seconds.abs());
+        if seconds >= 0.0 {
+            self.origin.checked_add(offset)
+        } else {
+            self.origin.checked_sub(offset)
+        }
+    }
+}
+
+/// Identifies a scheduled message, so it can be cancelled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ScheduleId(pub(crate) u64);
+
+/// When a scheduled message should be sent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScheduleTime {
+    At(Instant),
+    /// A beat of the current [BeatClock]. Messages scheduled by beat wait until a clock is
+    /// set, and follow it when it changes. Beats which no [Instant] can represent are never
+    /// sent.
+    Beat(f64),
+}
+
+struct Scheduled {
+    time: ScheduleTime,
+    bytes: Vec<u8>,
+}
+
+// Messages waiting to be sent, along with a queue of when they're due
+#[derive(Default)]
+pub(crate) struct Schedule {
+    pending: HashMap<ScheduleId, Scheduled>,
+    // The instant each pending message is due, soonest first, and then by id, which is
+    // the order they were scheduled in. Cancelled messages are left in the queue until
+    // they come up, and messages without an instant (scheduled by beat without a clock)
+    // aren't in it.
+    queue: BinaryHeap<Reverse<(Instant, ScheduleId)>>,
+    clock: Option<BeatClock>,
+}
+
+impl Schedule {
+    pub(crate) fn push(&mut self, id: ScheduleId, time: ScheduleTime, bytes: Vec<u8>) {
+        if let Some(instant) = self.instant(time) {
+            self.queue.push(Reverse((instant, id)));
+        }
+        self.pending.insert(id, Scheduled { time, bytes });
+    }
+
+    pub(crate) fn cancel(&mut self, id: ScheduleId) {
+        self.pending.remove(&id);
+        // Keep cancelled messages from piling up in the queue
+        if self.queue.len() > 2 * self.pending.len() + 16 {
+            self.requeue();
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.pending.clear();
+        self.queue.clear();
+    }
+
+    pub(crate) fn set_clock(&mut self, clock: BeatClock) {
+        self.clock = Some(clock);
+        // Messages scheduled by beat move with the clock
+        self.requeue();
+    }
+
+    pub(crate) fn clock(&self) -> Option<BeatClock> {
+        self.clock
+    }
+
+    fn instant(&self, time: ScheduleTime) -> Option<Instant> {
+        match time {
+            ScheduleTime::At(instant) => Some(instant),
+            ScheduleTime::Beat(beat) => self.clock.and_then(|clock| clock.instant_at(beat)),
+        }
+    }
+
+    // Rebuilds the queue from the pending messages
+    fn requeue(&mut self) {
+        let queue = self
+            .pending
+            .iter()
+            .filter_map(|(id, scheduled)| Some(Reverse((self.instant(scheduled.time)?, *id))))
+            .collect();
+        self.queue = queue;
+    }
+
+    /// The instant of the next message due.
+    pub(crate) fn next_due(&mut self) -> Option<Instant> {
+        while let Some(Reverse((instant, id))) = self.queue.peek() {
+            if self.pending.contains_key(id) {
+                return Some(*instant);
+            }
+            self.queue.pop();
+        }
+        None
+    }
+
+    /// Removes every message due by `now`, in the order they're due (and then the order
+    /// they were scheduled in).
+    pub(crate) fn pop_due(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        let mut due = Vec::new();
+        while let Some(Reverse((instant, id))) = self.queue.peek() {
+            if *instant > now {
+                break;
+            }
+            if let Some(scheduled) = self.pending.remove(id) {
+                due.push(scheduled.bytes);
+            }
+            self.queue.pop();
+        }
+        due
+    }
+}
@@ -0,0 +1,455 @@
+use super::message::{data_len, MidiMessage, MidiMessageError};
+use std::error::Error;
+use std::fmt::Display;
+use std::path::Path;
+use SmfError::*;
+
+/// The tempo of a file without tempo events, in microseconds per beat (120 bpm).
+pub const DEFAULT_TEMPO: u32 = 500_000;
+
+/// A Standard MIDI File: tracks of midi messages and meta events, timed in ticks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Smf {
+    pub format: SmfFormat,
+    pub timing: Timing,
+    pub tracks: Vec<Track>,
+}
+
+/// How the tracks of an [`Smf`] relate to each other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SmfFormat {
+    /// Format 0: a single track holding everything.
+    SingleTrack,
+    /// Format 1: tracks which play together, with the tempo events in the first.
+    MultiTrack,
+    /// Format 2: independent patterns, one per track.
+    Sequential,
+}
+
+/// What the ticks of an [`Smf`] measure.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timing {
+    /// Ticks per beat, where the length of a beat follows the tempo events.
+    TicksPerBeat(u16),
+    /// Ticks per frame of SMPTE timecode, at `fps` frames per second: `24`, `25`, `29`
+    /// (for 29.97 drop frame) or `30`. Tempo events are ignored.
+    Timecode { fps: u8, ticks_per_frame: u8 },
+}
+
+/// A track of an [`Smf`], with its events in order of their ticks.
+///
+/// The end of track event is left out, and written after the last event.
+pub type Track = Vec<TrackEvent>;
+
+/// An event in a [`Track`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrackEvent {
+    /// When the event happens, in ticks since the start of the track.
+    pub tick: u64,
+    pub kind: TrackEventKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TrackEventKind {
+    /// A channel message, or a complete system exclusive message.
+    Midi(MidiMessage),
+    /// A tempo change, in microseconds per beat (up to `0xFFFFFF`).
+    Tempo(u32),
+    /// Any other meta event, by its type byte and data.
+    Meta(u8, Vec<u8>),
+    /// Bytes to send as they are, such as a system exclusive message split over several
+    /// events, or a system common message.
+    Escape(Vec<u8>),
+}
+
+/// Errors from reading or parsing an [`Smf`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SmfError {
+    /// The file doesn't start with an `MThd` header chunk.
+    MissingHeader,
+    UnsupportedFormat(u16),
+    /// A timecode frame rate other than `24`, `25`, `29` or `30`.
+    UnsupportedFps(u8),
+    /// The file ends part way through a chunk or event.
+    UnexpectedEnd,
+    /// A variable length quantity longer than 4 bytes.
+    InvalidVarLen,
+    /// A midi message in a track which couldn't be parsed.
+    InvalidMessage(MidiMessageError),
+    Io(std::io::ErrorKind),
+}
+
+impl Error for SmfError {}
+impl Display for SmfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            MissingHeader => write!(f, "Not a midi file: missing MThd header"),
+            UnsupportedFormat(n) => write!(f, "Unsupported midi file format {}", n),
+            UnsupportedFps(fps) => write!(f, "Unsupported midi file frame rate {}", fps),
+            UnexpectedEnd => write!(f, "Midi file ends unexpectedly"),
+            InvalidVarLen => write!(f, "Invalid variable length quantity in midi file"),
+            InvalidMessage(e) => write!(f, "Invalid message in midi file: {}", e),
+            Io(kind) => write!(f, "Couldn't read or write midi file: {}", kind),
+        }
+    }
+}
+
+impl Smf {
+    /// An empty multi-track file with `ticks_per_beat` ticks per beat.
+    #[must_use]
+    pub fn new(ticks_per_beat: u16) -> Self {
+        Smf {
+            format: SmfFormat::MultiTrack,
+            timing: Timing::TicksPerBeat(ticks_per_beat),
+            tracks: Vec::new(),
+        }
+    }
+
+    /// Reads the file at `path`.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, SmfError> {
+        let bytes = std::fs::read(path).map_err(|e| Io(e.kind()))?;
+        Smf::from_bytes(&bytes)
+    }
+
+    /// Writes the file to `path`.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), SmfError> {
+        std::fs::write(path, self.to_bytes()?).map_err(|e| Io(e.kind()))
+    }
+
+    /// Parses a file. Chunks other than the header and tracks are skipped.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SmfError> {
+        let mut reader = Reader { bytes, pos: 0 };
+        let (id, header) = reader.chunk()?;
+        if id != *b"MThd" || header.len() < 6 {
+            return Err(MissingHeader);
+        }
+        let format = match u16::from_be_bytes([header[0], header[1]]) {
+            0 => SmfFormat::SingleTrack,
+            1 => SmfFormat::MultiTrack,
+            2 => SmfFormat::Sequential,
+            n => return Err(UnsupportedFormat(n)),
+        };
+        let timing = match [header[4], header[5]] {
+            [fps, ticks_per_frame] if fps & 0x80 != 0 => Timing::Timecode {
+                fps: check_fps((fps as i8).unsigned_abs())?,
+                ticks_per_frame,
+            },
+            division => Timing::TicksPerBeat(u16::from_be_bytes(division)),
+        };
+
+        let mut tracks = Vec::new();
+        while !reader.is_empty() {
+            let (id, data) = reader.chunk()?;
+            if id == *b"MTrk" {
+                tracks.push(parse_track(data)?);
+            }
+        }
+        Ok(Smf {
+            format,
+            timing,
+            tracks,
+        })
+    }
+
+    /// The bytes of the file, using running status within each track.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SmfError> {
+        let format: u16 = match self.format {
+            SmfFormat::SingleTrack => 0,
+            SmfFormat::MultiTrack => 1,
+            SmfFormat::Sequential => 2,
+        };
+        let division = match self.timing {
+            Timing::TicksPerBeat(ticks) => ticks.to_be_bytes(),
+            Timing::Timecode {
+                fps,
+                ticks_per_frame,
+            } => [(check_fps(fps)? as i8).wrapping_neg() as u8, ticks_per_frame],
+        };
+        let mut header = Vec::new();
+        header.extend(format.to_be_bytes());
+        header.extend((self.tracks.len() as u16).to_be_bytes());
+        header.extend(division);
+
+        let mut bytes = Vec::new();
+        push_chunk(&mut bytes, b"MThd", &header);
+        for track in &self.tracks {
+            push_chunk(&mut bytes, b"MTrk", &track_bytes(track));
+        }
+        Ok(bytes)
+    }
+
+    /// The events of every track merged in order of their ticks, with ties in the order
+    /// of the tracks. Sequential patterns are merged as if they played together too.
+    pub fn timeline(&self) -> Vec<(u64, &TrackEventKind)> {
+        let mut events: Vec<_> = self
+            .tracks
+            .iter()
+            .flatten()
+            .map(|event| (event.tick, &event.kind))
+            .collect();
+        // A stable sort keeps the order within and across tracks
+        events.sort_by_key(|(tick, _)| *tick);
+        events
+    }
+}
+
+/// Maps the ticks of an [`Smf`] to seconds, following its tempo events.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TempoMap {
+    timing: Timing,
+    // Each tempo change: its tick, the seconds at that tick, and the new tempo in
+    // microseconds per beat
+    changes: Vec<(u64, f64, u32)>,
+}
+
+impl TempoMap {
+    /// The tempo map of `smf`, from the tempo events in all of its tracks.
+    pub fn new(smf: &Smf) -> Self {
+        let mut tempos: Vec<(u64, u32)> = smf
+            .timeline()
+            .into_iter()
+            .filter_map(|(tick, kind)| match kind {
+                TrackEventKind::Tempo(tempo) => Some((tick, *tempo)),
+                _ => None,
+            })
+            .collect();
+        if !matches!(tempos.first(), Some((0, _))) {
+            tempos.insert(0, (0, DEFAULT_TEMPO));
+        }
+
+        let mut map = TempoMap {
+            timing: smf.timing,
+            changes: Vec::new(),
+        };
+        for (tick, tempo) in tempos {
+            let seconds = map.seconds_at(tick);
+            map.changes.push((tick, seconds, tempo));
+        }
+        map
+    }
+
+    /// The seconds from the start of the file to `tick`.
+    pub fn seconds_at(&self, tick: u64) -> f64 {
+        match self.timing {
+            Timing::TicksPerBeat(ticks_per_beat) => {
+                // There's only no change while building the map, for the one at tick 0
+                let Some(&(start, seconds, tempo)) = self.change_at(tick) else { return 0.0 };
+                let beats = (tick - start) as f64 / ticks_per_beat.max(1) as f64;
+                seconds + beats * tempo as f64 / 1e6
+            }
+            Timing::Timecode {
+                fps,
+                ticks_per_frame,
+            } => {
+                let fps = match fps {
+                    29 => 30_000.0 / 1001.0,
+                    fps => fps as f64,
+                };
+                tick as f64 / (fps * ticks_per_frame.max(1) as f64)
+            }
+        }
+    }
+
+    /// The tempo at `tick` in beats per minute.
+    pub fn tempo_at(&self, tick: u64) -> f64 {
+        let tempo = self
+            .change_at(tick)
+            .map_or(DEFAULT_TEMPO, |(_, _, tempo)| *tempo);
+        60e6 / tempo.max(1) as f64
+    }
+
+    // The last tempo change at or before `tick`
+    fn change_at(&self, tick: u64) -> Option<&(u64, f64, u32)> {
+        self.changes
+            .iter()
+            .rev()
+            .find(|(start, _, _)| *start <= tick)
+    }
+}
+
+// Reads the parts of a file, failing with `UnexpectedEnd` past the end
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SmfError> {
+        let end = self.pos.checked_add(len).ok_or(UnexpectedEnd)?;
+        let bytes = self.bytes.get(self.pos..end).ok_or(UnexpectedEnd)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, SmfError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn var_len(&mut self) -> Result<u32, SmfError> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.byte()?;
+            value = value << 7 | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(InvalidVarLen)
+    }
+
+    // A block of data after its variable length
+    fn var_data(&mut self) -> Result<&'a [u8], SmfError> {
+        let len = self.var_len()?;
+        self.take(len as usize)
+    }
+
+    fn chunk(&mut self) -> Result<([u8; 4], &'a [u8]), SmfError> {
+        let id = self.take(4)?;
+        let len = self.take(4)?;
+        let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]);
+        let data = self.take(len as usize)?;
+        Ok(([id[0], id[1], id[2], id[3]], data))
+    }
+}
+
+// The frame rates of SMPTE timecode, the only ones the header of a file can hold
+fn check_fps(fps: u8) -> Result<u8, SmfError> {
+    match fps {
+        24 | 25 | 29 | 30 => Ok(fps),
+        fps => Err(UnsupportedFps(fps)),
+    }
+}
+
+fn parse_track(data: &[u8]) -> Result<Track, SmfError> {
+    let mut reader = Reader {
+        bytes: data,
+        pos: 0,
+    };
+    let mut track = Vec::new();
+    let mut tick = 0;
+    let mut running_status = None;
+    // A track missing its end of track event ends with its chunk
+    while !reader.is_empty() {
+        tick += reader.var_len()? as u64;
+        let status = match reader.byte()? {
+            byte if byte < 0x80 => {
+                // The first data byte of a message using running status
+                reader.pos -= 1;
+                running_status.ok_or(InvalidMessage(MidiMessageError::MissingStatus(byte)))?
+            }
+            byte => byte,
+        };
+        let kind = match status {
+            0xFF => {
+                running_status = None;
+                let kind = reader.byte()?;
+                let data = reader.var_data()?;
+                match (kind, data) {
+                    (0x2F, _) => break,
+                    (0x51, &[a, b, c]) => TrackEventKind::Tempo(u32::from_be_bytes([0, a, b, c])),
+                    _ => TrackEventKind::Meta(kind, data.to_vec()),
+                }
+            }
+            0xF0 => {
+                running_status = None;
+                let data = reader.var_data()?;
+                match data.split_last() {
+                    Some((0xF7, data)) if data.iter().all(|b| *b < 0x80) => {
+                        TrackEventKind::Midi(MidiMessage::SysEx(data.to_vec()))
+                    }
+                    // The first packet of a message continued by escapes
+                    _ => TrackEventKind::Escape([&[0xF0], data].concat()),
+                }
+            }
+            0xF7 => {
+                running_status = None;
+                TrackEventKind::Escape(reader.var_data()?.to_vec())
+            }
+            0x80..=0xEF => {
+                running_status = Some(status);
+                let data = reader.take(data_len(status))?;
+                let message = MidiMessage::from_bytes(&[&[status], data].concat());
+                TrackEventKind::Midi(message.map_err(InvalidMessage)?)
+            }
+            _ => return Err(InvalidMessage(MidiMessageError::UndefinedStatus(status))),
+        };
+        track.push(TrackEvent { tick, kind });
+    }
+    Ok(track)
+}
+
+fn track_bytes(track: &Track) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut tick = 0;
+    let mut running_status = None;
+    for event in track {
+        // Deltas only go up to 28 bits, days at any usual resolution
+        let delta = event.tick.saturating_sub(tick).min(0x0FFF_FFFF);
+        tick = tick.max(event.tick);
+        push_var_len(&mut bytes, delta as u32);
+        match &event.kind {
+            // The data bytes are masked and end with the 0xF7
+            TrackEventKind::Midi(message @ MidiMessage::SysEx(_)) => {
+                running_status = None;
+                bytes.push(0xF0);
+                push_var_data(&mut bytes, &message.data_bytes());
+            }
+            TrackEventKind::Midi(message) if message.status() < 0xF0 => {
+                let status = message.status();
+                if running_status != Some(status) {
+                    running_status = Some(status);
+                    bytes.push(status);
+                }
+                bytes.extend(message.data_bytes());
+            }
+            // System common and realtime messages only fit in a file as escapes
+            TrackEventKind::Midi(message) => {
+                running_status = None;
+                bytes.push(0xF7);
+                push_var_data(&mut bytes, &message.to_bytes());
+            }
+            TrackEventKind::Tempo(tempo) => {
+                running_status = None;
+                bytes.extend([0xFF, 0x51]);
+                push_var_data(&mut bytes, &(*tempo).min(0xFF_FFFF).to_be_bytes()[1..]);
+            }
+            TrackEventKind::Meta(kind, data) => {
+                running_status = None;
+                bytes.extend([0xFF, *kind]);
+                push_var_data(&mut bytes, data);
+            }
+            TrackEventKind::Escape(data) => {
+                running_status = None;
+                bytes.push(0xF7);
+                push_var_data(&mut bytes, data);
+            }
+        }
+    }
+    bytes.extend([0x00, 0xFF, 0x2F, 0x00]);
+    bytes
+}
+
+fn push_var_len(bytes: &mut Vec<u8>, value: u32) {
+    let mut groups = vec![(value & 0x7F) as u8];
+    let mut value = value >> 7;
+    while value > 0 {
+        groups.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.extend(groups.into_iter().rev());
+}
+
+fn push_var_data(bytes: &mut Vec<u8>, data: &[u8]) {
+    push_var_len(bytes, data.len() as u32);
+    bytes.extend(data);
+}
+
+fn push_chunk(bytes: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    bytes.extend(id);
+    bytes.extend((data.len() as u32).to_be_bytes());
+    bytes.extend(data);
+}
@@ -1,4 +1,9 @@
+use crate::complex::Complex;
+use crate::per_channel::PerChannel;
+use crate::plot::unit_circle;
+use plotters::prelude::*;
 use rodio::Source;
+use std::{error::Error, path::Path};
 
 #[derive(Clone)]
 pub struct BiQuad {
@@ -14,34 +19,115 @@ impl BiQuad {
         BiQuad { b0, b1, b2, a1, a2 }
     }
 
+    /// The poles of the filter's transfer function, the roots of `z^2 + a1 z + a2`. The
+    /// filter is stable when they're both inside the unit circle.
+    #[must_use]
+    pub fn poles(&self) -> Vec<Complex<f32>> {
+        quadratic_roots(1.0, self.a1, self.a2)
+    }
+
+    /// The finite zeros of the filter's transfer function, the roots of
+    /// `b0 z^2 + b1 z + b2`.
+    #[must_use]
+    pub fn zeros(&self) -> Vec<Complex<f32>> {
+        quadratic_roots(self.b0, self.b1, self.b2)
+    }
+
+    /// The gain and phase shift of the filter at `frequency` Hz.
+    #[must_use]
+    pub fn frequency_response(&self, frequency: f32, sample_rate: u32) -> Complex<f32> {
+        let z = Complex::cis(std::f32::consts::TAU * frequency / sample_rate as f32);
+        let z2 = z * z;
+        (z2 * self.b0 + z * self.b1 + self.b2) / (z2 + z * self.a1 + self.a2)
+    }
+
+    /// Draws the poles (as crosses) and zeros (as circles) of the filter on the complex
+    /// plane to a PNG file, along with the unit circle.
+    pub fn render_pole_zero_png(
+        &self,
+        path: impl AsRef<Path>,
+        size: (u32, u32),
+        caption: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let (poles, zeros) = (self.poles(), self.zeros());
+        let extent = poles
+            .iter()
+            .chain(&zeros)
+            .fold(1.0f32, |extent, z| extent.max(z.magnitude()))
+            * 1.25;
+
+        let root = BitMapBackend::new(path.as_ref(), size).into_drawing_area();
+        root.fill(&WHITE)?;
+        let mut chart = ChartBuilder::on(&root)
+            .margin(10)
+            .x_label_area_size(30)
+            .y_label_area_size(40)
+            .caption(caption, ("sans-serif", 20.0).into_font())
+            .build_cartesian_2d(-extent..extent, -extent..extent)?;
+        chart.configure_mesh().x_desc("Re").y_desc("Im").draw()?;
+
+        chart.draw_series(std::iter::once(unit_circle(BLACK.mix(0.5))))?;
+        chart.draw_series(
+            poles
+                .iter()
+                .map(|z| Cross::new((z.real, z.im), 6, RED.stroke_width(2))),
+        )?;
+        chart.draw_series(
+            zeros
+                .iter()
+                .map(|z| Circle::new((z.real, z.im), 6, BLUE.stroke_width(2))),
+        )?;
+        root.present()?;
+        Ok(())
+    }
+
     pub fn source_from<S: Source<Item = f32>>(&self, source: S) -> BiQuadSource<S> {
         BiQuadSource {
+            state: PerChannel::new(source.channels(), BiQuadState::default()),
             source,
             biquad: self.clone(),
-            x1: 0.0,
-            x2: 0.0,
-            y1: 0.0,
-            y2: 0.0,
         }
     }
 }
 
-pub struct BiQuadSource<S: Source<Item = f32>> {
-    source: S,
-    biquad: BiQuad,
+// The roots of `a z^2 + b z + c`, dropping any which are at infinity
+fn quadratic_roots(a: f32, b: f32, c: f32) -> Vec<Complex<f32>> {
+    if a == 0.0 && b == 0.0 {
+        return Vec::new();
+    }
+    if a == 0.0 {
+        return vec![Complex::from(-c / b)];
+    }
+    let root = Complex::from(b * b - 4.0 * a * c).sqrt();
+    vec![(-b + root) / (2.0 * a), (-b - root) / (2.0 * a)]
+}
+
+// The filter history of a single channel.
+#[derive(Clone, Copy, Default)]
+struct BiQuadState {
     x1: f32,
     x2: f32,
     y1: f32,
     y2: f32,
 }
 
+/// A [Source] which runs a [BiQuad] filter over each channel of its inner source.
+///
+/// Interleaved channels are filtered independently, so the history of one channel never
+/// leaks into another.
+pub struct BiQuadSource<S: Source<Item = f32>> {
+    source: S,
+    biquad: BiQuad,
+    state: PerChannel<BiQuadState>,
+}
+
 // y = b0*x + b1*x1 + b2*x2 - a1*y1 - a2*y2
 impl<S: Source<Item = f32>> BiQuadSource<S> {
+    /// Sets the filter history of every channel.
     pub fn with_initial_values(&mut self, x1: f32, x2: f32, y1: f32, y2: f32) {
-        self.x1 = x1;
-        self.x2 = x2;
-        self.y1 = y1;
-        self.y2 = y2;
+        for state in self.state.iter_mut() {
+            *state = BiQuadState { x1, x2, y1, y2 };
+        }
     }
 }
 
@@ -49,14 +135,20 @@ impl<S: Source<Item = f32>> Iterator for BiQuadSource<S> {
     type Item = S::Item;
 
     fn next(&mut self) -> Option<Self::Item> {
+        self.state.set_channels(self.source.channels(), BiQuadState::default);
+
         let Some(x) = self.source.next() else { return None; };
-        let y = self.biquad.b0 * x + self.biquad.b1 * self.x1 + self.biquad.b2 * self.x2
-            - self.biquad.a1 * self.y1
-            - self.biquad.a2 * self.y2;
-        self.x2 = self.x1;
-        self.x1 = x;
-        self.y2 = self.y1;
-        self.y1 = y;
+        let biquad = &self.biquad;
+        let y = self.state.next(|state| {
+            let y = biquad.b0 * x + biquad.b1 * state.x1 + biquad.b2 * state.x2
+                - biquad.a1 * state.y1
+                - biquad.a2 * state.y2;
+            state.x2 = state.x1;
+            state.x1 = x;
+            state.y2 = state.y1;
+            state.y1 = y;
+            y
+        });
         Some(y)
     }
 }
@@ -4,25 +4,239 @@ use plotters::{
     style::{IntoFont, RGBColor, ShapeStyle},
 };
 use std::{
-    fmt::Debug,
-    ops::{Add, Mul},
+    fmt::{Debug, Display},
+    iter::{Product, Sum},
+    ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign},
 };
 
-#[derive(Clone, Copy, Default)]
-pub struct Complex {
-    pub real: f32,
-    pub im: f32,
+/// A floating point type which [Complex] numbers can be built from; either `f32` or `f64`.
+pub trait Float:
+    Copy
+    + Default
+    + PartialEq
+    + PartialOrd
+    + Debug
+    + Display
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+    + SubAssign
+    + MulAssign
+    + DivAssign
+    + Sum
+    + Product
+{
+    const ZERO: Self;
+    const ONE: Self;
+    const PI: Self;
+    const TAU: Self;
+
+    fn from_f64(x: f64) -> Self;
+    fn to_f64(self) -> f64;
+    fn sqrt(self) -> Self;
+    fn abs(self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    fn sin_cos(self) -> (Self, Self);
+    fn atan2(self, other: Self) -> Self;
+    fn hypot(self, other: Self) -> Self;
+    fn exp(self) -> Self;
+    fn ln(self) -> Self;
+    fn powf(self, n: Self) -> Self;
+}
+
+macro_rules! impl_float {
+    ($t:ident) => {
+        impl Float for $t {
+            const ZERO: Self = 0.0;
+            const ONE: Self = 1.0;
+            const PI: Self = std::$t::consts::PI;
+            const TAU: Self = std::$t::consts::TAU;
+
+            fn from_f64(x: f64) -> Self {
+                x as $t
+            }
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+            fn sqrt(self) -> Self {
+                $t::sqrt(self)
+            }
+            fn abs(self) -> Self {
+                $t::abs(self)
+            }
+            fn sin(self) -> Self {
+                $t::sin(self)
+            }
+            fn cos(self) -> Self {
+                $t::cos(self)
+            }
+            fn sin_cos(self) -> (Self, Self) {
+                $t::sin_cos(self)
+            }
+            fn atan2(self, other: Self) -> Self {
+                $t::atan2(self, other)
+            }
+            fn hypot(self, other: Self) -> Self {
+                $t::hypot(self, other)
+            }
+            fn exp(self) -> Self {
+                $t::exp(self)
+            }
+            fn ln(self) -> Self {
+                $t::ln(self)
+            }
+            fn powf(self, n: Self) -> Self {
+                $t::powf(self, n)
+            }
+        }
+    };
 }
 
-impl Complex {
-    pub fn new(real: f32, im: f32) -> Self {
+impl_float!(f32);
+impl_float!(f64);
+
+/// A complex number, made of a real and an imaginary part.
+///
+/// Defaults to `f32` parts, to match the samples of a [Source](rodio::Source).
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Complex<T = f32> {
+    pub real: T,
+    pub im: T,
+}
+
+impl<T: Float> Complex<T> {
+    pub const ZERO: Self = Complex {
+        real: T::ZERO,
+        im: T::ZERO,
+    };
+    pub const ONE: Self = Complex {
+        real: T::ONE,
+        im: T::ZERO,
+    };
+    pub const I: Self = Complex {
+        real: T::ZERO,
+        im: T::ONE,
+    };
+
+    pub fn new(real: T, im: T) -> Self {
         Complex { real, im }
     }
 
-    pub fn magnitude(&self) -> f32 {
-        (self.real * self.real + self.im * self.im).sqrt()
+    pub fn magnitude(&self) -> T {
+        self.real.hypot(self.im)
+    }
+
+    /// The square of the magnitude, which avoids taking a square root.
+    pub fn norm_sqr(&self) -> T {
+        self.real * self.real + self.im * self.im
+    }
+
+    /// The angle of the number from the positive real axis, between `-PI` and `PI`.
+    pub fn arg(&self) -> T {
+        self.im.atan2(self.real)
+    }
+
+    pub fn from_polar(r: T, theta: T) -> Self {
+        let (sin, cos) = theta.sin_cos();
+        Complex {
+            real: r * cos,
+            im: r * sin,
+        }
+    }
+
+    /// Gives the magnitude and the angle of the number.
+    pub fn to_polar(&self) -> (T, T) {
+        (self.magnitude(), self.arg())
     }
 
+    /// `e^(i * theta)`, the point on the unit circle at angle `theta`.
+    pub fn cis(theta: T) -> Self {
+        Self::from_polar(T::ONE, theta)
+    }
+
+    pub fn conj(&self) -> Self {
+        Complex {
+            real: self.real,
+            im: -self.im,
+        }
+    }
+
+    /// `1 / self`
+    pub fn recip(&self) -> Self {
+        let norm = self.norm_sqr();
+        Complex {
+            real: self.real / norm,
+            im: -self.im / norm,
+        }
+    }
+
+    pub fn exp(&self) -> Self {
+        Self::from_polar(self.real.exp(), self.im)
+    }
+
+    /// The principal natural logarithm, whose imaginary part is between `-PI` and `PI`.
+    pub fn ln(&self) -> Self {
+        Complex {
+            real: self.magnitude().ln(),
+            im: self.arg(),
+        }
+    }
+
+    /// Raises the number to a real power, using the principal branch. Anything to the
+    /// power of zero is one, including zero, and zero to a negative power is infinite, as
+    /// with `f32::powf`.
+    pub fn powf(&self, n: T) -> Self {
+        if n == T::ZERO {
+            return Self::ONE;
+        }
+        // The angle of zero is meaningless, so keep it from turning an infinity into NaN
+        if *self == Self::ZERO {
+            return Self::from(T::ZERO.powf(n));
+        }
+        let (r, theta) = self.to_polar();
+        Self::from_polar(r.powf(n), theta * n)
+    }
+
+    /// Raises the number to a complex power, using the principal branch. Zero to a power
+    /// is one for a power of zero, zero for a power with a positive real part, and isn't
+    /// finite otherwise.
+    pub fn powc(&self, n: Self) -> Self {
+        if n == Self::ZERO {
+            return Self::ONE;
+        }
+        // The logarithm of zero isn't finite, so this can't go through it
+        if *self == Self::ZERO {
+            return if n.real > T::ZERO {
+                Self::ZERO
+            } else {
+                self.recip()
+            };
+        }
+        (self.ln() * n).exp()
+    }
+
+    /// The principal square root, whose real part is never negative.
+    pub fn sqrt(&self) -> Self {
+        let two = T::ONE + T::ONE;
+        let r = self.magnitude();
+        let real = ((r + self.real) / two).sqrt();
+        let im = ((r - self.real) / two).sqrt();
+        Complex {
+            real,
+            im: if self.im < T::ZERO { -im } else { im },
+        }
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.real.to_f64().is_finite() && self.im.to_f64().is_finite()
+    }
+}
+
+impl Complex<f32> {
     pub fn as_labelled_point<B: DrawingBackend>(
         &self,
         c: RGBColor,
@@ -32,19 +246,18 @@ impl Complex {
             + Circle::new((0, 0), 3, ShapeStyle::from(c).filled())
             + Text::new(label.into(), (10, 0), ("sans-serif", 15.0).into_font())
     }
+}
 
-    pub fn from_polar(r: f32, theta: f32) -> Complex {
-        Complex {
-            real: r * theta.cos(),
-            im: r * theta.sin(),
-        }
+impl<T: Float> From<T> for Complex<T> {
+    fn from(real: T) -> Self {
+        Complex { real, im: T::ZERO }
     }
 }
 
-impl Mul<Complex> for Complex {
+impl<T: Float> Mul<Complex<T>> for Complex<T> {
     type Output = Self;
 
-    fn mul(self, rhs: Complex) -> Complex {
+    fn mul(self, rhs: Complex<T>) -> Complex<T> {
         Complex {
             real: self.real * rhs.real - self.im * rhs.im,
             im: self.real * rhs.im + self.im * rhs.real,
@@ -52,10 +265,22 @@ impl Mul<Complex> for Complex {
     }
 }
 
-impl Add<Complex> for Complex {
+impl<T: Float> Div<Complex<T>> for Complex<T> {
+    type Output = Self;
+
+    fn div(self, rhs: Complex<T>) -> Complex<T> {
+        let norm = rhs.norm_sqr();
+        Complex {
+            real: (self.real * rhs.real + self.im * rhs.im) / norm,
+            im: (self.im * rhs.real - self.real * rhs.im) / norm,
+        }
+    }
+}
+
+impl<T: Float> Add<Complex<T>> for Complex<T> {
     type Output = Self;
 
-    fn add(self, rhs: Complex) -> Complex {
+    fn add(self, rhs: Complex<T>) -> Complex<T> {
         Complex {
             real: self.real + rhs.real,
             im: self.im + rhs.im,
@@ -63,7 +288,159 @@ impl Add<Complex> for Complex {
     }
 }
 
-impl Debug for Complex {
+impl<T: Float> Sub<Complex<T>> for Complex<T> {
+    type Output = Self;
+
+    fn sub(self, rhs: Complex<T>) -> Complex<T> {
+        Complex {
+            real: self.real - rhs.real,
+            im: self.im - rhs.im,
+        }
+    }
+}
+
+impl<T: Float> Neg for Complex<T> {
+    type Output = Self;
+
+    fn neg(self) -> Complex<T> {
+        Complex {
+            real: -self.real,
+            im: -self.im,
+        }
+    }
+}
+
+// Operations with a real number on the right
+impl<T: Float> Add<T> for Complex<T> {
+    type Output = Self;
+
+    fn add(self, rhs: T) -> Complex<T> {
+        Complex {
+            real: self.real + rhs,
+            im: self.im,
+        }
+    }
+}
+
+impl<T: Float> Sub<T> for Complex<T> {
+    type Output = Self;
+
+    fn sub(self, rhs: T) -> Complex<T> {
+        Complex {
+            real: self.real - rhs,
+            im: self.im,
+        }
+    }
+}
+
+impl<T: Float> Mul<T> for Complex<T> {
+    type Output = Self;
+
+    fn mul(self, rhs: T) -> Complex<T> {
+        Complex {
+            real: self.real * rhs,
+            im: self.im * rhs,
+        }
+    }
+}
+
+impl<T: Float> Div<T> for Complex<T> {
+    type Output = Self;
+
+    fn div(self, rhs: T) -> Complex<T> {
+        Complex {
+            real: self.real / rhs,
+            im: self.im / rhs,
+        }
+    }
+}
+
+macro_rules! impl_assign_op {
+    ($trait:ident, $method:ident, $op:ident) => {
+        impl<T: Float> $trait<Complex<T>> for Complex<T> {
+            fn $method(&mut self, rhs: Complex<T>) {
+                *self = (*self).$op(rhs);
+            }
+        }
+
+        impl<T: Float> $trait<T> for Complex<T> {
+            fn $method(&mut self, rhs: T) {
+                *self = (*self).$op(rhs);
+            }
+        }
+    };
+}
+
+impl_assign_op!(AddAssign, add_assign, add);
+impl_assign_op!(SubAssign, sub_assign, sub);
+impl_assign_op!(MulAssign, mul_assign, mul);
+impl_assign_op!(DivAssign, div_assign, div);
+
+// Operations with a real number on the left
+macro_rules! impl_scalar_lhs {
+    ($t:ident) => {
+        impl Add<Complex<$t>> for $t {
+            type Output = Complex<$t>;
+
+            fn add(self, rhs: Complex<$t>) -> Complex<$t> {
+                rhs + self
+            }
+        }
+
+        impl Sub<Complex<$t>> for $t {
+            type Output = Complex<$t>;
+
+            fn sub(self, rhs: Complex<$t>) -> Complex<$t> {
+                -rhs + self
+            }
+        }
+
+        impl Mul<Complex<$t>> for $t {
+            type Output = Complex<$t>;
+
+            fn mul(self, rhs: Complex<$t>) -> Complex<$t> {
+                rhs * self
+            }
+        }
+
+        impl Div<Complex<$t>> for $t {
+            type Output = Complex<$t>;
+
+            fn div(self, rhs: Complex<$t>) -> Complex<$t> {
+                Complex::from(self) / rhs
+            }
+        }
+    };
+}
+
+impl_scalar_lhs!(f32);
+impl_scalar_lhs!(f64);
+
+impl<T: Float> Sum for Complex<T> {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::ZERO, |a, b| a + b)
+    }
+}
+
+impl<'a, T: Float> Sum<&'a Complex<T>> for Complex<T> {
+    fn sum<I: Iterator<Item = &'a Self>>(iter: I) -> Self {
+        iter.fold(Self::ZERO, |a, b| a + *b)
+    }
+}
+
+impl<T: Float> Product for Complex<T> {
+    fn product<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::ONE, |a, b| a * b)
+    }
+}
+
+impl<'a, T: Float> Product<&'a Complex<T>> for Complex<T> {
+    fn product<I: Iterator<Item = &'a Self>>(iter: I) -> Self {
+        iter.fold(Self::ONE, |a, b| a * *b)
+    }
+}
+
+impl<T: Float> Debug for Complex<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{} + {}j", self.real, self.im)
     }
@@ -0,0 +1,253 @@
+use crate::complex::Complex;
+use crate::fft::RealFftPlan;
+use crate::per_channel::PerChannel;
+use rodio::{Decoder, Source};
+use std::{
+    collections::VecDeque, error::Error, fs::File, io::BufReader, path::Path, sync::Arc,
+    time::Duration,
+};
+
+/// Convolves sources with an impulse response, for cabinet simulations, reverbs, and
+/// measured filters.
+///
+/// The first `partition_len` samples of the impulse response are convolved directly, so
+/// short impulse responses don't pay for any FFTs. The rest is split into blocks of
+/// `partition_len` samples, which are convolved with uniformly partitioned FFT convolution.
+/// Since the direct part covers the first block, there's no added latency either way.
+#[derive(Clone)]
+pub struct Convolution {
+    impulse_response: Arc<Vec<f32>>,
+    partition_len: usize,
+    // The spectrum of each block of the impulse response after the first, zero padded to
+    // twice the partition length
+    partitions: Arc<Vec<Vec<Complex<f32>>>>,
+    plan: Arc<RealFftPlan>,
+}
+
+impl Convolution {
+    /// Creates a convolution from the samples of an impulse response, with a partition
+    /// length of 128.
+    #[must_use]
+    pub fn new(impulse_response: impl IntoIterator<Item = f32>) -> Convolution {
+        Convolution::partitioned(Arc::new(impulse_response.into_iter().collect()), 128)
+    }
+
+    /// Loads an impulse response from a WAV file, mixing multichannel files down to mono.
+    ///
+    /// The impulse response isn't resampled, so it should have the same sample rate as the
+    /// sources it's used on.
+    pub fn from_wav(path: impl AsRef<Path>) -> Result<Convolution, Box<dyn Error>> {
+        let decoder = Decoder::new_wav(BufReader::new(File::open(path)?))?;
+        let channels = decoder.channels().max(1) as usize;
+        let samples: Vec<f32> = decoder.convert_samples().collect();
+        let mono = samples
+            .chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32);
+        Ok(Convolution::new(mono))
+    }
+
+    /// Sets the length of the directly convolved part of the impulse response, and of
+    /// each FFT convolved block after it. Shorter partitions spend more time on FFTs, and
+    /// longer ones more time on direct convolution.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `partition_len` is 0.
+    #[must_use]
+    pub fn with_partition_len(self, partition_len: usize) -> Self {
+        Convolution::partitioned(self.impulse_response, partition_len)
+    }
+
+    fn partitioned(impulse_response: Arc<Vec<f32>>, partition_len: usize) -> Convolution {
+        assert!(partition_len > 0, "partition_len must be at least 1");
+        let plan = RealFftPlan::new(2 * partition_len);
+        let partitions = impulse_response
+            .get(partition_len..)
+            .unwrap_or_default()
+            .chunks(partition_len)
+            .map(|block| {
+                let mut padded = block.to_vec();
+                padded.resize(2 * partition_len, 0.0);
+                plan.forward(&padded)
+            })
+            .collect();
+        Convolution {
+            impulse_response,
+            partition_len,
+            partitions: Arc::new(partitions),
+            plan: Arc::new(plan),
+        }
+    }
+
+    /// The samples of the impulse response.
+    #[must_use]
+    pub fn impulse_response(&self) -> &[f32] {
+        &self.impulse_response
+    }
+
+    #[must_use]
+    pub fn partition_len(&self) -> usize {
+        self.partition_len
+    }
+
+    /// Whether the impulse response is long enough to be partly convolved with FFTs.
+    #[must_use]
+    pub fn is_partitioned(&self) -> bool {
+        !self.partitions.is_empty()
+    }
+
+    /// Creates a [Source] which convolves each channel of `source` with the impulse
+    /// response.
+    pub fn source_from<S: Source<Item = f32>>(&self, source: S) -> ConvolutionSource<S> {
+        ConvolutionSource {
+            state: PerChannel::new(source.channels(), self.new_state()),
+            source,
+            convolution: self.clone(),
+            ringing: None,
+        }
+    }
+
+    // The length of the directly convolved part of the impulse response
+    fn head_len(&self) -> usize {
+        self.impulse_response.len().min(self.partition_len)
+    }
+
+    fn new_state(&self) -> ConvolutionState {
+        let spectrum_len = self.plan.spectrum_len();
+        ConvolutionState {
+            history: vec![0.0; self.head_len().max(1)],
+            position: 0,
+            input: vec![0.0; 2 * self.partition_len],
+            index: 0,
+            spectra: VecDeque::from(vec![
+                vec![Complex::ZERO; spectrum_len];
+                self.partitions.len()
+            ]),
+            tail: vec![0.0; self.partition_len],
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ConvolutionState {
+    // The last `head_len` inputs, as a ring buffer ending just before `position`
+    history: Vec<f32>,
+    position: usize,
+    // The previous input block followed by the current one, filled up to `index`
+    input: Vec<f32>,
+    index: usize,
+    // The spectrum of each of the latest input blocks, newest first
+    spectra: VecDeque<Vec<Complex<f32>>>,
+    // What the partitioned part of the impulse response adds to the current block
+    tail: Vec<f32>,
+}
+
+impl ConvolutionState {
+    fn process(&mut self, convolution: &Convolution, x: f32) -> f32 {
+        self.history[self.position] = x;
+        let len = self.history.len();
+        let mut y: f32 = convolution.impulse_response[..convolution.head_len()]
+            .iter()
+            .enumerate()
+            .map(|(m, h)| h * self.history[(self.position + len - m) % len])
+            .sum();
+        self.position = (self.position + 1) % len;
+
+        if !convolution.is_partitioned() {
+            return y;
+        }
+        let block_len = convolution.partition_len;
+        y += self.tail[self.index];
+        self.input[block_len + self.index] = x;
+        self.index += 1;
+        if self.index < block_len {
+            return y;
+        }
+
+        // Overlap-save the block just finished against each partition, for the next block.
+        // The first partition starts `block_len` samples into the impulse response, which
+        // lines up with the next block.
+        self.spectra.rotate_right(1);
+        self.spectra[0] = convolution.plan.forward(&self.input);
+        let mut sum = vec![Complex::ZERO; convolution.plan.spectrum_len()];
+        for (spectrum, partition) in self.spectra.iter().zip(convolution.partitions.iter()) {
+            for ((total, x), h) in sum.iter_mut().zip(spectrum).zip(partition) {
+                *total += *x * *h;
+            }
+        }
+        let output = convolution.plan.inverse(&sum);
+        self.tail.copy_from_slice(&output[block_len..]);
+        self.input.copy_within(block_len.., 0);
+        self.index = 0;
+        y
+    }
+}
+
+/// A [Source] which convolves each channel of its inner source with the impulse response
+/// of a [Convolution].
+///
+/// Once the inner source ends, the tail of the impulse response is played out.
+pub struct ConvolutionSource<S: Source<Item = f32>> {
+    source: S,
+    convolution: Convolution,
+    state: PerChannel<ConvolutionState>,
+    // The number of samples of the tail left to play, once the source has ended
+    ringing: Option<usize>,
+}
+
+impl<S: Source<Item = f32>> Iterator for ConvolutionSource<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let convolution = &self.convolution;
+        if self.ringing.is_none() {
+            self.state.set_channels(self.source.channels(), || convolution.new_state());
+        }
+
+        let x = match self.ringing {
+            None => match self.source.next() {
+                Some(x) => x,
+                None if self.state.at_frame_start() => {
+                    let tail_frames = convolution.impulse_response.len().saturating_sub(1);
+                    self.ringing = Some(tail_frames * self.state.channels());
+                    return self.next();
+                }
+                None => return None,
+            },
+            Some(0) => return None,
+            Some(remaining) => {
+                self.ringing = Some(remaining - 1);
+                0.0
+            }
+        };
+
+        let y = self.state.next(|state| state.process(convolution, x));
+        Some(y)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for ConvolutionSource<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        match self.ringing {
+            Some(remaining) => Some(remaining),
+            None => self.source.current_frame_len(),
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        match self.ringing {
+            Some(_) => self.state.channels() as u16,
+            None => self.source.channels(),
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        let tail_frames = self.convolution.impulse_response.len().saturating_sub(1);
+        let tail = Duration::from_secs_f64(tail_frames as f64 / self.sample_rate() as f64);
+        self.source.total_duration().map(|duration| duration + tail)
+    }
+}
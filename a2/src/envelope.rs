@@ -1,25 +1,133 @@
+use crossbeam_channel::{Receiver, Sender};
 use rodio::Source;
-use std::{f32::INFINITY, sync::Arc, time::Duration};
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+
+/// The shape of the segment between two points of an [Envelope].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Curve {
+    #[default]
+    Linear,
+    /// Starts slowly and speeds up for a positive shape, and the reverse for a negative
+    /// one. The larger the magnitude of the shape, the sharper the curve.
+    Exponential(f32),
+    /// Rises quickly and then levels off; the larger the shape, the sharper the curve.
+    Logarithmic(f32),
+    /// Holds the height of the previous point, then steps to the next one.
+    Hold,
+    /// A cubic bezier whose two control points sit at a third and two thirds of the way
+    /// through the segment, with the given (normalized) heights.
+    Bezier(f32, f32),
+}
+
+impl Curve {
+    /// Maps the progress `t` through a segment (from `0.0` to `1.0`) to how far the height
+    /// has moved from the start of the segment to its end (`0.0` to `1.0`).
+    #[must_use]
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match *self {
+            Curve::Linear => t,
+            Curve::Exponential(shape) if shape.abs() < 1e-4 => t,
+            Curve::Exponential(shape) if shape < 0.0 => (shape * t).exp_m1() / shape.exp_m1(),
+            // Scaled down by exp(shape) so that sharp curves don't overflow
+            Curve::Exponential(shape) => {
+                (shape * (t - 1.0)).exp() * (-shape * t).exp_m1() / (-shape).exp_m1()
+            }
+            Curve::Logarithmic(shape) if shape <= 1e-4 => t,
+            Curve::Logarithmic(shape) => (shape * t).ln_1p() / shape.ln_1p(),
+            Curve::Hold => {
+                if t < 1.0 {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+            Curve::Bezier(c1, c2) => {
+                let s = 1.0 - t;
+                3.0 * s * s * t * c1 + 3.0 * s * t * t * c2 + t * t * t
+            }
+        }
+    }
+}
 
 // Assumed to be sorted by x; x is the time, y is the height of the envelope (from 0 to 1)
 // The envelope cuts off after the last point, so if you want a tapered end to the
 // envelope, add a final point with sample of 0
-#[derive(Clone)]
+//
+// The segments between points are linear. For curved segments, sustain points and loops,
+// see `ShapedEnvelope`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Envelope {
     pub points: Arc<Vec<(f32, f32)>>,
 }
 
+impl From<Arc<Vec<(f32, f32)>>> for Envelope {
+    fn from(points: Arc<Vec<(f32, f32)>>) -> Self {
+        Envelope::new(points)
+    }
+}
+
 impl Envelope {
+    /// Creates an envelope with linear segments between each of the points.
+    pub fn new(points: Arc<Vec<(f32, f32)>>) -> Envelope {
+        Envelope { points }
+    }
+
+    /// Sets the shape of the segment leading up to the point at `index`.
+    #[must_use]
+    pub fn with_curve(self, index: usize, curve: Curve) -> ShapedEnvelope {
+        ShapedEnvelope::from(self).with_curve(index, curve)
+    }
+
+    /// Holds the envelope at the point at `index` until its gate is released.
+    #[must_use]
+    pub fn with_sustain(self, index: usize) -> ShapedEnvelope {
+        ShapedEnvelope::from(self).with_sustain(index)
+    }
+
+    /// Cycles the envelope between the points at `start` and `end`.
+    #[must_use]
+    pub fn with_loop(self, start: usize, end: usize) -> ShapedEnvelope {
+        ShapedEnvelope::from(self).with_loop(start, end)
+    }
+
     fn last_time(&self) -> f32 {
         self.points.last().map(|p| p.0).unwrap_or(0.0)
     }
 
-    pub fn source_from<S: Source<Item = f32>>(&self, source: S) -> EnvelopeSource<S> {
-        EnvelopeSource {
-            source,
-            envelope: self.clone(),
-            time: 0.0,
+    /// The height of the envelope at `time` seconds.
+    ///
+    /// Before the first point, the envelope rises from `(0.0, 0.0)`; after the last point,
+    /// it stays at the height of the last point. An envelope without any points has a
+    /// height of `1.0`.
+    #[must_use]
+    pub fn height_at(&self, time: f32) -> f32 {
+        self.height_with(time, |_| Curve::Linear)
+    }
+
+    // The height at `time`, with `curve(i)` the shape of the segment leading up to point `i`
+    fn height_with(&self, time: f32, curve: impl Fn(usize) -> Curve) -> f32 {
+        // The first point after `time`; when several points share a time, the envelope
+        // jumps straight to the last of them
+        let index = self.points.partition_point(|x| x.0 <= time);
+        let (t1, val1) = if index == 0 {
+            (0.0, 0.0)
+        } else {
+            self.points[index - 1]
+        };
+        let Some(&(t2, val2)) = self.points.get(index) else {
+            return if self.points.is_empty() { 1.0 } else { val1 };
+        };
+        if t2 <= t1 {
+            return val2;
         }
+        let progress = curve(index).apply((time - t1) / (t2 - t1));
+        val1 + (val2 - val1) * progress
+    }
+
+    pub fn source_from<S: Source<Item = f32>>(&self, source: S) -> EnvelopeSource<S> {
+        ShapedEnvelope::from(self.clone()).source_from(source)
     }
 
     pub fn adsr(
@@ -42,46 +150,305 @@ impl Envelope {
     }
 }
 
+/// An [Envelope] with curved segments, and optionally a sustain point and a loop.
+///
+/// `sustain` and `loop_points` are indices into the envelope's points. While the gate of
+/// the envelope is held open (see [`ShapedEnvelope::gated_source_from`]), it stops at the
+/// sustain point, and cycles between the loop points. Without a gate, the sustain point is
+/// ignored and the loop goes on forever.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ShapedEnvelope {
+    pub envelope: Envelope,
+    // `curves[i]` is the shape of the segment leading up to point `i`; segments without a
+    // curve are linear
+    #[serde(default)]
+    curves: Arc<Vec<Curve>>,
+    #[serde(default)]
+    pub sustain: Option<usize>,
+    #[serde(default)]
+    pub loop_points: Option<(usize, usize)>,
+}
+
+impl From<Envelope> for ShapedEnvelope {
+    fn from(envelope: Envelope) -> Self {
+        ShapedEnvelope {
+            envelope,
+            curves: Arc::new(Vec::new()),
+            sustain: None,
+            loop_points: None,
+        }
+    }
+}
+
+impl ShapedEnvelope {
+    /// Creates an envelope from points of the form `(time, height, curve)`, where the curve
+    /// is the shape of the segment leading up to that point.
+    pub fn from_segments(segments: impl IntoIterator<Item = (f32, f32, Curve)>) -> Self {
+        let (points, curves) = segments
+            .into_iter()
+            .map(|(time, height, curve)| ((time, height), curve))
+            .unzip();
+        ShapedEnvelope {
+            curves: Arc::new(curves),
+            ..Envelope::new(Arc::new(points)).into()
+        }
+    }
+
+    /// Sets the shape of the segment leading up to the point at `index`.
+    #[must_use]
+    pub fn with_curve(mut self, index: usize, curve: Curve) -> Self {
+        let curves = Arc::make_mut(&mut self.curves);
+        if curves.len() <= index {
+            curves.resize(index + 1, Curve::Linear);
+        }
+        curves[index] = curve;
+        self
+    }
+
+    /// The shape of the segment leading up to the point at `index`.
+    pub fn curve(&self, index: usize) -> Curve {
+        self.curves.get(index).copied().unwrap_or_default()
+    }
+
+    /// Holds the envelope at the point at `index` until its gate is released.
+    #[must_use]
+    pub fn with_sustain(mut self, index: usize) -> Self {
+        self.sustain = Some(index);
+        self
+    }
+
+    /// Cycles the envelope between the points at `start` and `end`.
+    #[must_use]
+    pub fn with_loop(mut self, start: usize, end: usize) -> Self {
+        self.loop_points = Some((start, end));
+        self
+    }
+
+    fn points(&self) -> &[(f32, f32)] {
+        &self.envelope.points
+    }
+
+    fn last_time(&self) -> f32 {
+        self.envelope.last_time()
+    }
+
+    // The time of the sustain point, if there is one
+    fn sustain_time(&self) -> Option<f32> {
+        self.sustain
+            .and_then(|i| self.points().get(i))
+            .map(|p| p.0)
+    }
+
+    // The start and end times of the loop, if there is a loop with a positive length
+    fn loop_times(&self) -> Option<(f32, f32)> {
+        let (start, end) = self.loop_points?;
+        let (start, end) = (self.points().get(start)?.0, self.points().get(end)?.0);
+        (end > start).then_some((start, end))
+    }
+
+    /// The height of the envelope at `time` seconds, as for [`Envelope::height_at`] but
+    /// following the curves of its segments.
+    #[must_use]
+    pub fn height_at(&self, time: f32) -> f32 {
+        self.envelope.height_with(time, |index| self.curve(index))
+    }
+
+    pub fn source_from<S: Source<Item = f32>>(&self, source: S) -> EnvelopeSource<S> {
+        EnvelopeSource {
+            source,
+            envelope: self.clone(),
+            start_time: 0.0,
+            frame: 0,
+            channel: 0,
+            height: 0.0,
+            gate: None,
+            open: false,
+        }
+    }
+
+    /// Applies the envelope to `source`, returning the source along with a gate which
+    /// releases it from its sustain point and loop.
+    ///
+    /// The gate starts open. A note-on after the gate has been closed restarts the envelope.
+    pub fn gated_source_from<S: Source<Item = f32>>(
+        &self,
+        source: S,
+    ) -> (EnvelopeSource<S>, EnvelopeGate) {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let source = EnvelopeSource {
+            gate: Some(receiver),
+            open: true,
+            ..self.source_from(source)
+        };
+        (source, EnvelopeGate { sender })
+    }
+
+    /// An attack-hold-decay-sustain-release envelope, which sustains until its gate is
+    /// released.
+    pub fn ahdsr(
+        attack_height: f32,
+        attack_time: f32,
+        hold_time: f32,
+        decay_time: f32,
+        sustain_height: f32,
+        release_time: f32,
+    ) -> Self {
+        ShapedEnvelope::dahdsr(
+            0.0,
+            attack_height,
+            attack_time,
+            hold_time,
+            decay_time,
+            sustain_height,
+            release_time,
+        )
+    }
+
+    /// A delay-attack-hold-decay-sustain-release envelope, which sustains until its gate is
+    /// released.
+    pub fn dahdsr(
+        delay_time: f32,
+        attack_height: f32,
+        attack_time: f32,
+        hold_time: f32,
+        decay_time: f32,
+        sustain_height: f32,
+        release_time: f32,
+    ) -> Self {
+        let attack_end = delay_time + attack_time;
+        let decay_end = attack_end + hold_time + decay_time;
+        Envelope::new(Arc::new(vec![
+            (0.0, 0.0),
+            (delay_time, 0.0),
+            (attack_end, attack_height),
+            (attack_end + hold_time, attack_height),
+            (decay_end, sustain_height),
+            (decay_end + release_time, 0.0),
+        ]))
+        .with_sustain(4)
+    }
+}
+
+/// A [Source] which applies an [Envelope] or a [ShapedEnvelope] to its inner source.
+///
+/// The envelope advances once per frame, so every channel of an interleaved frame is
+/// scaled by the same height. A one-shot envelope lasting `t` seconds plays exactly
+/// `round(t * sample_rate)` frames; an envelope without any points lets the whole of its
+/// inner source through.
 #[derive(Clone)]
 pub struct EnvelopeSource<S: Source<Item = f32>> {
-    envelope: Envelope,
+    envelope: ShapedEnvelope,
     source: S,
-    time: f64,
+    // The current time is `start_time + frame / sample_rate`. Counting frames rather than
+    // summing up frame lengths keeps rounding errors from building up.
+    start_time: f64,
+    frame: u64,
+    // The channel of the next sample, and the envelope height of the current frame
+    channel: u16,
+    height: f32,
+    gate: Option<Receiver<GateEvent>>,
+    open: bool,
+}
+
+impl<S: Source<Item = f32>> EnvelopeSource<S> {
+    /// The current position of the envelope, in seconds.
+    pub fn time(&self) -> f64 {
+        self.start_time + self.frame as f64 / self.source.sample_rate() as f64
+    }
+
+    fn set_time(&mut self, time: f64) {
+        self.start_time = time;
+        self.frame = 0;
+    }
+
+    // Whether the envelope is currently cycling through its loop
+    fn is_looping(&self) -> bool {
+        (self.gate.is_none() || self.open) && self.envelope.loop_times().is_some()
+    }
+
+    // Whether the envelope can't tell how long it will last
+    fn is_open_ended(&self) -> bool {
+        self.envelope.points().is_empty()
+            || self.is_looping()
+            || (self.open && self.envelope.sustain_time().is_some())
+    }
+
+    // The number of frames left before the envelope ends
+    fn remaining_frames(&self) -> u64 {
+        let sample_rate = self.source.sample_rate() as f64;
+        let remaining = (self.envelope.last_time() as f64 - self.time()) * sample_rate;
+        remaining.round().max(0.0) as u64
+    }
+
+    // Stops the time at the sustain point while the gate is open
+    fn hold_at_sustain(&mut self) {
+        if let (true, Some(sustain_time)) = (self.open, self.envelope.sustain_time()) {
+            if self.time() > sustain_time as f64 {
+                self.set_time(sustain_time as f64);
+            }
+        }
+    }
+
+    // Handles gate events, and wraps the time around sustain points and loops
+    fn update_time(&mut self) {
+        while let Some(Ok(event)) = self.gate.as_ref().map(Receiver::try_recv) {
+            match event {
+                GateEvent::NoteOn => {
+                    if !self.open {
+                        self.set_time(0.0);
+                    }
+                    self.open = true;
+                }
+                GateEvent::NoteOff => self.open = false,
+            }
+        }
+
+        self.hold_at_sustain();
+        if let (true, Some((start, end))) = (self.is_looping(), self.envelope.loop_times()) {
+            let time = self.time();
+            if time >= end as f64 {
+                self.set_time(start as f64 + (time - start as f64) % (end - start) as f64);
+            }
+        }
+    }
 }
 
 impl<S: Source<Item = f32>> Iterator for EnvelopeSource<S> {
     type Item = S::Item;
 
     fn next(&mut self) -> Option<f32> {
-        let time_f32 = self.time as f32;
-        if time_f32 > self.envelope.last_time() {
-            return None;
+        if self.channel == 0 {
+            self.update_time();
+            if !self.is_open_ended() && self.remaining_frames() == 0 {
+                return None;
+            }
+            self.height = self.envelope.height_at(self.time() as f32);
         }
         let Some(sample) = self.source.next() else { return None };
 
-        let index = self.envelope.points.partition_point(|x| x.0 < time_f32);
-        let (t1, val1) = if index == 0 {
-            (0.0, 0.0)
-        } else {
-            self.envelope.points[index - 1]
-        };
-        let (t2, val2) = self.envelope.points[index];
-        let lerp_param = (time_f32 - t1) / (t2 - t1);
-        let envelope_height = val2 * lerp_param + val1 * (1.0 - lerp_param);
-
-        self.time += 1.0 / self.source.sample_rate() as f64;
+        self.channel += 1;
+        if self.channel >= self.source.channels() {
+            self.channel = 0;
+            self.frame += 1;
+            // So that a release starts right from the sustain point
+            self.hold_at_sustain();
+        }
 
-        Some(sample * envelope_height)
+        Some(sample * self.height)
     }
 }
 
 impl<S: Source<Item = f32>> Source for EnvelopeSource<S> {
     fn current_frame_len(&self) -> Option<usize> {
-        let len_1 = self.source.current_frame_len().unwrap_or(std::usize::MAX);
-        let len_2 = ((self.envelope.last_time() as f64 - self.time).max(0.0)
-            / self.source.sample_rate() as f64)
-            .ceil() as usize;
-        Some(len_2.min(len_1))
+        let source_len = self.source.current_frame_len();
+        if self.is_open_ended() {
+            return source_len;
+        }
+        // The current frame has already been partially played if `channel` isn't 0
+        let channels = self.source.channels() as usize;
+        let len =
+            (self.remaining_frames() as usize * channels).saturating_sub(self.channel as usize);
+        Some(source_len.map_or(len, |source_len| source_len.min(len)))
     }
 
     fn channels(&self) -> u16 {
@@ -93,18 +460,248 @@ impl<S: Source<Item = f32>> Source for EnvelopeSource<S> {
     }
 
     fn total_duration(&self) -> Option<Duration> {
-        let time1 = self
-            .source
-            .total_duration()
-            .map(|dur| dur.as_secs_f32())
-            .unwrap_or(INFINITY);
-        let time2 = self.envelope.points.last().map(|p| p.0).unwrap_or(INFINITY);
-        let min_time = time1.min(time2);
-        if min_time == INFINITY {
-            None
-        }
-        else {
-            Some(Duration::from_secs_f32(min_time))
+        if self.is_open_ended() {
+            return self.source.total_duration();
+        }
+        let sample_rate = self.source.sample_rate() as f64;
+        let frames = (self.envelope.last_time() as f64 * sample_rate)
+            .round()
+            .max(0.0);
+        let duration = Duration::from_secs_f64(frames / sample_rate);
+        Some(match self.source.total_duration() {
+            Some(source_duration) => source_duration.min(duration),
+            None => duration,
+        })
+    }
+}
+
+/// How a [GatedAdsr] reacts to a note-on while notes are already held.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TriggerMode {
+    /// Every note-on restarts the attack, starting from the current level.
+    #[default]
+    Retrigger,
+    /// A note-on only restarts the attack when no other notes are held.
+    Legato,
+}
+
+/// An ADSR envelope whose sustain lasts for as long as its gate is held open.
+///
+/// Unlike [Envelope::adsr], there is no fixed sustain time; the envelope is driven by
+/// note-on/note-off events sent through an [EnvelopeGate].
+#[derive(Clone, Copy, Debug)]
+pub struct GatedAdsr {
+    pub attack_height: f32,
+    pub attack_time: f32,
+    pub decay_time: f32,
+    pub sustain_height: f32,
+    pub release_time: f32,
+    pub mode: TriggerMode,
+}
+
+impl GatedAdsr {
+    pub fn new(
+        attack_height: f32,
+        attack_time: f32,
+        decay_time: f32,
+        sustain_height: f32,
+        release_time: f32,
+    ) -> GatedAdsr {
+        GatedAdsr {
+            attack_height,
+            attack_time,
+            decay_time,
+            sustain_height,
+            release_time,
+            mode: TriggerMode::default(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_mode(mut self, mode: TriggerMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Applies the envelope to `source`, returning the source along with the gate that
+    /// controls it.
+    ///
+    /// The envelope starts closed, so the source is silent until the first note-on.
+    pub fn source_from<S: Source<Item = f32>>(
+        &self,
+        source: S,
+    ) -> (GatedEnvelopeSource<S>, EnvelopeGate) {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let source = GatedEnvelopeSource {
+            source,
+            adsr: *self,
+            receiver,
+            stage: AdsrStage::Idle,
+            level: 0.0,
+            release_rate: 0.0,
+            held: 0,
+            channel: 0,
+        };
+        (source, EnvelopeGate { sender })
+    }
+}
+
+/// An event which opens or closes the gate of a [GatedEnvelopeSource].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GateEvent {
+    NoteOn,
+    NoteOff,
+}
+
+/// A handle for opening and closing the gate of a [GatedEnvelopeSource].
+///
+/// Events sent after the source has been dropped are ignored.
+#[derive(Clone)]
+pub struct EnvelopeGate {
+    sender: Sender<GateEvent>,
+}
+
+impl EnvelopeGate {
+    pub fn note_on(&self) {
+        let _ = self.sender.send(GateEvent::NoteOn);
+    }
+
+    pub fn note_off(&self) {
+        let _ = self.sender.send(GateEvent::NoteOff);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AdsrStage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// A [Source] which applies a [GatedAdsr] to its inner source.
+///
+/// Gate events are picked up at the start of each frame. The source doesn't end when the
+/// release does, so that the gate can be opened again; it only ends with its inner source.
+pub struct GatedEnvelopeSource<S: Source<Item = f32>> {
+    source: S,
+    adsr: GatedAdsr,
+    receiver: Receiver<GateEvent>,
+    stage: AdsrStage,
+    level: f32,
+    // How much the level falls per second, fixed when the release starts
+    release_rate: f32,
+    // The number of notes currently holding the gate open
+    held: u32,
+    channel: u16,
+}
+
+impl<S: Source<Item = f32>> GatedEnvelopeSource<S> {
+    /// Whether the gate is currently held open.
+    pub fn is_open(&self) -> bool {
+        self.held > 0
+    }
+
+    /// The current height of the envelope.
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    fn handle_event(&mut self, event: GateEvent) {
+        match event {
+            GateEvent::NoteOn => {
+                let was_open = self.is_open();
+                self.held += 1;
+                if !was_open || self.adsr.mode == TriggerMode::Retrigger {
+                    self.stage = AdsrStage::Attack;
+                }
+            }
+            GateEvent::NoteOff => {
+                self.held = self.held.saturating_sub(1);
+                if !self.is_open() && self.stage != AdsrStage::Idle {
+                    self.stage = AdsrStage::Release;
+                    self.release_rate = self.level / self.adsr.release_time;
+                }
+            }
+        }
+    }
+
+    // Moves the envelope forward by `dt` seconds.
+    fn advance(&mut self, dt: f32) {
+        let adsr = self.adsr;
+        match self.stage {
+            AdsrStage::Idle | AdsrStage::Sustain => {}
+            AdsrStage::Attack => {
+                let rate = adsr.attack_height / adsr.attack_time;
+                if approach(&mut self.level, adsr.attack_height, rate * dt) {
+                    self.stage = AdsrStage::Decay;
+                }
+            }
+            AdsrStage::Decay => {
+                let rate = (adsr.attack_height - adsr.sustain_height).abs() / adsr.decay_time;
+                if approach(&mut self.level, adsr.sustain_height, rate * dt) {
+                    self.stage = AdsrStage::Sustain;
+                }
+            }
+            AdsrStage::Release => {
+                if approach(&mut self.level, 0.0, self.release_rate * dt) {
+                    self.stage = AdsrStage::Idle;
+                }
+            }
+        }
+    }
+}
+
+// Moves `level` towards `target` by at most `step`, returning whether it got there.
+//
+// A non-finite step (from a stage with a time of 0) jumps straight to the target.
+fn approach(level: &mut f32, target: f32, step: f32) -> bool {
+    if !step.is_finite() || (target - *level).abs() <= step {
+        *level = target;
+        true
+    } else {
+        *level += step.copysign(target - *level);
+        false
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for GatedEnvelopeSource<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.channel == 0 {
+            while let Ok(event) = self.receiver.try_recv() {
+                self.handle_event(event);
+            }
+        }
+        let Some(sample) = self.source.next() else { return None };
+        let result = sample * self.level;
+
+        self.channel += 1;
+        if self.channel >= self.source.channels() {
+            self.channel = 0;
+            self.advance(1.0 / self.source.sample_rate() as f32);
         }
+
+        Some(result)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for GatedEnvelopeSource<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
     }
 }
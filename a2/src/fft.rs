@@ -0,0 +1,351 @@
+use crate::complex::{Complex, Float};
+use rodio::Source;
+use std::f64::consts::TAU;
+
+/// A precomputed plan for taking fast Fourier transforms of a fixed length.
+///
+/// Any length is supported. Lengths made up of small prime factors (especially powers of
+/// two) are fastest, while large prime factors fall back to a plain DFT over that factor.
+#[derive(Clone, Debug)]
+pub struct FftPlan<T: Float = f32> {
+    len: usize,
+    // Each stage splits a transform of length `p * m` into `p` transforms of length `m`
+    factors: Vec<(usize, usize)>,
+    // `twiddles[k]` is `e^(-2 pi i k / len)`
+    twiddles: Vec<Complex<T>>,
+}
+
+impl<T: Float> FftPlan<T> {
+    /// Creates a plan for transforms of `len` points.
+    #[must_use]
+    pub fn new(len: usize) -> Self {
+        let twiddles = (0..len)
+            .map(|i| {
+                let (sin, cos) = (-TAU * i as f64 / len as f64).sin_cos();
+                Complex::new(T::from_f64(cos), T::from_f64(sin))
+            })
+            .collect();
+        FftPlan {
+            len,
+            factors: factorize(len),
+            twiddles,
+        }
+    }
+
+    /// The number of points transformed by this plan.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Replaces `data` with its discrete Fourier transform.
+    ///
+    /// The transform is unnormalized, so a sine wave of amplitude `a` which completes a
+    /// whole number of cycles over `data` shows up with a magnitude of `a * len / 2`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` doesn't have the length of the plan.
+    pub fn forward(&self, data: &mut [Complex<T>]) {
+        assert_eq!(
+            data.len(),
+            self.len,
+            "data doesn't match the length of the plan"
+        );
+        if self.len <= 1 {
+            return;
+        }
+        let input = data.to_vec();
+        let mut scratch = Vec::new();
+        self.work(data, &input, 1, 1, &self.factors, &mut scratch);
+    }
+
+    /// Replaces `data` with its inverse discrete Fourier transform.
+    ///
+    /// The inverse is normalized by `1 / len`, so that it undoes [FftPlan::forward].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` doesn't have the length of the plan.
+    pub fn inverse(&self, data: &mut [Complex<T>]) {
+        // ifft(x) = conj(fft(conj(x))) / n
+        data.iter_mut().for_each(|z| *z = z.conj());
+        self.forward(data);
+        let scale = T::ONE / T::from_f64(self.len as f64);
+        data.iter_mut().for_each(|z| *z = z.conj() * scale);
+    }
+
+    // Recursive decimation in time; `out` gets the transform of every `stride`th element
+    // of `input`, where `fstride` is how far apart this sub-transform's twiddles are.
+    fn work(
+        &self,
+        out: &mut [Complex<T>],
+        input: &[Complex<T>],
+        stride: usize,
+        fstride: usize,
+        factors: &[(usize, usize)],
+        scratch: &mut Vec<Complex<T>>,
+    ) {
+        let (p, m) = factors[0];
+        if m == 1 {
+            for (q, z) in out.iter_mut().enumerate().take(p) {
+                *z = input[q * stride];
+            }
+        } else {
+            for q in 0..p {
+                self.work(
+                    &mut out[q * m..(q + 1) * m],
+                    &input[q * stride..],
+                    stride * p,
+                    fstride * p,
+                    &factors[1..],
+                    scratch,
+                );
+            }
+        }
+
+        match p {
+            2 => self.butterfly_2(out, fstride, m),
+            _ => self.butterfly_generic(out, fstride, p, m, scratch),
+        }
+    }
+
+    fn butterfly_2(&self, out: &mut [Complex<T>], fstride: usize, m: usize) {
+        let (low, high) = out.split_at_mut(m);
+        for (j, (a, b)) in low.iter_mut().zip(high.iter_mut()).enumerate() {
+            let t = *b * self.twiddles[j * fstride];
+            *b = *a - t;
+            *a += t;
+        }
+    }
+
+    fn butterfly_generic(
+        &self,
+        out: &mut [Complex<T>],
+        fstride: usize,
+        p: usize,
+        m: usize,
+        scratch: &mut Vec<Complex<T>>,
+    ) {
+        scratch.resize(p, Complex::ZERO);
+        for u in 0..m {
+            for q in 0..p {
+                scratch[q] = out[q * m + u];
+            }
+            for q1 in 0..p {
+                let k = q1 * m + u;
+                let mut twiddle_index = 0;
+                let mut sum = scratch[0];
+                for z in scratch.iter().skip(1) {
+                    twiddle_index = (twiddle_index + fstride * k) % self.len;
+                    sum += *z * self.twiddles[twiddle_index];
+                }
+                out[k] = sum;
+            }
+        }
+    }
+}
+
+// Splits `n` into its prime factors, twos first
+fn factorize(mut n: usize) -> Vec<(usize, usize)> {
+    let mut factors = Vec::new();
+    let mut p = 2;
+    while n > 1 {
+        while n % p != 0 {
+            p = if p * p > n { n } else { p + 1 };
+        }
+        n /= p;
+        factors.push((p, n));
+    }
+    factors
+}
+
+/// A precomputed plan for Fourier transforms of real signals of a fixed length.
+///
+/// A real signal of length `len` has a spectrum which is symmetric around its middle, so
+/// only the first `len / 2 + 1` bins are computed. Even lengths are transformed as a
+/// complex signal of half the length.
+#[derive(Clone, Debug)]
+pub struct RealFftPlan<T: Float = f32> {
+    len: usize,
+    inner: FftPlan<T>,
+    // `e^(-2 pi i k / len)` for each bin, for even lengths
+    twiddles: Vec<Complex<T>>,
+}
+
+impl<T: Float> RealFftPlan<T> {
+    /// Creates a plan for transforms of `len` real points.
+    #[must_use]
+    pub fn new(len: usize) -> Self {
+        if len % 2 == 1 {
+            return RealFftPlan {
+                len,
+                inner: FftPlan::new(len),
+                twiddles: Vec::new(),
+            };
+        }
+        let twiddles = (0..=len / 2)
+            .map(|k| {
+                let (sin, cos) = (-TAU * k as f64 / len as f64).sin_cos();
+                Complex::new(T::from_f64(cos), T::from_f64(sin))
+            })
+            .collect();
+        RealFftPlan {
+            len,
+            inner: FftPlan::new(len / 2),
+            twiddles,
+        }
+    }
+
+    /// The number of real points transformed by this plan.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of bins in the spectrum, `len / 2 + 1`.
+    #[must_use]
+    pub fn spectrum_len(&self) -> usize {
+        self.len / 2 + 1
+    }
+
+    /// Gives the first `len / 2 + 1` bins of the discrete Fourier transform of `input`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `input` doesn't have the length of the plan.
+    #[must_use]
+    pub fn forward(&self, input: &[T]) -> Vec<Complex<T>> {
+        assert_eq!(
+            input.len(),
+            self.len,
+            "input doesn't match the length of the plan"
+        );
+        if self.len % 2 == 1 {
+            let mut data: Vec<_> = input.iter().map(|x| Complex::from(*x)).collect();
+            self.inner.forward(&mut data);
+            data.truncate(self.spectrum_len());
+            return data;
+        }
+        if self.len == 0 {
+            return vec![Complex::ZERO];
+        }
+
+        // Pack even samples into the real part and odd samples into the imaginary part
+        let half = self.len / 2;
+        let mut z: Vec<_> = input
+            .chunks_exact(2)
+            .map(|pair| Complex::new(pair[0], pair[1]))
+            .collect();
+        self.inner.forward(&mut z);
+
+        let two = T::ONE + T::ONE;
+        (0..=half)
+            .map(|k| {
+                let a = z[k % half];
+                let b = z[(half - k) % half].conj();
+                let even = (a + b) / two;
+                let odd = (a - b) * Complex::new(T::ZERO, -T::ONE / two);
+                even + self.twiddles[k] * odd
+            })
+            .collect()
+    }
+
+    /// Gives the real signal whose spectrum starts with `spectrum`, undoing
+    /// [RealFftPlan::forward].
+    ///
+    /// The imaginary parts of the first bin (and the last bin, for even lengths) are
+    /// ignored, since they're always zero for a real signal.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `spectrum` doesn't have `len / 2 + 1` bins.
+    #[must_use]
+    pub fn inverse(&self, spectrum: &[Complex<T>]) -> Vec<T> {
+        assert_eq!(
+            spectrum.len(),
+            self.spectrum_len(),
+            "spectrum doesn't match the length of the plan"
+        );
+        if self.len % 2 == 1 {
+            // Rebuild the upper half of the spectrum from its symmetry
+            let mut data: Vec<_> = (0..self.len)
+                .map(|k| match k {
+                    0 => Complex::from(spectrum[0].real),
+                    k if k < spectrum.len() => spectrum[k],
+                    k => spectrum[self.len - k].conj(),
+                })
+                .collect();
+            self.inner.inverse(&mut data);
+            return data.into_iter().map(|z| z.real).collect();
+        }
+        if self.len == 0 {
+            return Vec::new();
+        }
+
+        let half = self.len / 2;
+        let mut spectrum = spectrum.to_vec();
+        spectrum[0].im = T::ZERO;
+        spectrum[half].im = T::ZERO;
+
+        let two = T::ONE + T::ONE;
+        let mut z: Vec<_> = (0..half)
+            .map(|k| {
+                let a = spectrum[k];
+                let b = spectrum[half - k].conj();
+                let even = (a + b) / two;
+                let odd = (a - b) / two * self.twiddles[k].conj();
+                even + Complex::I * odd
+            })
+            .collect();
+        self.inner.inverse(&mut z);
+        z.into_iter().flat_map(|z| [z.real, z.im]).collect()
+    }
+}
+
+/// Replaces `data` with its discrete Fourier transform.
+///
+/// Creates a new [FftPlan] on every call; reuse a plan when taking many transforms of the
+/// same length.
+pub fn fft<T: Float>(data: &mut [Complex<T>]) {
+    FftPlan::new(data.len()).forward(data);
+}
+
+/// Replaces `data` with its (normalized) inverse discrete Fourier transform.
+///
+/// Creates a new [FftPlan] on every call; reuse a plan when taking many transforms of the
+/// same length.
+pub fn ifft<T: Float>(data: &mut [Complex<T>]) {
+    FftPlan::new(data.len()).inverse(data);
+}
+
+/// The frequency in Hz at the center of `bin`, for a transform of `len` points.
+#[must_use]
+pub fn bin_frequency(bin: usize, len: usize, sample_rate: u32) -> f32 {
+    bin as f32 * sample_rate as f32 / len as f32
+}
+
+/// An extension trait for taking the spectrum of a [Source].
+pub trait SpectrumSourceExt: Source<Item = f32> + Sized {
+    /// Collects the next `len` samples of the source, and gives the first `len / 2 + 1`
+    /// bins of their spectrum. If the source ends early, the rest is padded with zeroes.
+    ///
+    /// Multichannel sources are taken as they are, without separating their channels.
+    fn spectrum(self, len: usize) -> Vec<Complex<f32>> {
+        let mut samples: Vec<f32> = self.take(len).collect();
+        samples.resize(len, 0.0);
+        RealFftPlan::new(len).forward(&samples)
+    }
+}
+
+impl<S: Source<Item = f32> + Sized> SpectrumSourceExt for S {}
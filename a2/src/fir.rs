@@ -0,0 +1,495 @@
+use crate::complex::Complex;
+use crate::per_channel::PerChannel;
+use crate::window::Window;
+use rodio::Source;
+use std::f64::consts::{PI, TAU};
+use std::sync::Arc;
+
+/// A finite impulse response filter, defined by its taps.
+///
+/// The design functions all give linear phase filters, which delay every frequency by the
+/// same [delay](Fir::delay) of half the filter length.
+#[derive(Clone, Debug)]
+pub struct Fir {
+    taps: Arc<Vec<f32>>,
+}
+
+impl Fir {
+    /// Creates a filter from its taps, the first of which is applied to the newest sample.
+    #[must_use]
+    pub fn new(taps: impl IntoIterator<Item = f32>) -> Fir {
+        Fir {
+            taps: Arc::new(taps.into_iter().collect()),
+        }
+    }
+
+    /// Designs a lowpass filter of `len` taps with a windowed sinc, passing frequencies
+    /// below `cutoff` Hz.
+    ///
+    /// Longer filters have a sharper transition around the cutoff, and windows with lower
+    /// sidelobes (like [Window::BlackmanHarris]) let less through above it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is 0.
+    #[must_use]
+    pub fn lowpass(len: usize, cutoff: f32, sample_rate: u32, window: Window) -> Fir {
+        Fir::new(windowed_sinc(
+            len,
+            cutoff as f64 / sample_rate as f64,
+            window,
+        ))
+    }
+
+    /// Designs a highpass filter of `len` taps, passing frequencies above `cutoff` Hz.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is even, since even length linear phase filters can't pass the
+    /// Nyquist frequency.
+    #[must_use]
+    pub fn highpass(len: usize, cutoff: f32, sample_rate: u32, window: Window) -> Fir {
+        assert!(len % 2 == 1, "highpass filters must have an odd length");
+        let mut taps = windowed_sinc(len, cutoff as f64 / sample_rate as f64, window);
+        spectral_inversion(&mut taps);
+        Fir::new(taps)
+    }
+
+    /// Designs a bandpass filter of `len` taps, passing frequencies between `low` and
+    /// `high` Hz.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is 0.
+    #[must_use]
+    pub fn bandpass(len: usize, low: f32, high: f32, sample_rate: u32, window: Window) -> Fir {
+        let sample_rate = sample_rate as f64;
+        let upper = windowed_sinc(len, high as f64 / sample_rate, window);
+        let lower = windowed_sinc(len, low as f64 / sample_rate, window);
+        Fir::new(upper.iter().zip(lower).map(|(a, b)| a - b))
+    }
+
+    /// Designs a bandstop filter of `len` taps, blocking frequencies between `low` and
+    /// `high` Hz.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is even, since even length linear phase filters can't pass the
+    /// Nyquist frequency.
+    #[must_use]
+    pub fn bandstop(len: usize, low: f32, high: f32, sample_rate: u32, window: Window) -> Fir {
+        assert!(len % 2 == 1, "bandstop filters must have an odd length");
+        let mut taps = Fir::bandpass(len, low, high, sample_rate, window)
+            .taps
+            .to_vec();
+        spectral_inversion(&mut taps);
+        Fir::new(taps)
+    }
+
+    /// Designs a Hilbert transformer of `len` taps, which shifts the phase of every
+    /// frequency by a quarter of a cycle (turning cosines into sines), apart from near `0`
+    /// and the Nyquist frequency.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is even.
+    #[must_use]
+    pub fn hilbert(len: usize, window: Window) -> Fir {
+        assert!(len % 2 == 1, "Hilbert transformers must have an odd length");
+        let middle = (len / 2) as isize;
+        let window = window.symmetric(len);
+        Fir::new(window.iter().enumerate().map(|(n, w)| {
+            let k = n as isize - middle;
+            if k % 2 == 0 {
+                0.0
+            } else {
+                (2.0 / (PI * k as f64)) as f32 * w
+            }
+        }))
+    }
+
+    /// Designs an equiripple filter of `len` taps with the Parks-McClellan algorithm,
+    /// which spreads the error evenly over the bands, weighted by each band's weight.
+    ///
+    /// Frequencies between the bands are left unconstrained, as transition bands. Filters
+    /// with an even length always have a gain of zero at the Nyquist frequency.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no bands, if any band is outside of `0` to `sample_rate / 2`
+    /// Hz or overlaps the one before it, or if an even length filter is asked for a gain
+    /// at the Nyquist frequency.
+    #[must_use]
+    pub fn parks_mcclellan(len: usize, bands: &[Band], sample_rate: u32) -> Fir {
+        Fir::new(remez(len, bands, sample_rate as f64))
+    }
+
+    /// The taps of the filter.
+    #[must_use]
+    pub fn taps(&self) -> &[f32] {
+        &self.taps
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.taps.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.taps.is_empty()
+    }
+
+    /// How many samples a linear phase filter delays its input by.
+    #[must_use]
+    pub fn delay(&self) -> f32 {
+        self.taps.len().saturating_sub(1) as f32 / 2.0
+    }
+
+    /// The gain and phase shift of the filter at `frequency` Hz.
+    #[must_use]
+    pub fn frequency_response(&self, frequency: f32, sample_rate: u32) -> Complex<f32> {
+        let omega = TAU * frequency as f64 / sample_rate as f64;
+        let response: Complex<f64> = self
+            .taps
+            .iter()
+            .enumerate()
+            .map(|(n, h)| Complex::cis(-omega * n as f64) * *h as f64)
+            .sum();
+        Complex::new(response.real as f32, response.im as f32)
+    }
+
+    /// Creates a [Source] which runs the filter over each channel of `source`.
+    pub fn source_from<S: Source<Item = f32>>(&self, source: S) -> FirSource<S> {
+        FirSource {
+            state: PerChannel::new(source.channels(), self.new_state()),
+            source,
+            fir: self.clone(),
+        }
+    }
+
+    fn new_state(&self) -> FirState {
+        FirState {
+            history: vec![0.0; 2 * self.taps.len().max(1)],
+            position: 0,
+        }
+    }
+}
+
+/// A frequency band for [Fir::parks_mcclellan], from `start` to `end` Hz.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Band {
+    pub start: f32,
+    pub end: f32,
+    /// The gain wanted over the band.
+    pub gain: f32,
+    /// How much the error over this band counts compared to the others.
+    pub weight: f32,
+}
+
+impl Band {
+    /// Creates a band with a weight of `1.0`.
+    #[must_use]
+    pub fn new(start: f32, end: f32, gain: f32) -> Band {
+        Band {
+            start,
+            end,
+            gain,
+            weight: 1.0,
+        }
+    }
+
+    #[must_use]
+    pub fn with_weight(mut self, weight: f32) -> Self {
+        self.weight = weight;
+        self
+    }
+}
+
+// A lowpass windowed sinc with a cutoff in cycles per sample, normalized to unity gain at 0
+fn windowed_sinc(len: usize, cutoff: f64, window: Window) -> Vec<f32> {
+    assert!(len > 0, "filters must have at least one tap");
+    let middle = (len - 1) as f64 / 2.0;
+    let window = window.symmetric(len);
+    let taps: Vec<f64> = window
+        .iter()
+        .enumerate()
+        .map(|(n, w)| {
+            let x = n as f64 - middle;
+            let sinc = if x == 0.0 {
+                2.0 * cutoff
+            } else {
+                (TAU * cutoff * x).sin() / (PI * x)
+            };
+            sinc * *w as f64
+        })
+        .collect();
+    let sum: f64 = taps.iter().sum();
+    let scale = if sum.abs() > f64::EPSILON {
+        1.0 / sum
+    } else {
+        1.0
+    };
+    taps.iter().map(|h| (h * scale) as f32).collect()
+}
+
+// Turns an odd length lowpass into a highpass, by subtracting it from an impulse
+fn spectral_inversion(taps: &mut [f32]) {
+    taps.iter_mut().for_each(|h| *h = -*h);
+    taps[taps.len() / 2] += 1.0;
+}
+
+// Parks-McClellan design of a symmetric filter. The amplitude response is written as
+// `Q(w) P(w)`, where `P` is a sum of cosines (a polynomial in `cos(w)`), and `Q` is `1` for odd
+// lengths and `cos(w / 2)` for even ones.
+fn remez(len: usize, bands: &[Band], sample_rate: f64) -> Vec<f32> {
+    const GRID_DENSITY: usize = 16;
+    const MAX_ITERATIONS: usize = 40;
+
+    assert!(len > 0, "filters must have at least one tap");
+    assert!(!bands.is_empty(), "filters need at least one band");
+    let even = len % 2 == 0;
+    // The number of cosines in `P`, and of extremal frequencies in the error
+    let cosines = if even { len / 2 } else { len / 2 + 1 };
+    let extremal_count = cosines + 1;
+
+    // A dense grid of (frequency in radians per sample, desired response, weight) over the
+    // bands, with `Q` divided out
+    let spacing = 0.5 / (GRID_DENSITY * cosines) as f64;
+    let mut grid: Vec<(f64, f64, f64)> = Vec::new();
+    let mut band_edges = Vec::new();
+    let mut previous_end = 0.0;
+    for band in bands {
+        let (start, mut end) = (
+            band.start as f64 / sample_rate,
+            band.end as f64 / sample_rate,
+        );
+        assert!(
+            start >= previous_end && start <= end && end <= 0.5,
+            "bands must be in order, between 0 Hz and the Nyquist frequency"
+        );
+        previous_end = end;
+        if even && end > 0.5 - spacing {
+            assert!(
+                band.gain == 0.0,
+                "even length filters can't have a gain at the Nyquist frequency"
+            );
+            end = 0.5 - spacing;
+        }
+        let points = (((end - start) / spacing).ceil() as usize).max(1);
+        band_edges.push(grid.len());
+        for i in 0..=points {
+            let omega = TAU * (start + (end - start) * i as f64 / points as f64);
+            let q = if even { (omega / 2.0).cos() } else { 1.0 };
+            grid.push((omega, band.gain as f64 / q, band.weight as f64 * q));
+        }
+    }
+    let is_band_end = |j: usize| j + 1 == grid.len() || band_edges.contains(&(j + 1));
+    assert!(
+        grid.len() >= extremal_count,
+        "bands are too narrow for the filter length"
+    );
+
+    let mut extremals: Vec<usize> = (0..extremal_count)
+        .map(|i| i * (grid.len() - 1) / (extremal_count - 1))
+        .collect();
+    let mut interpolation = Interpolation::new(&grid, &extremals);
+    for _ in 0..MAX_ITERATIONS {
+        let errors: Vec<f64> = grid
+            .iter()
+            .map(|(omega, desired, weight)| {
+                weight * (desired - interpolation.evaluate(omega.cos()))
+            })
+            .collect();
+
+        // Every local extremum of the error, with neighbours of the same sign merged
+        let mut candidates: Vec<usize> = Vec::new();
+        for j in 0..grid.len() {
+            // Compared with the sign of this point, so that a neighbour of the opposite sign
+            // never hides it
+            let sign = errors[j].signum();
+            let error = errors[j].abs();
+            let rising = band_edges.contains(&j) || error >= sign * errors[j - 1];
+            let falling = is_band_end(j) || error >= sign * errors[j + 1];
+            if !(rising && falling) {
+                continue;
+            }
+            match candidates.last_mut() {
+                Some(last) if errors[*last].signum() == errors[j].signum() => {
+                    if error > errors[*last].abs() {
+                        *last = j;
+                    }
+                }
+                _ => candidates.push(j),
+            }
+        }
+        // Drop the smaller end until there are as many as needed
+        while candidates.len() > extremal_count {
+            if errors[candidates[0]].abs() < errors[*candidates.last().unwrap()].abs() {
+                candidates.remove(0);
+            } else {
+                candidates.pop();
+            }
+        }
+        if candidates.len() < extremal_count || candidates == extremals {
+            break;
+        }
+
+        let max_error = candidates
+            .iter()
+            .fold(0.0f64, |max, j| max.max(errors[*j].abs()));
+        extremals = candidates;
+        interpolation = Interpolation::new(&grid, &extremals);
+        if max_error - interpolation.deviation.abs() < 1e-6 * max_error {
+            break;
+        }
+    }
+
+    // Sample the amplitude response all the way round, and take the inverse DFT
+    let middle = (len - 1) as f64 / 2.0;
+    let amplitudes: Vec<f64> = (0..len)
+        .map(|k| {
+            let omega = TAU * k as f64 / len as f64;
+            let q = if even { (omega / 2.0).cos() } else { 1.0 };
+            q * interpolation.evaluate(omega.cos())
+        })
+        .collect();
+    (0..len)
+        .map(|n| {
+            let sum: f64 = amplitudes
+                .iter()
+                .enumerate()
+                .map(|(k, a)| a * (TAU * k as f64 / len as f64 * (n as f64 - middle)).cos())
+                .sum();
+            (sum / len as f64) as f32
+        })
+        .collect()
+}
+
+// The polynomial in `cos(w)` which alternates around the desired response by the
+// deviation at each extremal frequency, in barycentric form
+struct Interpolation {
+    points: Vec<f64>,
+    weights: Vec<f64>,
+    values: Vec<f64>,
+    deviation: f64,
+}
+
+impl Interpolation {
+    fn new(grid: &[(f64, f64, f64)], extremals: &[usize]) -> Interpolation {
+        let points: Vec<f64> = extremals.iter().map(|j| grid[*j].0.cos()).collect();
+        let weights: Vec<f64> = points
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                let product: f64 = points
+                    .iter()
+                    .enumerate()
+                    .filter(|(k, _)| *k != i)
+                    .map(|(_, y)| 2.0 * (x - y))
+                    .product();
+                1.0 / product
+            })
+            .collect();
+
+        let sign = |i: usize| if i % 2 == 0 { 1.0 } else { -1.0 };
+        let (mut numerator, mut denominator) = (0.0, 0.0);
+        for (i, j) in extremals.iter().enumerate() {
+            let (_, desired, weight) = grid[*j];
+            numerator += weights[i] * desired;
+            denominator += weights[i] * sign(i) / weight;
+        }
+        let deviation = numerator / denominator;
+        let values = extremals
+            .iter()
+            .enumerate()
+            .map(|(i, j)| {
+                let (_, desired, weight) = grid[*j];
+                desired - sign(i) * deviation / weight
+            })
+            .collect();
+
+        Interpolation {
+            points,
+            weights,
+            values,
+            deviation,
+        }
+    }
+
+    fn evaluate(&self, x: f64) -> f64 {
+        let (mut numerator, mut denominator) = (0.0, 0.0);
+        for ((point, weight), value) in self.points.iter().zip(&self.weights).zip(&self.values) {
+            let difference = x - point;
+            if difference.abs() < 1e-12 {
+                return *value;
+            }
+            numerator += weight / difference * value;
+            denominator += weight / difference;
+        }
+        numerator / denominator
+    }
+}
+
+#[derive(Clone)]
+struct FirState {
+    // The last `len` inputs, written twice over so that they can always be read as one
+    // contiguous slice, newest first, starting at `position`
+    history: Vec<f32>,
+    position: usize,
+}
+
+impl FirState {
+    fn process(&mut self, fir: &Fir, x: f32) -> f32 {
+        let len = self.history.len() / 2;
+        self.position = (self.position + len - 1) % len;
+        self.history[self.position] = x;
+        self.history[self.position + len] = x;
+        self.history[self.position..self.position + len]
+            .iter()
+            .zip(fir.taps.iter())
+            .map(|(x, h)| x * h)
+            .sum()
+    }
+}
+
+/// A [Source] which runs a [Fir] filter over each channel of its inner source.
+///
+/// The output is as long as the input, so the last [delay](Fir::delay) samples of filtered
+/// signal are left out.
+pub struct FirSource<S: Source<Item = f32>> {
+    source: S,
+    fir: Fir,
+    state: PerChannel<FirState>,
+}
+
+impl<S: Source<Item = f32>> Iterator for FirSource<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let fir = &self.fir;
+        self.state.set_channels(self.source.channels(), || fir.new_state());
+
+        let Some(x) = self.source.next() else { return None; };
+        let y = self.state.next(|state| state.process(fir, x));
+        Some(y)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for FirSource<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<std::time::Duration> {
+        self.source.total_duration()
+    }
+}
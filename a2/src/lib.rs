@@ -1,13 +1,31 @@
 pub mod bevy_midi;
 pub mod biquad;
 pub mod complex;
+pub mod convolution;
 pub mod envelope;
+pub mod fft;
+pub mod fir;
+pub mod metering;
+mod per_channel;
+pub mod pitch;
+pub mod plot;
+pub mod sinusoidal;
 pub mod source_queue;
+pub mod stft;
 pub mod wavetable;
+pub mod window;
 
 pub mod prelude {
     pub use crate::biquad::*;
+    pub use crate::convolution::*;
     pub use crate::envelope::*;
+    pub use crate::fft::*;
+    pub use crate::fir::*;
+    pub use crate::metering::*;
+    pub use crate::pitch::*;
+    pub use crate::sinusoidal::*;
     pub use crate::source_queue::*;
+    pub use crate::stft::*;
     pub use crate::wavetable::*;
+    pub use crate::window::*;
 }
@@ -0,0 +1,247 @@
+use crate::fft::{bin_frequency, RealFftPlan};
+use crate::stft::mono_samples;
+use crate::window::Window;
+use rodio::Source;
+use std::f32::consts::PI;
+
+/// The root mean square level of a block of samples, `0.0` if it's empty.
+#[must_use]
+pub fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    (samples.iter().map(|x| x * x).sum::<f32>() / samples.len() as f32).sqrt()
+}
+
+/// The largest absolute sample in a block.
+#[must_use]
+pub fn peak(samples: &[f32]) -> f32 {
+    samples.iter().fold(0.0, |peak, x| x.abs().max(peak))
+}
+
+/// The largest absolute level of the signal between the samples of a block, found by
+/// oversampling it four times, as in ITU-R BS.1770.
+///
+/// This catches peaks which fall between samples, and would clip once the signal is
+/// converted back to analogue.
+#[must_use]
+pub fn true_peak(samples: &[f32]) -> f32 {
+    // Taps either side of each interpolated point
+    const HALF_TAPS: isize = 16;
+
+    let len = samples.len() as isize;
+    let mut true_peak = peak(samples);
+    for i in 0..len - 1 {
+        for phase in 1..4 {
+            let t = i as f32 + phase as f32 / 4.0;
+            let value: f32 = (i - HALF_TAPS + 1..=i + HALF_TAPS)
+                .filter(|k| (0..len).contains(k))
+                .map(|k| {
+                    let x = t - k as f32;
+                    // A Hann windowed sinc
+                    let window = 0.5 + 0.5 * (PI * x / HALF_TAPS as f32).cos();
+                    samples[k as usize] * (PI * x).sin() / (PI * x) * window
+                })
+                .sum();
+            true_peak = true_peak.max(value.abs());
+        }
+    }
+    true_peak
+}
+
+/// The ratio of the peak of a block to its RMS level; `2.0f32.sqrt()` for a sine wave, and
+/// higher for spikier signals. Gives `0.0` for silence.
+#[must_use]
+pub fn crest_factor(samples: &[f32]) -> f32 {
+    let rms = rms(samples);
+    if rms == 0.0 {
+        return 0.0;
+    }
+    peak(samples) / rms
+}
+
+/// The fraction of neighbouring samples in a block which change sign, from `0.0` to `1.0`.
+///
+/// A sine wave of frequency `f` crosses zero `2 * f / sample_rate` times per sample.
+#[must_use]
+pub fn zero_crossing_rate(samples: &[f32]) -> f32 {
+    if samples.len() < 2 {
+        return 0.0;
+    }
+    let crossings = samples
+        .windows(2)
+        .filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0))
+        .count();
+    crossings as f32 / (samples.len() - 1) as f32
+}
+
+/// The features measured over one frame of a signal.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Features {
+    /// The time in seconds at the center of the frame.
+    pub time: f32,
+    pub rms: f32,
+    pub peak: f32,
+    pub true_peak: f32,
+    pub crest_factor: f32,
+    pub zero_crossing_rate: f32,
+    /// The center of mass of the magnitude spectrum in Hz, a measure of brightness.
+    pub spectral_centroid: f32,
+    /// The frequency in Hz below which the rolloff fraction of the spectrum's energy lies.
+    pub spectral_rolloff: f32,
+    /// The geometric mean of the power spectrum over its arithmetic mean, from `0.0` for a
+    /// pure tone to `1.0` for white noise.
+    pub spectral_flatness: f32,
+}
+
+/// Settings for measuring a signal frame by frame.
+///
+/// The signal is cut into frames of `frame_len` samples, `hop` samples apart, with the last
+/// frame padded with zeroes. Spectral features are taken from the windowed frame.
+#[derive(Clone, Debug)]
+pub struct Meter {
+    frame_len: usize,
+    hop: usize,
+    window: Window,
+    rolloff: f32,
+}
+
+impl Meter {
+    /// Creates a meter with a Hann window, and a rolloff fraction of `0.85`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frame_len` or `hop` is 0.
+    #[must_use]
+    pub fn new(frame_len: usize, hop: usize) -> Meter {
+        assert!(
+            frame_len > 0 && hop > 0,
+            "frame_len and hop must be at least 1"
+        );
+        Meter {
+            frame_len,
+            hop,
+            window: Window::Hann,
+            rolloff: 0.85,
+        }
+    }
+
+    #[must_use]
+    pub fn with_window(mut self, window: Window) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Sets the fraction of spectral energy which lies below the spectral rolloff.
+    #[must_use]
+    pub fn with_rolloff(mut self, rolloff: f32) -> Self {
+        self.rolloff = rolloff;
+        self
+    }
+
+    pub fn frame_len(&self) -> usize {
+        self.frame_len
+    }
+
+    pub fn hop(&self) -> usize {
+        self.hop
+    }
+
+    /// Measures each frame of `source`.
+    ///
+    /// Multichannel sources are mixed down to mono first. The source has to end, so
+    /// infinite sources should be cut short with something like
+    /// [take_duration](Source::take_duration).
+    pub fn measure<S: Source<Item = f32>>(&self, source: S) -> Vec<Features> {
+        let sample_rate = source.sample_rate();
+        self.measure_samples(&mono_samples(source, None), sample_rate)
+    }
+
+    /// Measures each frame of a block of mono samples.
+    #[must_use]
+    pub fn measure_samples(&self, samples: &[f32], sample_rate: u32) -> Vec<Features> {
+        let plan = RealFftPlan::new(self.frame_len);
+        let window = self.window.periodic(self.frame_len);
+
+        let frame_count = match samples.len() {
+            0 => 0,
+            len => (len.saturating_sub(self.frame_len) + self.hop - 1) / self.hop + 1,
+        };
+        (0..frame_count)
+            .map(|i| {
+                let start = i * self.hop;
+                let end = (start + self.frame_len).min(samples.len());
+                let mut frame = samples[start..end].to_vec();
+                frame.resize(self.frame_len, 0.0);
+                let mut features = self.measure_frame(&plan, &window, &frame, sample_rate);
+                features.time = (start + self.frame_len / 2) as f32 / sample_rate as f32;
+                features
+            })
+            .collect()
+    }
+
+    fn measure_frame(
+        &self,
+        plan: &RealFftPlan,
+        window: &[f32],
+        frame: &[f32],
+        sample_rate: u32,
+    ) -> Features {
+        let time_domain = Features {
+            time: 0.0,
+            rms: rms(frame),
+            peak: peak(frame),
+            true_peak: true_peak(frame),
+            crest_factor: crest_factor(frame),
+            zero_crossing_rate: zero_crossing_rate(frame),
+            ..Features::default()
+        };
+
+        let windowed: Vec<f32> = frame.iter().zip(window).map(|(x, w)| x * w).collect();
+        let spectrum = plan.forward(&windowed);
+        let magnitudes: Vec<f32> = spectrum.iter().map(|bin| bin.magnitude()).collect();
+        let frequency = |bin| bin_frequency(bin, self.frame_len, sample_rate);
+
+        let magnitude_sum: f32 = magnitudes.iter().sum();
+        let energy: f32 = magnitudes.iter().map(|m| m * m).sum();
+        // The window can silence a frame which isn't silent, such as a click at its edge
+        if energy == 0.0 {
+            return Features {
+                spectral_flatness: 1.0,
+                ..time_domain
+            };
+        }
+
+        let spectral_centroid = magnitudes
+            .iter()
+            .enumerate()
+            .map(|(bin, m)| frequency(bin) * m)
+            .sum::<f32>()
+            / magnitude_sum;
+
+        let mut cumulative = 0.0;
+        let rolloff_bin = magnitudes
+            .iter()
+            .position(|m| {
+                cumulative += m * m;
+                cumulative >= self.rolloff * energy
+            })
+            .unwrap_or(magnitudes.len() - 1);
+
+        // Computed in the log domain so that long frames don't underflow
+        let mean_log_power = magnitudes
+            .iter()
+            .map(|m| ((m * m) as f64).max(1e-30).ln())
+            .sum::<f64>()
+            / magnitudes.len() as f64;
+        let mean_power = energy as f64 / magnitudes.len() as f64;
+        let spectral_flatness = (mean_log_power.exp() / mean_power).min(1.0) as f32;
+
+        Features {
+            spectral_centroid,
+            spectral_rolloff: frequency(rolloff_bin),
+            spectral_flatness,
+            ..time_domain
+        }
+    }
+}
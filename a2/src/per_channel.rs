@@ -0,0 +1,50 @@
+// Keeps a separate state for each channel of an interleaved source, such as the history of
+// a filter, so that one channel never leaks into another
+#[derive(Clone)]
+pub(crate) struct PerChannel<T> {
+    states: Vec<T>,
+    // The channel of the next sample
+    channel: usize,
+}
+
+impl<T: Clone> PerChannel<T> {
+    pub(crate) fn new(channels: u16, state: T) -> Self {
+        PerChannel {
+            states: vec![state; channels.max(1) as usize],
+            channel: 0,
+        }
+    }
+
+    // Follows a change in the channel count, starting any new channels from `state`. The
+    // count may only change at a frame boundary, so this does nothing part way through a
+    // frame.
+    pub(crate) fn set_channels(&mut self, channels: u16, state: impl FnOnce() -> T) {
+        let channels = channels.max(1) as usize;
+        if self.channel == 0 && channels != self.states.len() {
+            self.states.resize(channels, state());
+        }
+    }
+}
+
+impl<T> PerChannel<T> {
+    pub(crate) fn channels(&self) -> usize {
+        self.states.len()
+    }
+
+    // Whether the next sample starts a frame
+    pub(crate) fn at_frame_start(&self) -> bool {
+        self.channel == 0
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.states.iter_mut()
+    }
+
+    // Runs `f` on the state of the channel of the next sample, and moves on to the
+    // following channel
+    pub(crate) fn next<R>(&mut self, f: impl FnOnce(&mut T) -> R) -> R {
+        let result = f(&mut self.states[self.channel]);
+        self.channel = (self.channel + 1) % self.states.len();
+        result
+    }
+}
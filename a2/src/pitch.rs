@@ -0,0 +1,226 @@
+use rodio::Source;
+use std::collections::VecDeque;
+
+/// The pitch found in one frame of a signal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PitchEstimate {
+    /// The time in seconds at the center of the frame.
+    pub time: f32,
+    /// The fundamental frequency in Hz.
+    pub frequency: f32,
+    /// How periodic the frame is, from `0.0` (noise or silence) to `1.0` (perfectly
+    /// periodic). Frequencies with a low confidence are just the best guess.
+    pub confidence: f32,
+}
+
+impl PitchEstimate {
+    /// The nearest MIDI note to the frequency, and how far off it is in cents (from `-50.0`
+    /// to `50.0`), or [None] if the frequency is outside of the MIDI range.
+    #[must_use]
+    pub fn nearest_note(&self) -> Option<(u8, f32)> {
+        let note = frequency_to_midi(self.frequency);
+        let nearest = note.round();
+        if !(0.0..=127.0).contains(&nearest) {
+            return None;
+        }
+        Some((nearest as u8, 100.0 * (note - nearest)))
+    }
+}
+
+/// The MIDI note number of a frequency in Hz, where `69.0` is A440 and each semitone is
+/// `1.0`.
+#[must_use]
+pub fn frequency_to_midi(frequency: f32) -> f32 {
+    69.0 + 12.0 * (frequency / 440.0).log2()
+}
+
+/// The frequency in Hz of a (possibly fractional) MIDI note number.
+#[must_use]
+pub fn midi_to_frequency(note: f32) -> f32 {
+    440.0 * 2f32.powf((note - 69.0) / 12.0)
+}
+
+/// Settings for tracking the pitch of a signal with the YIN algorithm.
+///
+/// The signal is cut into frames of `frame_len` samples, `hop` samples apart. The lowest
+/// frequency which can be found has a period of half a frame.
+#[derive(Clone, Debug)]
+pub struct PitchTracker {
+    frame_len: usize,
+    hop: usize,
+    threshold: f32,
+    min_frequency: f32,
+    max_frequency: f32,
+}
+
+impl PitchTracker {
+    /// Creates a tracker with a threshold of `0.15`, looking for frequencies between 40Hz
+    /// and 4kHz.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `hop` is 0, or `frame_len` is shorter than 8 samples.
+    #[must_use]
+    pub fn new(frame_len: usize, hop: usize) -> PitchTracker {
+        assert!(hop > 0, "hop must be at least 1");
+        assert!(frame_len >= 8, "frame_len must be at least 8");
+        PitchTracker {
+            frame_len,
+            hop,
+            threshold: 0.15,
+            min_frequency: 40.0,
+            max_frequency: 4000.0,
+        }
+    }
+
+    /// Sets how aperiodic a frame can be while still taking the first period found,
+    /// rather than the best one. Lower thresholds are less likely to jump up an octave,
+    /// but more likely to jump down one.
+    #[must_use]
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Limits the frequencies looked for, in Hz.
+    #[must_use]
+    pub fn with_range(mut self, min_frequency: f32, max_frequency: f32) -> Self {
+        self.min_frequency = min_frequency;
+        self.max_frequency = max_frequency;
+        self
+    }
+
+    pub fn frame_len(&self) -> usize {
+        self.frame_len
+    }
+
+    pub fn hop(&self) -> usize {
+        self.hop
+    }
+
+    /// Tracks the pitch of a source, giving an estimate for every whole frame.
+    ///
+    /// Frames are pulled from the source as they're needed, so infinite sources are fine.
+    /// Multichannel sources are mixed down to mono first. Nothing is given if the frames
+    /// are too short to find any of the frequencies looked for.
+    pub fn track<S: Source<Item = f32>>(&self, source: S) -> PitchTrack<S> {
+        PitchTrack {
+            sample_rate: source.sample_rate(),
+            source,
+            tracker: self.clone(),
+            buffer: VecDeque::with_capacity(self.frame_len),
+            frame: 0,
+        }
+    }
+
+    /// Estimates the pitch of a single frame of mono samples, giving its frequency and
+    /// confidence.
+    ///
+    /// Returns [None] if the frame is too short to hold a period in the range looked for.
+    #[must_use]
+    pub fn estimate(&self, frame: &[f32], sample_rate: u32) -> Option<(f32, f32)> {
+        let sample_rate = sample_rate as f32;
+        let window = frame.len() / 2;
+        let max_lag = ((sample_rate / self.min_frequency) as usize).min(window);
+        let min_lag = ((sample_rate / self.max_frequency) as usize).max(2);
+        if min_lag + 1 >= max_lag {
+            return None;
+        }
+
+        // The cumulative mean normalized difference of the frame with itself at each lag
+        let mut differences = vec![1.0; max_lag + 1];
+        let mut running_sum = 0.0;
+        for lag in 1..=max_lag {
+            let difference: f32 = frame[..window]
+                .iter()
+                .zip(&frame[lag..lag + window])
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum();
+            running_sum += difference;
+            if running_sum > 0.0 {
+                differences[lag] = difference * lag as f32 / running_sum;
+            }
+        }
+
+        // The first dip below the threshold, or failing that the deepest dip
+        let lag = match (min_lag..max_lag).find(|lag| differences[*lag] < self.threshold) {
+            Some(mut lag) => {
+                while lag + 1 < max_lag && differences[lag + 1] < differences[lag] {
+                    lag += 1;
+                }
+                lag
+            }
+            None => (min_lag..max_lag)
+                .min_by(|a, b| differences[*a].total_cmp(&differences[*b]))
+                .unwrap_or(min_lag),
+        };
+
+        // Refine the lag with the vertex of the parabola through its neighbours
+        let (a, b, c) = (differences[lag - 1], differences[lag], differences[lag + 1]);
+        let curvature = a - 2.0 * b + c;
+        let offset = if curvature > 0.0 {
+            (0.5 * (a - c) / curvature).clamp(-1.0, 1.0)
+        } else {
+            0.0
+        };
+
+        let frequency = sample_rate / (lag as f32 + offset);
+        let confidence = (1.0 - b).clamp(0.0, 1.0);
+        Some((frequency, confidence))
+    }
+}
+
+/// An iterator over the pitch of each frame of a [Source], created by
+/// [PitchTracker::track].
+pub struct PitchTrack<S: Source<Item = f32>> {
+    source: S,
+    tracker: PitchTracker,
+    sample_rate: u32,
+    // The mono samples of the current frame
+    buffer: VecDeque<f32>,
+    frame: usize,
+}
+
+impl<S: Source<Item = f32>> PitchTrack<S> {
+    // Pulls the next frame of the source, mixed down to mono
+    fn next_mono_sample(&mut self) -> Option<f32> {
+        let channels = self.source.channels().max(1);
+        let mut sum = 0.0;
+        for _ in 0..channels {
+            sum += self.source.next()?;
+        }
+        Some(sum / channels as f32)
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for PitchTrack<S> {
+    type Item = PitchEstimate;
+
+    fn next(&mut self) -> Option<PitchEstimate> {
+        let (frame_len, hop) = (self.tracker.frame_len, self.tracker.hop);
+        if self.frame > 0 {
+            // Skip past the hop, even if it's longer than a frame
+            for _ in 0..hop.min(self.buffer.len()) {
+                self.buffer.pop_front();
+            }
+            for _ in frame_len..hop {
+                self.next_mono_sample()?;
+            }
+        }
+        while self.buffer.len() < frame_len {
+            let sample = self.next_mono_sample()?;
+            self.buffer.push_back(sample);
+        }
+
+        let time = (self.frame * hop + frame_len / 2) as f32 / self.sample_rate as f32;
+        self.frame += 1;
+        let (frequency, confidence) = self
+            .tracker
+            .estimate(self.buffer.make_contiguous(), self.sample_rate)?;
+        Some(PitchEstimate {
+            time,
+            frequency,
+            confidence,
+        })
+    }
+}
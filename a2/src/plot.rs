@@ -0,0 +1,144 @@
+use crate::complex::Complex;
+use crate::sinusoidal::Partial;
+use plotters::prelude::*;
+use std::{error::Error, f32::consts::TAU, path::Path};
+
+/// A line from the origin to `z`, for drawing on a complex plane.
+#[must_use]
+pub fn vector(z: Complex<f32>, style: impl Into<ShapeStyle>) -> PathElement<(f32, f32)> {
+    PathElement::new(vec![(0.0, 0.0), (z.real, z.im)], style)
+}
+
+/// An arrow from `from` to `to`, with a head a fifth of its length.
+#[must_use]
+pub fn arrow(
+    from: Complex<f32>,
+    to: Complex<f32>,
+    style: impl Into<ShapeStyle>,
+) -> PathElement<(f32, f32)> {
+    // The two sides of the head, swept back from the tip
+    let back = (from - to) * 0.2;
+    let left = to + back * Complex::cis(0.4);
+    let right = to + back * Complex::cis(-0.4);
+    PathElement::new(
+        vec![
+            (from.real, from.im),
+            (to.real, to.im),
+            (left.real, left.im),
+            (to.real, to.im),
+            (right.real, right.im),
+        ],
+        style,
+    )
+}
+
+/// A circle of `radius` around `center`, drawn in the units of the plane (unlike
+/// [Circle], which is sized in pixels).
+#[must_use]
+pub fn circle(
+    center: Complex<f32>,
+    radius: f32,
+    style: impl Into<ShapeStyle>,
+) -> PathElement<(f32, f32)> {
+    const SEGMENTS: usize = 128;
+    PathElement::new(
+        (0..=SEGMENTS)
+            .map(|i| {
+                let z = center + Complex::from_polar(radius, i as f32 / SEGMENTS as f32 * TAU);
+                (z.real, z.im)
+            })
+            .collect::<Vec<_>>(),
+        style,
+    )
+}
+
+/// The circle of radius `1` around the origin.
+#[must_use]
+pub fn unit_circle(style: impl Into<ShapeStyle>) -> PathElement<(f32, f32)> {
+    circle(Complex::ZERO, 1.0, style)
+}
+
+/// Arrows for each phasor, placed head to tail so that the last one ends at their sum.
+#[must_use]
+pub fn phasor_sum(
+    phasors: &[Complex<f32>],
+    style: impl Into<ShapeStyle>,
+) -> Vec<PathElement<(f32, f32)>> {
+    let style = style.into();
+    let mut tail = Complex::ZERO;
+    phasors
+        .iter()
+        .map(|phasor| {
+            let head = tail + *phasor;
+            let arrow = arrow(tail, head, style);
+            tail = head;
+            arrow
+        })
+        .collect()
+}
+
+/// The path traced by the sum of the phasors of `partials` over `times` (in seconds).
+#[must_use]
+pub fn phasor_trace(
+    partials: &[Partial],
+    times: impl IntoIterator<Item = f32>,
+    style: impl Into<ShapeStyle>,
+) -> PathElement<(f32, f32)> {
+    PathElement::new(
+        times
+            .into_iter()
+            .map(|t| {
+                let z: Complex<f32> = partials.iter().map(|p| p.phasor(t)).sum();
+                (z.real, z.im)
+            })
+            .collect::<Vec<_>>(),
+        style,
+    )
+}
+
+/// Renders the phasors of `partials` rotating over `duration` seconds to an animated GIF,
+/// with `frames` frames shown for `frame_delay` milliseconds each.
+///
+/// The phasors are drawn head to tail along with the path their sum has traced so far.
+/// The imaginary part of the sum is the value of the partials added together.
+pub fn animate_phasors(
+    path: impl AsRef<Path>,
+    partials: &[Partial],
+    size: (u32, u32),
+    duration: f32,
+    frames: usize,
+    frame_delay: u32,
+) -> Result<(), Box<dyn Error>> {
+    let root = BitMapBackend::gif(path.as_ref(), size, frame_delay)?.into_drawing_area();
+    let extent = 1.1 * partials.iter().map(|p| p.amplitude.abs()).sum::<f32>();
+    let extent = extent.max(f32::EPSILON);
+    let time = |frame: usize| duration * frame as f32 / frames.max(1) as f32;
+
+    for frame in 0..frames {
+        root.fill(&WHITE)?;
+        let mut chart = ChartBuilder::on(&root)
+            .margin(10)
+            .x_label_area_size(30)
+            .y_label_area_size(40)
+            .build_cartesian_2d(-extent..extent, -extent..extent)?;
+        chart.configure_mesh().x_desc("Re").y_desc("Im").draw()?;
+
+        let t = time(frame);
+        chart.draw_series(std::iter::once(phasor_trace(
+            partials,
+            (0..=frame).map(time),
+            BLUE.mix(0.5),
+        )))?;
+        let phasors: Vec<Complex<f32>> = partials.iter().map(|p| p.phasor(t)).collect();
+        chart.draw_series(phasor_sum(&phasors, BLACK))?;
+
+        let sum: Complex<f32> = phasors.iter().sum();
+        chart.draw_series(std::iter::once(Circle::new(
+            (sum.real, sum.im),
+            3,
+            RED.filled(),
+        )))?;
+        root.present()?;
+    }
+    Ok(())
+}
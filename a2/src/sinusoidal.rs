@@ -0,0 +1,193 @@
+use crate::complex::Complex;
+use crate::fft::RealFftPlan;
+use crate::stft::mono_samples;
+use crate::window::Window;
+use rodio::Source;
+use std::f64::consts::{FRAC_PI_2, TAU};
+
+/// A single sine wave making up part of a signal; `amplitude * sin(TAU * frequency * t +
+/// phase)`, with `t` in seconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Partial {
+    pub frequency: f32,
+    pub amplitude: f32,
+    /// The phase at the start of the analysed block, from `0.0` to `TAU`.
+    pub phase: f32,
+}
+
+impl Partial {
+    /// The value of the partial at `time` seconds.
+    #[must_use]
+    pub fn at(&self, time: f32) -> f32 {
+        self.amplitude * (std::f32::consts::TAU * self.frequency * time + self.phase).sin()
+    }
+
+    /// The partial as a rotating phasor at `time` seconds, whose imaginary part is the value
+    /// of the partial.
+    #[must_use]
+    pub fn phasor(&self, time: f32) -> Complex<f32> {
+        Complex::from_polar(
+            self.amplitude,
+            std::f32::consts::TAU * self.frequency * time + self.phase,
+        )
+    }
+}
+
+/// The correlation of `samples` with a complex sinusoid of `frequency` cycles per sample,
+/// `sum(samples[n] * e^(-2 pi i frequency n))`, computed with the Goertzel algorithm.
+///
+/// This is the same as a single bin of a DFT, but `frequency` doesn't need to line up with
+/// a bin.
+#[must_use]
+pub fn goertzel(samples: &[f32], frequency: f64) -> Complex<f64> {
+    let omega = TAU * frequency;
+    let coefficient = 2.0 * omega.cos();
+    let (mut s1, mut s2) = (0.0, 0.0);
+    for x in samples {
+        let s0 = *x as f64 + coefficient * s1 - s2;
+        s2 = s1;
+        s1 = s0;
+    }
+    // s1 - e^(-i omega) s2 is the correlation relative to the last sample; shift it back
+    // to be relative to the first
+    let y = Complex::new(s1, 0.0) - Complex::cis(-omega) * s2;
+    let last = samples.len().saturating_sub(1) as f64;
+    y * Complex::cis(-omega * last)
+}
+
+// Turns the correlation of a windowed block with a sinusoid into the amplitude and (sine)
+// phase of that sinusoid
+fn partial_from_correlation(correlation: Complex<f64>, frequency: f32, window_sum: f64) -> Partial {
+    // A sin(wn + phase) correlates to (A * window_sum / 2) * e^(i (phase - PI / 2))
+    let amplitude = 2.0 * correlation.magnitude() / window_sum;
+    let phase = (correlation.arg() + FRAC_PI_2).rem_euclid(TAU);
+    Partial {
+        frequency,
+        amplitude: amplitude as f32,
+        phase: phase as f32,
+    }
+}
+
+/// Settings for analysing the sinusoids making up a [Source].
+///
+/// The first `len` frames of the source are mixed down to mono, windowed, and analysed.
+/// Longer blocks can tell apart partials which are closer in frequency.
+#[derive(Clone, Debug)]
+pub struct SinusoidalAnalysis {
+    len: usize,
+    window: Window,
+}
+
+impl SinusoidalAnalysis {
+    /// Analyses blocks of `len` frames with a Hann window.
+    #[must_use]
+    pub fn new(len: usize) -> SinusoidalAnalysis {
+        SinusoidalAnalysis {
+            len,
+            window: Window::Hann,
+        }
+    }
+
+    #[must_use]
+    pub fn with_window(mut self, window: Window) -> Self {
+        self.window = window;
+        self
+    }
+
+    // Collects and windows a block from the source, returning it along with the sum of
+    // the window
+    fn windowed_block<S: Source<Item = f32>>(&self, source: S) -> (Vec<f32>, f64) {
+        let mut samples = mono_samples(source, Some(self.len));
+        samples.resize(self.len, 0.0);
+        let window = self.window.symmetric(self.len);
+        samples.iter_mut().zip(&window).for_each(|(x, w)| *x *= w);
+        let window_sum = window.iter().map(|w| *w as f64).sum();
+        (samples, window_sum)
+    }
+
+    /// Estimates the amplitude and phase of the sinusoid at each of `frequencies` (in Hz).
+    ///
+    /// The frequencies don't need to be harmonics of each other, or line up with any
+    /// particular block length. Partials within a couple of `sample_rate / len` Hz of each
+    /// other will bleed into each other's estimates.
+    pub fn estimate<S: Source<Item = f32>>(&self, source: S, frequencies: &[f32]) -> Vec<Partial> {
+        let sample_rate = source.sample_rate() as f64;
+        let (block, window_sum) = self.windowed_block(source);
+        frequencies
+            .iter()
+            .map(|f| {
+                let correlation = goertzel(&block, *f as f64 / sample_rate);
+                partial_from_correlation(correlation, *f, window_sum)
+            })
+            .collect()
+    }
+
+    /// Finds up to `max_partials` of the loudest sinusoids in the source, which are no more
+    /// than `threshold_db` dB below the loudest one, sorted by frequency.
+    ///
+    /// Peaks in the spectrum are refined with parabolic interpolation, so frequencies are
+    /// found to a fraction of a bin.
+    pub fn find_partials<S: Source<Item = f32>>(
+        &self,
+        source: S,
+        max_partials: usize,
+        threshold_db: f32,
+    ) -> Vec<Partial> {
+        let sample_rate = source.sample_rate() as f64;
+        let (block, window_sum) = self.windowed_block(source);
+        if block.len() < 3 {
+            return Vec::new();
+        }
+
+        // Zero pad to make the parabolic interpolation more accurate
+        let fft_len = (4 * self.len).next_power_of_two();
+        let mut padded = block.clone();
+        padded.resize(fft_len, 0.0);
+        let spectrum = RealFftPlan::new(fft_len).forward(&padded);
+        let log_magnitudes: Vec<f64> = spectrum
+            .iter()
+            .map(|bin| (bin.magnitude() as f64).max(1e-30).ln())
+            .collect();
+
+        let loudest = log_magnitudes.iter().copied().fold(f64::MIN, f64::max);
+        let threshold = loudest - threshold_db.abs() as f64 / 20.0 * 10f64.ln();
+        let mut peaks: Vec<(f64, f64)> = (1..log_magnitudes.len() - 1)
+            .filter_map(|k| {
+                let (a, b, c) = (
+                    log_magnitudes[k - 1],
+                    log_magnitudes[k],
+                    log_magnitudes[k + 1],
+                );
+                if b <= a || b < c || b < threshold {
+                    return None;
+                }
+                // The vertex of the parabola through the three bins
+                let offset = 0.5 * (a - c) / (a - 2.0 * b + c);
+                let peak = b - 0.25 * (a - c) * offset;
+                Some((k as f64 + offset, peak))
+            })
+            .collect();
+
+        peaks.sort_by(|a, b| b.1.total_cmp(&a.1));
+        peaks.truncate(max_partials);
+        let mut partials: Vec<Partial> = peaks
+            .into_iter()
+            .map(|(bin, _)| {
+                let frequency = bin / fft_len as f64;
+                let correlation = goertzel(&block, frequency);
+                partial_from_correlation(correlation, (frequency * sample_rate) as f32, window_sum)
+            })
+            .collect();
+        partials.sort_by(|a, b| a.frequency.total_cmp(&b.frequency));
+        partials
+    }
+}
+
+/// Turns a set of partials back into a periodic function of the form `Fn(f32) -> f32`,
+/// running from `0.0` to `1.0` over one period of `fundamental`, ready for
+/// [WaveTable::from_function](crate::wavetable::WaveTable::from_function).
+///
+/// The function only repeats cleanly when every partial is a harmonic of `fundamental`.
+pub fn resynthesize(partials: Vec<Partial>, fundamental: f32) -> impl Fn(f32) -> f32 + 'static {
+    move |t| partials.iter().map(|p| p.at(t / fundamental)).sum()
+}
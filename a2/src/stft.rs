@@ -0,0 +1,290 @@
+use crate::complex::Complex;
+use crate::fft::RealFftPlan;
+use crate::window::Window;
+use plotters::{
+    prelude::{BitMapBackend, ChartBuilder, IntoDrawingArea, Rectangle},
+    style::{Color, HSLColor, IntoFont, WHITE},
+};
+use rodio::Source;
+use std::{error::Error, path::Path};
+
+/// Settings for a short-time Fourier transform.
+///
+/// The signal is cut into overlapping frames of `frame_len` samples, `hop` samples apart,
+/// with frame `i` centered on sample `i * hop`. Each frame is windowed, padded with zeroes
+/// up to `fft_len` samples, and transformed.
+#[derive(Clone, Debug)]
+pub struct Stft {
+    frame_len: usize,
+    hop: usize,
+    fft_len: usize,
+    window: Window,
+}
+
+impl Stft {
+    /// Creates a transform with a Hann window and no zero padding.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `hop` is 0 or longer than `frame_len`.
+    #[must_use]
+    pub fn new(frame_len: usize, hop: usize) -> Stft {
+        assert!(
+            hop > 0 && hop <= frame_len,
+            "hop must be between 1 and the frame length"
+        );
+        Stft {
+            frame_len,
+            hop,
+            fft_len: frame_len,
+            window: Window::Hann,
+        }
+    }
+
+    #[must_use]
+    pub fn with_window(mut self, window: Window) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Pads each frame with zeroes up to `fft_len` samples, which interpolates between the
+    /// bins of the spectrum.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fft_len` is shorter than the frame length.
+    #[must_use]
+    pub fn with_fft_len(mut self, fft_len: usize) -> Self {
+        assert!(
+            fft_len >= self.frame_len,
+            "fft_len must be at least the frame length"
+        );
+        self.fft_len = fft_len;
+        self
+    }
+
+    pub fn frame_len(&self) -> usize {
+        self.frame_len
+    }
+
+    pub fn hop(&self) -> usize {
+        self.hop
+    }
+
+    pub fn fft_len(&self) -> usize {
+        self.fft_len
+    }
+
+    pub fn window(&self) -> Window {
+        self.window
+    }
+
+    /// Transforms the whole of `source`.
+    ///
+    /// Multichannel sources are mixed down to mono first. The source has to end, so
+    /// infinite sources should be cut short with something like
+    /// [take_duration](Source::take_duration).
+    pub fn analyze<S: Source<Item = f32>>(&self, source: S) -> Spectrogram {
+        let sample_rate = source.sample_rate();
+        self.analyze_samples(&mono_samples(source, None), sample_rate)
+    }
+
+    /// Transforms a block of mono samples.
+    #[must_use]
+    pub fn analyze_samples(&self, samples: &[f32], sample_rate: u32) -> Spectrogram {
+        let plan = RealFftPlan::new(self.fft_len);
+        let window = self.window.periodic(self.frame_len);
+        let half = (self.frame_len / 2) as isize;
+
+        // Every frame which overlaps with the samples
+        let frame_count = match samples.len() {
+            0 => 0,
+            len => (len + half as usize + self.hop - 1) / self.hop,
+        };
+        let frames = (0..frame_count)
+            .map(|i| {
+                let start = (i * self.hop) as isize - half;
+                let mut buffer = vec![0.0; self.fft_len];
+                for (j, w) in window.iter().enumerate() {
+                    let index = start + j as isize;
+                    if index >= 0 && (index as usize) < samples.len() {
+                        buffer[j] = samples[index as usize] * w;
+                    }
+                }
+                plan.forward(&buffer)
+            })
+            .collect();
+
+        Spectrogram {
+            frames,
+            stft: self.clone(),
+            sample_rate,
+            len: samples.len(),
+        }
+    }
+
+    /// Turns a spectrogram back into samples, by overlapping and adding the inverse
+    /// transform of each frame.
+    ///
+    /// Frames are windowed again after their inverse transform, and the result normalized by
+    /// the overlapping windows, so unmodified spectrograms come back as the original signal.
+    #[must_use]
+    pub fn synthesize(&self, spectrogram: &Spectrogram) -> Vec<f32> {
+        let plan = RealFftPlan::new(self.fft_len);
+        let window = self.window.periodic(self.frame_len);
+        let half = (self.frame_len / 2) as isize;
+
+        let mut output = vec![0.0; spectrogram.len];
+        let mut weights = vec![0.0; spectrogram.len];
+        for (i, frame) in spectrogram.frames.iter().enumerate() {
+            let samples = plan.inverse(frame);
+            let start = (i * self.hop) as isize - half;
+            for (j, w) in window.iter().enumerate() {
+                let index = start + j as isize;
+                if index >= 0 && (index as usize) < output.len() {
+                    output[index as usize] += samples[j] * w;
+                    weights[index as usize] += w * w;
+                }
+            }
+        }
+
+        for (sample, weight) in output.iter_mut().zip(weights) {
+            if weight > 1e-6 {
+                *sample /= weight;
+            }
+        }
+        output
+    }
+}
+
+/// The result of a short-time Fourier transform; the spectrum of each frame of a signal.
+#[derive(Clone, Debug)]
+pub struct Spectrogram {
+    /// The first `fft_len / 2 + 1` bins of the spectrum of each frame.
+    pub frames: Vec<Vec<Complex<f32>>>,
+    stft: Stft,
+    sample_rate: u32,
+    // The number of samples analysed
+    len: usize,
+}
+
+impl Spectrogram {
+    /// The settings of the transform which made this spectrogram.
+    pub fn stft(&self) -> &Stft {
+        &self.stft
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// The number of samples which were analysed.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The time in seconds at the center of frame `index`.
+    pub fn frame_time(&self, index: usize) -> f32 {
+        (index * self.stft.hop) as f32 / self.sample_rate as f32
+    }
+
+    /// The frequency in Hz at the center of bin `index`.
+    pub fn bin_frequency(&self, index: usize) -> f32 {
+        crate::fft::bin_frequency(index, self.stft.fft_len, self.sample_rate)
+    }
+
+    /// The magnitude of each bin of each frame in decibels, relative to a full-scale sine
+    /// wave, and floored at `floor` dB.
+    pub fn magnitudes_db(&self, floor: f32) -> Vec<Vec<f32>> {
+        // A full scale sine adds up to half the sum of the window
+        let window_sum: f32 = self.stft.window.periodic(self.stft.frame_len).iter().sum();
+        let reference = (window_sum / 2.0).max(f32::MIN_POSITIVE);
+        self.frames
+            .iter()
+            .map(|frame| {
+                frame
+                    .iter()
+                    .map(|bin| (20.0 * (bin.magnitude() / reference).log10()).max(floor))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Draws the spectrogram to a PNG file, with time along the x axis, frequency along the
+    /// y axis, and the level of each bin as its colour (from `-100` dB to `0` dB).
+    pub fn render_png(
+        &self,
+        path: impl AsRef<Path>,
+        size: (u32, u32),
+        caption: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        const FLOOR: f32 = -100.0;
+
+        let root = BitMapBackend::new(path.as_ref(), size).into_drawing_area();
+        root.fill(&WHITE)?;
+
+        let duration = self.len as f32 / self.sample_rate as f32;
+        let nyquist = self.sample_rate as f32 / 2.0;
+        let mut chart = ChartBuilder::on(&root)
+            .x_label_area_size(35)
+            .y_label_area_size(60)
+            .margin(5)
+            .caption(caption, ("sans-serif", 30.0).into_font())
+            .build_cartesian_2d(0f32..duration.max(f32::EPSILON), 0f32..nyquist)?;
+
+        chart
+            .configure_mesh()
+            .disable_x_mesh()
+            .disable_y_mesh()
+            .x_desc("Time (s)")
+            .y_desc("Frequency (Hz)")
+            .draw()?;
+
+        let frame_width = self.stft.hop as f32 / self.sample_rate as f32;
+        let bin_height = self.sample_rate as f32 / self.stft.fft_len as f32;
+        let magnitudes = self.magnitudes_db(FLOOR);
+        chart.draw_series(magnitudes.iter().enumerate().flat_map(|(i, frame)| {
+            let time = self.frame_time(i) - frame_width / 2.0;
+            frame.iter().enumerate().map(move |(j, db)| {
+                let frequency = j as f32 * bin_height - bin_height / 2.0;
+                Rectangle::new(
+                    [
+                        (time, frequency),
+                        (time + frame_width, frequency + bin_height),
+                    ],
+                    heat_colour(*db / FLOOR).filled(),
+                )
+            })
+        }))?;
+
+        root.present()?;
+        Ok(())
+    }
+}
+
+// Collects the samples of a source (up to `max_frames` frames, if given), mixing
+// multichannel sources down to mono.
+pub(crate) fn mono_samples<S: Source<Item = f32>>(
+    source: S,
+    max_frames: Option<usize>,
+) -> Vec<f32> {
+    let channels = source.channels().max(1) as usize;
+    let samples: Vec<f32> = match max_frames {
+        Some(frames) => source.take(frames * channels).collect(),
+        None => source.collect(),
+    };
+    samples
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect()
+}
+
+// Maps 0 (loud) to 1 (quiet) onto a red to blue colour scale, fading to black
+fn heat_colour(quietness: f32) -> HSLColor {
+    let quietness = quietness.clamp(0.0, 1.0) as f64;
+    HSLColor(0.7 * quietness, 1.0, 0.5 * (1.0 - quietness * quietness))
+}
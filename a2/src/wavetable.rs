@@ -1,5 +1,8 @@
+use crate::complex::Complex;
+use crate::fft::RealFftPlan;
 use bevy::audio::Decodable;
 use rodio::Source;
+use std::f64::consts::{FRAC_PI_2, TAU};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -32,6 +35,54 @@ impl WaveTable {
         }
     }
 
+    /// Creates a new band-limited wavetable of `sample_num` samples from the
+    /// `(amplitude, phase)` of each harmonic, starting with the fundamental.
+    ///
+    /// Harmonic `k` contributes `amplitude * sin(TAU * k * t + phase)` over one period
+    /// of the table. Harmonics which don't fit below half of `sample_num` are left out,
+    /// since the table can't hold them without aliasing.
+    #[must_use]
+    pub fn from_harmonics(sample_num: u32, harmonics: &[(f32, f32)]) -> WaveTable {
+        let len = sample_num as usize;
+        let plan = RealFftPlan::<f64>::new(len);
+        let mut spectrum = vec![Complex::ZERO; plan.spectrum_len()];
+        // A sin(wt + phase) shows up in its bin as (A * len / 2) * e^(i (phase - PI / 2))
+        for (bin, (amplitude, phase)) in spectrum
+            .iter_mut()
+            .skip(1)
+            .zip(harmonics)
+            .take(harmonic_count(len))
+        {
+            *bin = Complex::from_polar(
+                *amplitude as f64 * len as f64 / 2.0,
+                *phase as f64 - FRAC_PI_2,
+            );
+        }
+        WaveTable::new(plan.inverse(&spectrum).into_iter().map(|x| x as f32))
+    }
+
+    /// Gives the `(amplitude, phase)` of each harmonic in the table, starting with the
+    /// fundamental, in the same form taken by [WaveTable::from_harmonics].
+    ///
+    /// Every harmonic below half the length of the table is given, and any constant offset
+    /// is left out.
+    #[must_use]
+    pub fn harmonics(&self) -> Vec<(f32, f32)> {
+        let len = self.data.len();
+        let samples: Vec<f64> = self.data.iter().map(|x| *x as f64).collect();
+        let spectrum = RealFftPlan::<f64>::new(len).forward(&samples);
+        spectrum
+            .iter()
+            .skip(1)
+            .take(harmonic_count(len))
+            .map(|bin| {
+                let amplitude = 2.0 * bin.magnitude() / len as f64;
+                let phase = (bin.arg() + FRAC_PI_2).rem_euclid(TAU);
+                (amplitude as f32, phase as f32)
+            })
+            .collect()
+    }
+
     /// Creates a [Source] from a wave table.
     #[must_use]
     pub fn source(&self, sample_rate: u32) -> WaveTableSource {
@@ -62,6 +113,11 @@ impl WaveTable {
     }
 }
 
+// The number of harmonics which fit strictly below the Nyquist frequency of a table
+fn harmonic_count(len: usize) -> usize {
+    len.saturating_sub(1) / 2
+}
+
 /// A [Source] of audio created by [WaveTable].
 #[derive(Clone)]
 pub struct WaveTableSource {
@@ -0,0 +1,78 @@
+use std::f64::consts::TAU;
+
+/// A window function, for tapering the ends of a block of samples before analysing it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Window {
+    /// No tapering at all.
+    Rectangular,
+    #[default]
+    Hann,
+    Hamming,
+    /// The 4-term Blackman-Harris window, which has very low sidelobes.
+    BlackmanHarris,
+    /// A Kaiser window with the given `beta`; larger values give lower sidelobes and a
+    /// wider main lobe.
+    Kaiser(f32),
+}
+
+impl Window {
+    /// The value of the window at `x`, where `x` runs from `0.0` at the start of the window
+    /// to `1.0` at its end.
+    #[must_use]
+    pub fn at(&self, x: f64) -> f64 {
+        match *self {
+            Window::Rectangular => 1.0,
+            Window::Hann => 0.5 - 0.5 * (TAU * x).cos(),
+            Window::Hamming => 0.54 - 0.46 * (TAU * x).cos(),
+            Window::BlackmanHarris => {
+                0.35875 - 0.48829 * (TAU * x).cos() + 0.14128 * (2.0 * TAU * x).cos()
+                    - 0.01168 * (3.0 * TAU * x).cos()
+            }
+            Window::Kaiser(beta) => {
+                let beta = beta as f64;
+                let r = 2.0 * x - 1.0;
+                bessel_i0(beta * (1.0 - r * r).max(0.0).sqrt()) / bessel_i0(beta)
+            }
+        }
+    }
+
+    /// Gives `len` coefficients of the window, symmetric around its middle.
+    ///
+    /// Symmetric windows are the ones to use for designing filters.
+    #[must_use]
+    pub fn symmetric(&self, len: usize) -> Vec<f32> {
+        if len == 1 {
+            return vec![1.0];
+        }
+        (0..len)
+            .map(|i| self.at(i as f64 / (len - 1) as f64) as f32)
+            .collect()
+    }
+
+    /// Gives `len` coefficients of the window, as if it were one sample longer and the last
+    /// sample cut off.
+    ///
+    /// Periodic windows are the ones to use for spectral analysis, since overlapping copies
+    /// of them sum up evenly.
+    #[must_use]
+    pub fn periodic(&self, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| self.at(i as f64 / len as f64) as f32)
+            .collect()
+    }
+}
+
+// The zeroth order modified Bessel function of the first kind, from its power series
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_x = x / 2.0;
+    for k in 1..200 {
+        term *= half_x / k as f64;
+        sum += term * term;
+        if term * term < sum * 1e-17 {
+            break;
+        }
+    }
+    sum
+}
@@ -0,0 +1,97 @@
+use a2::complex::Complex;
+use a2::prelude::*;
+use rodio::{buffer::SamplesBuffer, dynamic_mixer, Source};
+
+const SAMPLE_RATE: u32 = 44100;
+
+// A stereo source with an impulse on the left channel, and silence on the right
+fn left_impulse(frames: usize) -> SamplesBuffer<f32> {
+    let mut data = vec![0.0; frames * 2];
+    data[0] = 1.0;
+    SamplesBuffer::new(2, SAMPLE_RATE, data)
+}
+
+#[test]
+fn stereo_channels_are_filtered_independently() {
+    let (controller, mixer) = dynamic_mixer::mixer::<f32>(2, SAMPLE_RATE);
+    controller.add(left_impulse(64));
+
+    let lowpass = BiQuad::new(-0.5, 0.25, 0.3, 0.3, 0.3);
+    let output: Vec<f32> = lowpass.source_from(mixer).collect();
+
+    assert_eq!(output.len(), 128);
+    for frame in output.chunks(2) {
+        assert_eq!(frame[1], 0.0, "right channel picked up left channel history");
+    }
+    assert!(output.iter().step_by(2).skip(1).any(|s| *s != 0.0));
+}
+
+#[test]
+fn stereo_output_matches_mono_filtering() {
+    let (controller, mixer) = dynamic_mixer::mixer::<f32>(2, SAMPLE_RATE);
+    controller.add(left_impulse(64));
+
+    let biquad = BiQuad::new(-1.2, 0.5, 0.2, 0.4, 0.2);
+    let stereo: Vec<f32> = biquad.source_from(mixer).collect();
+
+    let mut mono_data = vec![0.0; 64];
+    mono_data[0] = 1.0;
+    let mono: Vec<f32> = biquad
+        .source_from(SamplesBuffer::new(1, SAMPLE_RATE, mono_data))
+        .collect();
+
+    let left: Vec<f32> = stereo.iter().step_by(2).copied().collect();
+    assert_eq!(left, mono);
+}
+
+#[test]
+fn biquad_source_keeps_source_format() {
+    let source = BiQuad::new(0.0, 0.0, 1.0, 0.0, 0.0).source_from(left_impulse(16));
+    assert_eq!(source.channels(), 2);
+    assert_eq!(source.sample_rate(), SAMPLE_RATE);
+    assert_eq!(source.collect::<Vec<_>>(), {
+        let mut data = vec![0.0; 32];
+        data[0] = 1.0;
+        data
+    });
+}
+
+#[test]
+fn poles_and_zeros() {
+    // Poles at 0.5 +- 0.5i, and a double zero at -1
+    let biquad = BiQuad::new(-1.0, 0.5, 1.0, 2.0, 1.0);
+    let mut poles = biquad.poles();
+    poles.sort_by(|a, b| a.im.total_cmp(&b.im));
+    assert!((poles[0] - Complex::new(0.5, -0.5)).magnitude() < 1e-6);
+    assert!((poles[1] - Complex::new(0.5, 0.5)).magnitude() < 1e-6);
+    for zero in biquad.zeros() {
+        assert!((zero - Complex::new(-1.0, 0.0)).magnitude() < 1e-3);
+    }
+
+    // Without a z^2 term, there's only one finite zero
+    let zeros = BiQuad::new(0.0, 0.0, 0.0, 1.0, 0.5).zeros();
+    assert_eq!(zeros.len(), 1);
+    assert!((zeros[0] - Complex::new(-0.5, 0.0)).magnitude() < 1e-6);
+}
+
+#[test]
+fn frequency_response_matches_filtered_sine() {
+    let lowpass = BiQuad::new(-0.5, 0.25, 0.3, 0.3, 0.3);
+    let response = lowpass.frequency_response(2000.0, SAMPLE_RATE);
+
+    let input: Vec<f32> = wave::sin
+        .source(SAMPLE_RATE)
+        .with_frequency(2000.0)
+        .take(4410)
+        .collect();
+    let output: Vec<f32> = lowpass
+        .source_from(SamplesBuffer::new(1, SAMPLE_RATE, input))
+        .collect();
+    // Once the filter settles, the output is the sine scaled and shifted by the response
+    let phase = response.arg();
+    for (n, y) in output.iter().enumerate().skip(1000) {
+        let t = n as f32 / SAMPLE_RATE as f32;
+        let expected = response.magnitude() * (std::f32::consts::TAU * 2000.0 * t + phase).sin();
+        assert!((y - expected).abs() < 1e-2, "{y} != {expected}");
+    }
+}
@@ -0,0 +1,111 @@
+// Fixtures shared by the midi tests
+#![allow(dead_code)]
+
+use a2::bevy_midi::output::*;
+use bevy::core::CorePlugin;
+use bevy::prelude::*;
+use std::sync::{Mutex, MutexGuard};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+// The tests check when messages are sent, which apps busy with their own midi threads
+// alongside would throw off, so only one app runs at a time
+static SERIAL: Mutex<()> = Mutex::new(());
+
+pub fn serial() -> MutexGuard<'static, ()> {
+    SERIAL.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[derive(Resource, Default)]
+struct Errors(Vec<MidiOutputError>);
+
+#[derive(Resource, Default)]
+struct Events(Vec<MidiOutputEvent>);
+
+fn collect(
+    mut error_reader: EventReader<MidiOutputError>,
+    mut errors: ResMut<Errors>,
+    mut event_reader: EventReader<MidiOutputEvent>,
+    mut events: ResMut<Events>,
+) {
+    errors.0.extend(error_reader.iter().cloned());
+    events.0.extend(event_reader.iter().cloned());
+}
+
+pub fn app(loopback: &Loopback) -> App {
+    app_with(MidiOutputSettings {
+        port_name: "test",
+        backend: MidiOutputBackend::Loopback(loopback.clone()),
+        poll_interval: None,
+        reconnect_buffer: 0,
+        virtual_port: None,
+    })
+}
+
+pub fn app_with(settings: MidiOutputSettings) -> App {
+    let mut app = App::new();
+    app.add_plugin(CorePlugin::default())
+        .insert_resource(settings)
+        .add_plugin(MidiOutputPlugin)
+        .init_resource::<Errors>()
+        .init_resource::<Events>()
+        .add_system(collect);
+    app
+}
+
+// Updates the app until `done`, since replies come back from another thread
+pub fn update_until(app: &mut App, done: impl Fn(&App) -> bool) {
+    let start = Instant::now();
+    loop {
+        app.update();
+        if done(app) {
+            return;
+        }
+        assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+        sleep(Duration::from_millis(1));
+    }
+}
+
+pub fn output(app: &App) -> &MidiOutput {
+    app.world.resource::<MidiOutput>()
+}
+
+pub fn port_names(app: &App) -> Vec<String> {
+    output(app).ports().iter().map(|(n, _)| n.clone()).collect()
+}
+
+pub fn is_connected(app: &App) -> bool {
+    app.world.resource::<MidiOutputConnections>().is_connected()
+}
+
+pub fn connection_status(app: &App, name: &str) -> Option<MidiConnectionStatus> {
+    app.world.resource::<MidiOutputConnections>().status(name)
+}
+
+pub fn errors(app: &App) -> &[MidiOutputError] {
+    &app.world.resource::<Errors>().0
+}
+
+pub fn events(app: &App) -> &[MidiOutputEvent] {
+    &app.world.resource::<Events>().0
+}
+
+// Starts an app and connects it to the port `name`
+pub fn connected_app(loopback: &Loopback, name: &str) -> App {
+    connect(app(loopback), name)
+}
+
+pub fn connect(mut app: App, name: &str) -> App {
+    update_until(&mut app, |app| port_names(app).iter().any(|n| n == name));
+    let (_, port) = output(&app)
+        .ports()
+        .iter()
+        .find(|(n, _)| n == name)
+        .unwrap()
+        .clone();
+    output(&app).connect(port).unwrap();
+    update_until(&mut app, |app| {
+        connection_status(app, name) == Some(MidiConnectionStatus::Connected)
+    });
+    app
+}
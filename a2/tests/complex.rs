@@ -0,0 +1,93 @@
+use a2::complex::*;
+use std::f64::consts::{FRAC_PI_2, PI};
+
+fn assert_close(a: Complex<f64>, b: Complex<f64>) {
+    assert!((a - b).magnitude() < 1e-9, "{a:?} != {b:?}");
+}
+
+#[test]
+fn arithmetic_identities() {
+    let a = Complex::new(3.0, -2.0);
+    let b = Complex::new(-0.5, 4.0);
+
+    assert_close(a - b + b, a);
+    assert_close(a / b * b, a);
+    assert_close(-a + a, Complex::ZERO);
+    assert_close(a * a.recip(), Complex::ONE);
+    assert_close(Complex::I * Complex::I, Complex::from(-1.0));
+    assert_eq!(a.conj(), Complex::new(3.0, 2.0));
+    assert_eq!((a * a.conj()).real, a.norm_sqr());
+
+    // Real numbers on either side
+    assert_close(2.0 * a, a + a);
+    assert_close(a * 2.0, a + a);
+    assert_close(1.0 / b, b.recip());
+    assert_close(1.0 - a, -(a - 1.0));
+
+    let mut c = a;
+    c += b;
+    c -= b;
+    c *= b;
+    c /= b;
+    c *= 2.0;
+    c /= 2.0;
+    assert_close(c, a);
+}
+
+#[test]
+fn polar_form() {
+    let z = Complex::from_polar(2.0, FRAC_PI_2);
+    assert_close(z, Complex::new(0.0, 2.0));
+    let (r, theta) = z.to_polar();
+    assert!((r - 2.0).abs() < 1e-12);
+    assert!((theta - FRAC_PI_2).abs() < 1e-12);
+    assert!((Complex::new(-1.0, 0.0).arg() - PI).abs() < 1e-12);
+}
+
+#[test]
+fn transcendental_functions() {
+    // Euler's identity
+    assert_close(Complex::new(0.0, PI).exp(), Complex::from(-1.0));
+
+    let z = Complex::new(1.5, -0.7);
+    assert_close(z.ln().exp(), z);
+    assert_close(z.sqrt() * z.sqrt(), z);
+    assert_close(z.powf(3.0), z * z * z);
+    assert_close(z.powc(Complex::from(2.0)), z * z);
+    assert_close(Complex::new(-4.0, 0.0).sqrt(), Complex::new(0.0, 2.0));
+    assert!(z.sqrt().real >= 0.0);
+    assert_eq!(Complex::<f64>::ZERO.powf(2.0), Complex::ZERO);
+}
+
+#[test]
+fn powers_of_zero() {
+    let zero = Complex::<f64>::ZERO;
+    assert_eq!(zero.powf(0.0), Complex::ONE);
+    assert_eq!(zero.powc(Complex::ZERO), Complex::ONE);
+    assert_eq!(Complex::new(1.5, -0.7).powf(0.0), Complex::ONE);
+    assert_eq!(zero.powc(Complex::new(2.0, 1.0)), Complex::ZERO);
+    assert_eq!(zero.powf(2.5), Complex::ZERO);
+    assert_eq!(zero.powf(-1.0), Complex::new(f64::INFINITY, 0.0));
+    assert!(!zero.powf(-1.0).is_finite());
+    assert!(!zero.powc(Complex::from(-1.0)).is_finite());
+    assert!(!zero.powc(Complex::new(0.0, 1.0)).is_finite());
+}
+
+#[test]
+fn sum_and_product() {
+    // The 8th roots of unity sum to zero, and multiply to -1
+    let roots: Vec<Complex<f64>> = (0..8)
+        .map(|k| Complex::cis(2.0 * PI * k as f64 / 8.0))
+        .collect();
+    assert_close(roots.iter().sum(), Complex::ZERO);
+    assert_close(roots.iter().product(), Complex::from(-1.0));
+    assert_close(roots.into_iter().sum(), Complex::ZERO);
+}
+
+#[test]
+fn f32_complex_still_works() {
+    let z: Complex = Complex::new(0.0, 1.0) * Complex::new(2.0, 1.0);
+    assert_eq!(z, Complex::new(-1.0f32, 2.0));
+    assert!((z.magnitude() - 5.0f32.sqrt()).abs() < 1e-6);
+    assert_eq!(format!("{z:?}"), "-1 + 2j");
+}
@@ -0,0 +1,126 @@
+use a2::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rodio::{buffer::SamplesBuffer, Source};
+use std::time::Duration;
+
+fn random_samples(rng: &mut ChaCha8Rng, len: usize) -> Vec<f32> {
+    (0..len).map(|_| rng.gen_range(-1.0..1.0)).collect()
+}
+
+// Full linear convolution, the slow way
+fn convolve(input: &[f32], impulse_response: &[f32]) -> Vec<f32> {
+    let mut output = vec![0.0; input.len() + impulse_response.len() - 1];
+    for (i, x) in input.iter().enumerate() {
+        for (j, h) in impulse_response.iter().enumerate() {
+            output[i + j] += x * h;
+        }
+    }
+    output
+}
+
+fn assert_close(actual: &[f32], expected: &[f32]) {
+    assert_eq!(actual.len(), expected.len());
+    for (i, (a, e)) in actual.iter().zip(expected).enumerate() {
+        assert!((a - e).abs() < 1e-3, "sample {i}: {a} != {e}");
+    }
+}
+
+#[test]
+fn short_impulse_responses_are_direct() {
+    let mut rng = ChaCha8Rng::seed_from_u64(38);
+    let input = random_samples(&mut rng, 500);
+    let impulse_response = random_samples(&mut rng, 20);
+
+    let convolution = Convolution::new(impulse_response.clone());
+    assert!(!convolution.is_partitioned());
+    let output: Vec<f32> = convolution
+        .source_from(SamplesBuffer::new(1, 44100, input.clone()))
+        .collect();
+    assert_close(&output, &convolve(&input, &impulse_response));
+}
+
+#[test]
+fn long_impulse_responses_are_partitioned() {
+    let mut rng = ChaCha8Rng::seed_from_u64(380);
+    for (input_len, ir_len, partition_len) in [(3000, 1000, 64), (777, 2049, 128), (100, 300, 16)] {
+        let input = random_samples(&mut rng, input_len);
+        let impulse_response = random_samples(&mut rng, ir_len);
+
+        let convolution =
+            Convolution::new(impulse_response.clone()).with_partition_len(partition_len);
+        assert!(convolution.is_partitioned());
+        let output: Vec<f32> = convolution
+            .source_from(SamplesBuffer::new(1, 44100, input.clone()))
+            .collect();
+        assert_close(&output, &convolve(&input, &impulse_response));
+    }
+}
+
+#[test]
+fn channels_are_convolved_independently() {
+    let mut rng = ChaCha8Rng::seed_from_u64(3800);
+    let left = random_samples(&mut rng, 400);
+    let right = random_samples(&mut rng, 400);
+    let impulse_response = random_samples(&mut rng, 150);
+    let interleaved: Vec<f32> = left
+        .iter()
+        .zip(&right)
+        .flat_map(|(l, r)| [*l, *r])
+        .collect();
+
+    let source = Convolution::new(impulse_response.clone())
+        .with_partition_len(32)
+        .source_from(SamplesBuffer::new(2, 44100, interleaved));
+    assert_eq!(source.channels(), 2);
+    let output: Vec<f32> = source.collect();
+    let output_left: Vec<f32> = output.iter().step_by(2).copied().collect();
+    let output_right: Vec<f32> = output.iter().skip(1).step_by(2).copied().collect();
+    assert_close(&output_left, &convolve(&left, &impulse_response));
+    assert_close(&output_right, &convolve(&right, &impulse_response));
+}
+
+#[test]
+fn tail_extends_the_duration() {
+    let source = SamplesBuffer::new(1, 1000, vec![1.0; 1000]);
+    let source = Convolution::new(vec![0.5; 251]).source_from(source);
+    assert_eq!(source.total_duration(), Some(Duration::from_millis(1250)));
+    assert_eq!(source.count(), 1250);
+
+    let infinite = Convolution::new(vec![0.5; 251]).source_from(wave::sin.source(1000));
+    assert_eq!(infinite.total_duration(), None);
+}
+
+// Writes a 16 bit PCM WAV file
+fn write_wav(path: &std::path::Path, channels: u16, sample_rate: u32, samples: &[i16]) {
+    let data_len = (samples.len() * 2) as u32;
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&channels.to_le_bytes());
+    bytes.extend_from_slice(&sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&(sample_rate * channels as u32 * 2).to_le_bytes());
+    bytes.extend_from_slice(&(channels * 2).to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+    std::fs::write(path, bytes).unwrap();
+}
+
+#[test]
+fn impulse_responses_load_from_wav() {
+    let path = std::env::temp_dir().join("a2_convolution_test_ir.wav");
+    // A stereo file, which mixes down to 0.5, 0.25, 0.0
+    write_wav(&path, 2, 44100, &[16384, 16384, 16384, 0, 16384, -16384]);
+    let convolution = Convolution::from_wav(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_close(convolution.impulse_response(), &[0.5, 0.25, 0.0]);
+
+    assert!(Convolution::from_wav("does_not_exist.wav").is_err());
+}
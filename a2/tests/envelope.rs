@@ -0,0 +1,330 @@
+use a2::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rodio::{buffer::SamplesBuffer, dynamic_mixer, Source};
+use std::{sync::Arc, time::Duration};
+
+const SAMPLE_RATE: u32 = 1000;
+
+#[test]
+fn stereo_envelope_advances_per_frame() {
+    let (controller, mixer) = dynamic_mixer::mixer::<f32>(2, SAMPLE_RATE);
+    controller.add(SamplesBuffer::new(2, SAMPLE_RATE, vec![1.0; 4000]));
+
+    let envelope = Envelope {
+        points: Arc::new(vec![(1.0, 1.0)]),
+    };
+    let output: Vec<f32> = envelope.source_from(mixer).collect();
+
+    // One second of audio at 1000 frames per second, with two samples per frame
+    let frames = output.len() / 2;
+    assert_eq!(output.len() % 2, 0);
+    assert_eq!(frames, 1000);
+}
+
+#[test]
+fn stereo_envelope_scales_both_channels_equally() {
+    let (controller, mixer) = dynamic_mixer::mixer::<f32>(2, SAMPLE_RATE);
+    controller.add(SamplesBuffer::new(2, SAMPLE_RATE, vec![1.0; 4000]));
+
+    let envelope = Envelope {
+        points: Arc::new(vec![(0.5, 1.0), (1.0, 0.0)]),
+    };
+    let output: Vec<f32> = envelope.source_from(mixer).collect();
+
+    for frame in output.chunks(2) {
+        assert_eq!(frame[0], frame[1]);
+    }
+    // The peak of the envelope is at half a second, i.e. frame 500
+    let peak = output
+        .chunks(2)
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a[0].total_cmp(&b[0]))
+        .map(|(i, _)| i)
+        .unwrap();
+    assert!((499..=501).contains(&peak), "peak at frame {peak}");
+}
+
+// A mono source of 1.0, so that the output of an envelope is just its height
+fn ones() -> SamplesBuffer<f32> {
+    SamplesBuffer::new(1, SAMPLE_RATE, vec![1.0; 10_000])
+}
+
+#[test]
+fn gated_adsr_holds_sustain_until_note_off() {
+    let adsr = GatedAdsr::new(1.0, 0.1, 0.1, 0.5, 0.2);
+    let (mut source, gate) = adsr.source_from(ones());
+
+    assert_eq!(source.next(), Some(0.0));
+
+    gate.note_on();
+    let attack_decay: Vec<f32> = source.by_ref().take(250).collect();
+    let peak = attack_decay.iter().copied().fold(0.0, f32::max);
+    assert!((peak - 1.0).abs() < 1e-3, "peak was {peak}");
+
+    // No matter how long the gate is held, the envelope stays at the sustain height
+    assert!(source.by_ref().take(3000).all(|s| (s - 0.5).abs() < 1e-3));
+
+    gate.note_off();
+    let release: Vec<f32> = source.by_ref().take(205).collect();
+    assert!(release.windows(2).all(|w| w[1] <= w[0]));
+    assert_eq!(*release.last().unwrap(), 0.0);
+    assert!(release[195] > 0.0, "release ended early");
+}
+
+#[test]
+fn gated_adsr_releases_from_current_level() {
+    let adsr = GatedAdsr::new(1.0, 1.0, 0.1, 0.5, 0.1);
+    let (mut source, gate) = adsr.source_from(ones());
+
+    // Release halfway through the attack
+    gate.note_on();
+    source.by_ref().take(500).count();
+    gate.note_off();
+
+    let release: Vec<f32> = source.by_ref().take(200).collect();
+    assert!((release[0] - 0.5).abs() < 1e-2, "release started at {}", release[0]);
+    assert!(release.iter().all(|s| *s <= release[0]));
+    assert_eq!(release[150], 0.0);
+}
+
+#[test]
+fn retrigger_restarts_attack_but_legato_does_not() {
+    for (mode, restarts) in [(TriggerMode::Retrigger, true), (TriggerMode::Legato, false)] {
+        let adsr = GatedAdsr::new(1.0, 0.1, 0.1, 0.5, 0.1).with_mode(mode);
+        let (mut source, gate) = adsr.source_from(ones());
+
+        gate.note_on();
+        source.by_ref().take(1000).count();
+        assert!(source.is_open());
+
+        // A second note while the first is held
+        gate.note_on();
+        let after: Vec<f32> = source.by_ref().take(100).collect();
+        let rose = after.iter().any(|s| *s > 0.6);
+        assert_eq!(rose, restarts, "{mode:?}");
+
+        // Releasing one of the two notes keeps the gate open
+        gate.note_off();
+        source.by_ref().take(1000).count();
+        assert!(source.is_open());
+        assert!((source.level() - 0.5).abs() < 1e-3);
+
+        gate.note_off();
+        source.by_ref().take(1000).count();
+        assert!(!source.is_open());
+        assert_eq!(source.level(), 0.0);
+    }
+}
+
+#[test]
+fn curves_keep_segment_endpoints() {
+    let curves = [
+        Curve::Linear,
+        Curve::Exponential(4.0),
+        Curve::Exponential(-4.0),
+        Curve::Logarithmic(10.0),
+        Curve::Hold,
+        Curve::Bezier(0.9, 0.1),
+    ];
+    for curve in curves {
+        assert_eq!(curve.apply(0.0), 0.0, "{curve:?}");
+        assert!((curve.apply(1.0) - 1.0).abs() < 1e-6, "{curve:?}");
+    }
+
+    // Exponential curves start slowly and logarithmic ones start quickly
+    assert!(Curve::Exponential(4.0).apply(0.5) < 0.5);
+    assert!(Curve::Exponential(-4.0).apply(0.5) > 0.5);
+    assert!(Curve::Logarithmic(10.0).apply(0.5) > 0.5);
+    assert_eq!(Curve::Hold.apply(0.99), 0.0);
+}
+
+#[test]
+fn sharp_exponential_curves_stay_finite() {
+    for shape in [100.0, -100.0, 1000.0, -1000.0] {
+        let curve = Curve::Exponential(shape);
+        let heights: Vec<f32> = (0..=100).map(|i| curve.apply(i as f32 / 100.0)).collect();
+        assert_eq!(heights[0], 0.0, "{curve:?}");
+        assert!((heights[100] - 1.0).abs() < 1e-6, "{curve:?}");
+        assert!(heights.iter().all(|h| h.is_finite()), "{curve:?}");
+        assert!(heights.windows(2).all(|w| w[1] >= w[0]), "{curve:?}");
+    }
+}
+
+#[test]
+fn curved_envelope_follows_its_segments() {
+    let envelope = ShapedEnvelope::from_segments([
+        (0.0, 0.0, Curve::Linear),
+        (0.5, 1.0, Curve::Exponential(5.0)),
+        (1.0, 0.0, Curve::Hold),
+    ]);
+    let output: Vec<f32> = envelope.source_from(ones()).collect();
+
+    // Slow start to the exponential attack
+    assert!(output[250] < 0.25, "attack was at {}", output[250]);
+    // The hold segment stays at the peak until the very end
+    assert!(output[500..999].iter().all(|s| (s - 1.0).abs() < 1e-6));
+
+    // Points given without curves are linear
+    let linear = Envelope::new(Arc::new(vec![(1.0, 1.0)])).with_curve(0, Curve::Linear);
+    let output: Vec<f32> = linear.source_from(ones()).collect();
+    assert!((output[500] - 0.5).abs() < 1e-2);
+}
+
+#[test]
+fn looping_envelope_cycles_forever() {
+    // A triangle LFO with a period of 0.2 seconds
+    let envelope = Envelope::new(Arc::new(vec![(0.0, 0.0), (0.1, 1.0), (0.2, 0.0)]))
+        .with_loop(0, 2);
+    let source = envelope.source_from(ones());
+    // The envelope never ends, so the source lasts as long as its inner source
+    assert_eq!(source.total_duration(), Some(Duration::from_secs(10)));
+
+    let output: Vec<f32> = source.take(1000).collect();
+    assert_eq!(output.len(), 1000);
+    for cycle in 1..5 {
+        let peak = output[cycle * 200 + 100];
+        assert!((peak - 1.0).abs() < 1e-2, "cycle {cycle} peaked at {peak}");
+    }
+}
+
+#[test]
+fn sustain_point_holds_until_gate_closes() {
+    let envelope = ShapedEnvelope::ahdsr(1.0, 0.1, 0.1, 0.1, 0.5, 0.2);
+    let (mut source, gate) = envelope.gated_source_from(ones());
+
+    source.by_ref().take(400).count();
+    assert!(source.by_ref().take(2000).all(|s| (s - 0.5).abs() < 1e-3));
+
+    gate.note_off();
+    let release: Vec<f32> = source.by_ref().collect();
+    assert_eq!(release.len(), 200);
+    assert!(release.windows(2).all(|w| w[1] <= w[0]));
+}
+
+#[test]
+fn envelopes_without_a_gate_play_through_their_sustain_point() {
+    let envelope = ShapedEnvelope::dahdsr(0.1, 1.0, 0.1, 0.1, 0.1, 0.5, 0.2);
+    let output: Vec<f32> = envelope.source_from(ones()).collect();
+
+    assert_eq!(output.len(), 600);
+    assert!(output[..100].iter().all(|s| *s == 0.0));
+    assert!((output[250] - 1.0).abs() < 1e-6);
+}
+
+#[test]
+fn envelope_ron_round_trip() {
+    let envelope = ShapedEnvelope::from_segments([
+        (0.0, 0.0, Curve::Linear),
+        (0.1, 1.0, Curve::Exponential(-3.0)),
+        (0.5, 0.2, Curve::Bezier(0.5, 0.0)),
+    ])
+    .with_loop(1, 2);
+    let text = ron::to_string(&envelope).unwrap();
+    let parsed: ShapedEnvelope = ron::from_str(&text).unwrap();
+    assert_eq!(parsed.envelope.points, envelope.envelope.points);
+    for i in 0..3 {
+        assert_eq!(parsed.curve(i), envelope.curve(i));
+    }
+    assert_eq!(parsed.loop_points, Some((1, 2)));
+
+    // Only the points are required
+    let text = "(envelope: (points: [(0.5, 1.0), (1.0, 0.0)]))";
+    let parsed: ShapedEnvelope = ron::from_str(text).unwrap();
+    assert_eq!(parsed.curve(1), Curve::Linear);
+    assert_eq!(parsed.sustain, None);
+    let parsed: Envelope = ron::from_str("(points: [(0.5, 1.0), (1.0, 0.0)])").unwrap();
+    assert_eq!(parsed.height_at(0.75), 0.5);
+}
+
+// A random one-shot envelope, along with its length in seconds
+fn random_envelope(rng: &mut impl Rng) -> (Envelope, f32) {
+    let mut time = 0.0;
+    let points: Vec<(f32, f32)> = (0..rng.gen_range(1..8))
+        .map(|_| {
+            // Some points share a time, to test vertical segments
+            if rng.gen_bool(0.8) {
+                time += rng.gen_range(0.0..0.5);
+            }
+            (time, rng.gen_range(0.0..1.0))
+        })
+        .collect();
+    (Envelope::new(Arc::new(points)), time)
+}
+
+#[test]
+fn envelope_plays_exact_number_of_samples() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    for _ in 0..200 {
+        let (envelope, length) = random_envelope(&mut rng);
+        let channels = rng.gen_range(1..=3);
+        let sample_rate = [1000, 8000, 22050, 44100][rng.gen_range(0..4)];
+
+        let input = SamplesBuffer::new(channels, sample_rate, vec![1.0; 10 * 44100]);
+        let source = envelope.source_from(input);
+        let expected_frames = (length as f64 * sample_rate as f64).round() as usize;
+        let expected_samples = expected_frames * channels as usize;
+
+        assert_eq!(source.current_frame_len(), Some(expected_samples));
+        assert_eq!(
+            source.total_duration(),
+            Some(Duration::from_secs_f64(
+                expected_frames as f64 / sample_rate as f64
+            ))
+        );
+
+        let output: Vec<f32> = source.collect();
+        assert_eq!(output.len(), expected_samples, "{:?}", envelope.points);
+        assert!(output.iter().all(|s| s.is_finite()));
+    }
+}
+
+#[test]
+fn envelope_time_is_monotonic() {
+    let mut rng = ChaCha8Rng::seed_from_u64(1);
+    for _ in 0..50 {
+        let (envelope, _) = random_envelope(&mut rng);
+        let mut source = envelope.source_from(SamplesBuffer::new(2, 8000, vec![1.0; 80_000]));
+
+        let mut last_time = source.time();
+        let mut last_frame_len = source.current_frame_len().unwrap();
+        while source.next().is_some() {
+            assert!(source.time() >= last_time);
+            last_time = source.time();
+
+            let frame_len = source.current_frame_len().unwrap();
+            assert_eq!(frame_len + 1, last_frame_len);
+            last_frame_len = frame_len;
+        }
+        assert_eq!(last_frame_len, 0);
+    }
+}
+
+#[test]
+fn envelope_ends_with_shorter_source() {
+    let envelope = Envelope::new(Arc::new(vec![(2.0, 1.0)]));
+    let source = envelope.source_from(SamplesBuffer::new(1, SAMPLE_RATE, vec![1.0; 500]));
+    assert_eq!(source.total_duration(), Some(Duration::from_millis(500)));
+    assert_eq!(source.count(), 500);
+}
+
+#[test]
+fn envelope_edge_cases() {
+    // A point at time 0 used to divide by zero
+    let envelope = Envelope::new(Arc::new(vec![(0.0, 0.5), (0.0, 1.0), (0.1, 0.0)]));
+    let output: Vec<f32> = envelope.source_from(ones()).collect();
+    assert_eq!(output.len(), 100);
+    assert_eq!(output[0], 1.0);
+
+    // Sampling at or past the last point holds its height
+    assert_eq!(envelope.height_at(0.1), 0.0);
+    assert_eq!(envelope.height_at(5.0), 0.0);
+
+    // An envelope without any points passes its source through untouched
+    let empty = Envelope::new(Arc::new(Vec::new()));
+    let source = empty.source_from(ones());
+    assert_eq!(source.total_duration(), Some(Duration::from_secs(10)));
+    let output: Vec<f32> = source.collect();
+    assert_eq!(output.len(), 10_000);
+    assert!(output.iter().all(|s| *s == 1.0));
+}
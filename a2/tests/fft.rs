@@ -0,0 +1,103 @@
+use a2::complex::Complex;
+use a2::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::f64::consts::TAU;
+
+fn naive_dft(input: &[Complex<f64>]) -> Vec<Complex<f64>> {
+    let n = input.len();
+    (0..n)
+        .map(|k| {
+            input
+                .iter()
+                .enumerate()
+                .map(|(j, x)| *x * Complex::cis(-TAU * (j * k) as f64 / n as f64))
+                .sum()
+        })
+        .collect()
+}
+
+fn random_signal(rng: &mut impl Rng, len: usize) -> Vec<Complex<f64>> {
+    (0..len)
+        .map(|_| Complex::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)))
+        .collect()
+}
+
+fn max_error(a: &[Complex<f64>], b: &[Complex<f64>]) -> f64 {
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (*x - *y).magnitude())
+        .fold(0.0, f64::max)
+}
+
+const LENGTHS: [usize; 16] = [1, 2, 3, 4, 5, 6, 7, 8, 12, 15, 16, 30, 49, 64, 97, 360];
+
+#[test]
+fn fft_matches_naive_dft() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    for len in LENGTHS {
+        let input = random_signal(&mut rng, len);
+        let mut output = input.clone();
+        FftPlan::new(len).forward(&mut output);
+        assert!(max_error(&output, &naive_dft(&input)) < 1e-9, "len {len}");
+    }
+}
+
+#[test]
+fn inverse_fft_round_trips() {
+    let mut rng = ChaCha8Rng::seed_from_u64(1);
+    for len in LENGTHS {
+        let input = random_signal(&mut rng, len);
+        let mut data = input.clone();
+        fft(&mut data);
+        ifft(&mut data);
+        assert!(max_error(&data, &input) < 1e-12, "len {len}");
+    }
+}
+
+#[test]
+fn real_fft_matches_complex_fft() {
+    let mut rng = ChaCha8Rng::seed_from_u64(2);
+    for len in LENGTHS {
+        let input: Vec<f64> = (0..len).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        let plan = RealFftPlan::new(len);
+        let spectrum = plan.forward(&input);
+
+        let mut full: Vec<_> = input.iter().map(|x| Complex::from(*x)).collect();
+        fft(&mut full);
+        assert_eq!(spectrum.len(), len / 2 + 1);
+        assert!(
+            max_error(&spectrum, &full[..len / 2 + 1]) < 1e-9,
+            "len {len}"
+        );
+
+        let output = plan.inverse(&spectrum);
+        let error = output
+            .iter()
+            .zip(&input)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f64::max);
+        assert!(error < 1e-12, "len {len}");
+    }
+}
+
+#[test]
+fn source_spectrum_finds_sine_frequency() {
+    // 1000Hz at 44100Hz with 4410 points puts the sine exactly on bin 100
+    let source = wave::sin
+        .wavetable(4096)
+        .source(44100)
+        .with_frequency(1000.0);
+    let spectrum = source.spectrum(4410);
+    let peak = (0..spectrum.len())
+        .max_by(|a, b| {
+            spectrum[*a]
+                .magnitude()
+                .total_cmp(&spectrum[*b].magnitude())
+        })
+        .unwrap();
+    assert_eq!(peak, 100);
+    assert_eq!(bin_frequency(peak, 4410, 44100), 1000.0);
+    // A unit sine shows up with a magnitude of len / 2
+    assert!((spectrum[peak].magnitude() - 2205.0).abs() < 5.0);
+}
@@ -0,0 +1,170 @@
+use a2::complex::Complex;
+use a2::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rodio::{buffer::SamplesBuffer, Source};
+use std::f32::consts::PI;
+
+const SAMPLE_RATE: u32 = 48000;
+
+fn gain(fir: &Fir, frequency: f32) -> f32 {
+    fir.frequency_response(frequency, SAMPLE_RATE).magnitude()
+}
+
+fn gain_db(fir: &Fir, frequency: f32) -> f32 {
+    20.0 * gain(fir, frequency).log10()
+}
+
+// Checks that the taps are (anti)symmetric, and that removing the delay from the
+// frequency response leaves it purely real (or imaginary)
+fn assert_linear_phase(fir: &Fir, antisymmetric: bool) {
+    let taps = fir.taps();
+    for (a, b) in taps.iter().zip(taps.iter().rev()) {
+        let expected = if antisymmetric { -b } else { *b };
+        assert!((a - expected).abs() < 1e-5, "{a} != {expected}");
+    }
+    for frequency in (1..24).map(|i| i as f32 * 1000.0) {
+        let omega = 2.0 * PI * frequency / SAMPLE_RATE as f32;
+        let zero_phase =
+            fir.frequency_response(frequency, SAMPLE_RATE) * Complex::cis(omega * fir.delay());
+        let leftover = if antisymmetric {
+            zero_phase.real
+        } else {
+            zero_phase.im
+        };
+        assert!(leftover.abs() < 1e-3, "{frequency}Hz: {zero_phase:?}");
+    }
+}
+
+#[test]
+fn windowed_sinc_responses() {
+    let window = Window::BlackmanHarris;
+    let lowpass = Fir::lowpass(101, 6000.0, SAMPLE_RATE, window);
+    assert!((gain(&lowpass, 0.0) - 1.0).abs() < 1e-4);
+    assert!((gain(&lowpass, 2000.0) - 1.0).abs() < 1e-3);
+    assert!(gain_db(&lowpass, 10000.0) < -80.0);
+    assert_linear_phase(&lowpass, false);
+
+    let highpass = Fir::highpass(101, 6000.0, SAMPLE_RATE, window);
+    assert!(gain_db(&highpass, 2000.0) < -80.0);
+    assert!((gain(&highpass, 12000.0) - 1.0).abs() < 1e-3);
+    assert!((gain(&highpass, 24000.0) - 1.0).abs() < 1e-3);
+    assert_linear_phase(&highpass, false);
+
+    let bandpass = Fir::bandpass(201, 4000.0, 8000.0, SAMPLE_RATE, window);
+    assert!(gain_db(&bandpass, 1000.0) < -80.0);
+    assert!((gain(&bandpass, 6000.0) - 1.0).abs() < 1e-3);
+    assert!(gain_db(&bandpass, 12000.0) < -80.0);
+    assert_linear_phase(&bandpass, false);
+
+    let bandstop = Fir::bandstop(201, 4000.0, 8000.0, SAMPLE_RATE, window);
+    assert!((gain(&bandstop, 1000.0) - 1.0).abs() < 1e-3);
+    assert!(gain_db(&bandstop, 6000.0) < -80.0);
+    assert!((gain(&bandstop, 12000.0) - 1.0).abs() < 1e-3);
+    assert_linear_phase(&bandstop, false);
+
+    // Even lengths are fine for lowpass filters, with a half sample delay
+    let even = Fir::lowpass(64, 6000.0, SAMPLE_RATE, Window::Hamming);
+    assert_eq!(even.delay(), 31.5);
+    assert_linear_phase(&even, false);
+}
+
+#[test]
+#[should_panic]
+fn even_highpass_panics() {
+    let _ = Fir::highpass(100, 6000.0, SAMPLE_RATE, Window::Hann);
+}
+
+#[test]
+fn hilbert_shifts_phase() {
+    let hilbert = Fir::hilbert(101, Window::BlackmanHarris);
+    assert_linear_phase(&hilbert, true);
+    for frequency in [2000.0, 6000.0, 12000.0, 20000.0] {
+        let omega = 2.0 * PI * frequency / SAMPLE_RATE as f32;
+        let zero_phase = hilbert.frequency_response(frequency, SAMPLE_RATE)
+            * Complex::cis(omega * hilbert.delay());
+        // -i for positive frequencies
+        assert!(
+            (zero_phase.im + 1.0).abs() < 1e-2,
+            "{frequency}Hz: {zero_phase:?}"
+        );
+    }
+}
+
+#[test]
+fn parks_mcclellan_is_equiripple() {
+    let bands = [
+        Band::new(0.0, 8000.0, 1.0),
+        Band::new(10000.0, 24000.0, 0.0).with_weight(10.0),
+    ];
+    for len in [51, 52] {
+        let fir = Fir::parks_mcclellan(len, &bands, SAMPLE_RATE);
+        assert_eq!(fir.len(), len);
+        assert_linear_phase(&fir, false);
+
+        let passband: Vec<f32> = (0..=80).map(|i| gain(&fir, i as f32 * 100.0)).collect();
+        let stopband: Vec<f32> = (0..=140)
+            .map(|i| gain(&fir, 10000.0 + i as f32 * 100.0))
+            .collect();
+        let passband_ripple = passband
+            .iter()
+            .fold(0.0f32, |max, g| max.max((g - 1.0).abs()));
+        let stopband_ripple = stopband.iter().fold(0.0f32, |max, g| max.max(*g));
+        assert!(passband_ripple < 0.05, "{len}: {passband_ripple}");
+        // The stopband is weighted ten times more, so its ripple is ten times smaller
+        let ratio = passband_ripple / stopband_ripple;
+        assert!((ratio - 10.0).abs() < 1.0, "{len}: {ratio}");
+    }
+}
+
+#[test]
+fn parks_mcclellan_beats_windowed_sinc() {
+    // With the same length and transition band, the equiripple stopband is lower
+    let equiripple = Fir::parks_mcclellan(
+        61,
+        &[Band::new(0.0, 5000.0, 1.0), Band::new(7000.0, 24000.0, 0.0)],
+        SAMPLE_RATE,
+    );
+    let windowed = Fir::lowpass(61, 6000.0, SAMPLE_RATE, Window::Hamming);
+    let worst_stopband = |fir: &Fir| {
+        (70..240)
+            .map(|i| gain_db(fir, i as f32 * 100.0))
+            .fold(f32::MIN, f32::max)
+    };
+    assert!(worst_stopband(&equiripple) < worst_stopband(&windowed) - 3.0);
+}
+
+#[test]
+fn source_matches_direct_convolution() {
+    let mut rng = ChaCha8Rng::seed_from_u64(39);
+    let taps: Vec<f32> = (0..17).map(|_| rng.gen_range(-1.0..1.0)).collect();
+    let input: Vec<f32> = (0..300).map(|_| rng.gen_range(-1.0..1.0)).collect();
+    let interleaved: Vec<f32> = input.iter().flat_map(|x| [*x, -*x]).collect();
+
+    let source =
+        Fir::new(taps.clone()).source_from(SamplesBuffer::new(2, SAMPLE_RATE, interleaved));
+    assert_eq!(source.channels(), 2);
+    let output: Vec<f32> = source.collect();
+    assert_eq!(output.len(), 600);
+    for n in 0..300 {
+        let expected: f32 = (0..taps.len().min(n + 1))
+            .map(|k| taps[k] * input[n - k])
+            .sum();
+        assert!((output[2 * n] - expected).abs() < 1e-5);
+        assert!((output[2 * n + 1] + expected).abs() < 1e-5);
+    }
+}
+
+#[test]
+fn linear_phase_delays_evenly() {
+    // A lowpassed sine comes out as the same sine, delayed by half the filter
+    let fir = Fir::lowpass(101, 4000.0, SAMPLE_RATE, Window::BlackmanHarris);
+    let sine = |n: usize| (2.0 * PI * 1000.0 * n as f32 / SAMPLE_RATE as f32).sin();
+    let input: Vec<f32> = (0..1000).map(sine).collect();
+    let output: Vec<f32> = fir
+        .source_from(SamplesBuffer::new(1, SAMPLE_RATE, input))
+        .collect();
+    for (n, y) in output.iter().enumerate().skip(100) {
+        assert!((y - sine(n - 50)).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,155 @@
+use a2::prelude::*;
+use rodio::{buffer::SamplesBuffer, Source};
+use std::f32::consts::{FRAC_PI_4, PI};
+use std::time::Duration;
+
+fn sine(frequency: f32, amplitude: f32, len: usize) -> Vec<f32> {
+    wave::sin
+        .source(44100)
+        .with_frequency(frequency)
+        .take(len)
+        .map(|x| amplitude * x)
+        .collect()
+}
+
+#[test]
+fn sine_levels() {
+    let samples = sine(1000.0, 0.5, 44100);
+    assert!((rms(&samples) - 0.5 / 2f32.sqrt()).abs() < 1e-3);
+    assert!((peak(&samples) - 0.5).abs() < 1e-3);
+    assert!((crest_factor(&samples) - 2f32.sqrt()).abs() < 1e-2);
+    assert!((zero_crossing_rate(&samples) - 2000.0 / 44100.0).abs() < 1e-3);
+}
+
+#[test]
+fn silence_levels() {
+    let samples = [0.0; 64];
+    assert_eq!(rms(&samples), 0.0);
+    assert_eq!(peak(&samples), 0.0);
+    assert_eq!(true_peak(&samples), 0.0);
+    assert_eq!(crest_factor(&samples), 0.0);
+    assert_eq!(zero_crossing_rate(&samples), 0.0);
+    assert_eq!(rms(&[]), 0.0);
+}
+
+#[test]
+fn true_peak_finds_peaks_between_samples() {
+    // A sine at a quarter of the sample rate, sampled halfway between its peaks
+    let samples: Vec<f32> = (0..256)
+        .map(|n| (PI / 2.0 * n as f32 + FRAC_PI_4).sin())
+        .collect();
+    assert!((peak(&samples) - FRAC_PI_4.sin()).abs() < 1e-4);
+    assert!(
+        (true_peak(&samples) - 1.0).abs() < 0.02,
+        "{}",
+        true_peak(&samples)
+    );
+}
+
+#[test]
+fn frames_cover_the_whole_source() {
+    let source = SamplesBuffer::new(1, 1000, vec![0.5; 1000]);
+    let features = Meter::new(256, 128).measure(source);
+    // Frames start at every multiple of 128 up to the last one which reaches the end
+    assert_eq!(features.len(), 7);
+    assert_eq!(features[0].time, 0.128);
+    assert_eq!(features[1].time, 0.256);
+    assert!((features[0].rms - 0.5).abs() < 1e-6);
+    // The last frame is padded with zeroes
+    assert!(features[6].rms < 0.5);
+
+    let source = SamplesBuffer::new(1, 1000, Vec::new());
+    assert!(Meter::new(256, 128).measure(source).is_empty());
+}
+
+#[test]
+fn sine_spectrum() {
+    let samples = sine(1000.0, 1.0, 8192);
+    for features in Meter::new(2048, 2048).measure_samples(&samples, 44100) {
+        assert!(
+            (features.spectral_centroid - 1000.0).abs() < 30.0,
+            "{features:?}"
+        );
+        assert!(
+            (features.spectral_rolloff - 1000.0).abs() < 30.0,
+            "{features:?}"
+        );
+        assert!(features.spectral_flatness < 0.01, "{features:?}");
+    }
+}
+
+#[test]
+fn noise_is_flat_and_bright() {
+    let samples: Vec<f32> = wave::noise.source(44100).take(8192).collect();
+    let source = SamplesBuffer::new(1, 44100, samples);
+    for features in Meter::new(2048, 2048).measure(source) {
+        assert!(features.spectral_flatness > 0.3, "{features:?}");
+        assert!(
+            (features.spectral_centroid - 11025.0).abs() < 2000.0,
+            "{features:?}"
+        );
+        assert!(features.zero_crossing_rate > 0.3, "{features:?}");
+    }
+}
+
+#[test]
+fn silent_frames() {
+    let source = SamplesBuffer::new(1, 44100, vec![0.0; 1024]);
+    let features = Meter::new(512, 512).measure(source);
+    assert_eq!(features.len(), 2);
+    for features in features {
+        assert_eq!(features.rms, 0.0);
+        assert_eq!(features.spectral_centroid, 0.0);
+        assert_eq!(features.spectral_flatness, 1.0);
+    }
+}
+
+#[test]
+fn windowed_out_frames_keep_their_levels() {
+    // The Hann window is zero at the start of the frame, leaving no spectrum
+    let mut samples = vec![0.0; 512];
+    samples[0] = 0.5;
+    let features = Meter::new(512, 512).measure_samples(&samples, 44100);
+    assert_eq!(features.len(), 1);
+    assert_eq!(features[0].peak, 0.5);
+    assert!(features[0].rms > 0.0);
+    assert_eq!(features[0].spectral_centroid, 0.0);
+    assert_eq!(features[0].spectral_flatness, 1.0);
+}
+
+#[test]
+fn lowpass_reduces_brightness() {
+    // The lowpass filter from the subtractive synth example
+    let lowpass = BiQuad::new(
+        -7.173_366e-17,
+        0.171_499_6,
+        0.292_874_9,
+        0.585_749_8,
+        0.292_874_9,
+    );
+    let saw = wave::sawtooth
+        .wavetable(1024)
+        .source(44100)
+        .with_frequency(220.0);
+    let meter = Meter::new(4096, 4096);
+
+    let mean_centroid = |features: Vec<Features>| {
+        features.iter().map(|f| f.spectral_centroid).sum::<f32>() / features.len() as f32
+    };
+    let duration = Duration::from_millis(500);
+    let pure = mean_centroid(meter.measure(saw.clone().take_duration(duration)));
+    let filtered = mean_centroid(meter.measure(lowpass.source_from(saw).take_duration(duration)));
+    assert!(filtered < 0.8 * pure, "{filtered} vs {pure}");
+}
+
+#[test]
+fn stereo_sources_are_mixed_down() {
+    let samples: Vec<f32> = sine(440.0, 1.0, 4096)
+        .into_iter()
+        .flat_map(|x| [x, -x])
+        .collect();
+    let source = SamplesBuffer::new(2, 44100, samples);
+    for features in Meter::new(1024, 1024).measure(source) {
+        assert_eq!(features.rms, 0.0);
+    }
+}
@@ -0,0 +1,99 @@
+use a2::bevy_midi::clock::{ClockFollower, PPQN};
+use a2::bevy_midi::message::MidiMessage;
+use std::time::{Duration, Instant};
+
+// The length of a timing clock at `tempo`
+fn period(tempo: f64) -> Duration {
+    Duration::from_secs_f64(60.0 / (tempo * PPQN as f64))
+}
+
+// Feeds `count` timing clocks at `tempo` starting at `start`, each off by up to
+// `jitter` either way, returning when the last one arrived
+fn pulses(
+    follower: &mut ClockFollower,
+    start: Instant,
+    tempo: f64,
+    count: u32,
+    jitter: Duration,
+) -> Instant {
+    let mut at = start;
+    for i in 0..count {
+        at = start + period(tempo) * i;
+        at = if i % 2 == 0 { at + jitter } else { at - jitter };
+        assert!(follower.update(&MidiMessage::TimingClock, at));
+    }
+    at
+}
+
+#[test]
+fn estimates_tempo_through_jitter() {
+    let mut follower = ClockFollower::new();
+    let start = Instant::now() + Duration::from_millis(10);
+    follower.update(&MidiMessage::Start, start);
+    let last = pulses(&mut follower, start, 120.0, 97, Duration::from_millis(1));
+
+    let tempo = follower.tempo().unwrap();
+    assert!((tempo - 120.0).abs() < 1.0, "tempo was {}", tempo);
+    assert_eq!(follower.pulse(), Some(96));
+    let clock = follower.beat_clock().unwrap();
+    let beat = clock.beat_at(last);
+    assert!((beat - 4.0).abs() < 0.05, "beat was {}", beat);
+}
+
+#[test]
+fn follows_start_stop_and_continue() {
+    let mut follower = ClockFollower::new();
+    let start = Instant::now();
+    assert!(!follower.is_playing());
+    follower.update(&MidiMessage::Start, start);
+    assert!(follower.is_playing());
+    let last = pulses(&mut follower, start, 120.0, 24, Duration::ZERO);
+    assert_eq!(follower.pulse(), Some(23));
+
+    follower.update(&MidiMessage::Stop, last);
+    assert!(!follower.is_playing());
+    assert!(follower.beat_clock().is_none());
+
+    // Two beats in
+    follower.update(&MidiMessage::SongPosition(8), last);
+    follower.update(&MidiMessage::Continue, last);
+    assert_eq!(follower.pulse(), None);
+    let later = last + Duration::from_secs(1);
+    follower.update(&MidiMessage::TimingClock, later);
+    assert_eq!(follower.pulse(), Some(48));
+    let clock = follower.beat_clock().unwrap();
+    assert!((clock.beat_at(later) - 2.0).abs() < 1e-6);
+}
+
+#[test]
+fn clocks_while_stopped_only_estimate_tempo() {
+    let mut follower = ClockFollower::new();
+    pulses(&mut follower, Instant::now(), 90.0, 10, Duration::ZERO);
+    assert!((follower.tempo().unwrap() - 90.0).abs() < 1e-6);
+    assert_eq!(follower.pulse(), None);
+    assert!(follower.beat_clock().is_none());
+}
+
+#[test]
+fn follows_tempo_jumps_at_once() {
+    let mut follower = ClockFollower::new();
+    let start = Instant::now();
+    follower.update(&MidiMessage::Start, start);
+    let last = pulses(&mut follower, start, 120.0, 24, Duration::ZERO);
+    pulses(&mut follower, last + period(50.0), 50.0, 2, Duration::ZERO);
+    assert!((follower.tempo().unwrap() - 50.0).abs() < 1e-6);
+}
+
+#[test]
+fn only_clock_messages_count() {
+    let mut follower = ClockFollower::new();
+    let note = MidiMessage::note_on(0, 60, 100).unwrap();
+    assert!(!follower.update(&note, Instant::now()));
+    assert!(follower.update(&MidiMessage::Start, Instant::now()));
+}
+
+#[test]
+#[should_panic]
+fn smoothing_must_be_positive() {
+    let _ = ClockFollower::new().with_smoothing(0.0);
+}
@@ -0,0 +1,156 @@
+use a2::bevy_midi::message::*;
+
+#[test]
+fn constructors_validate_ranges() {
+    assert!(MidiMessage::note_on(15, 127, 127).is_ok());
+    assert_eq!(
+        MidiMessage::note_on(16, 60, 100),
+        Err(MidiMessageError::ChannelOutOfRange(16))
+    );
+    assert_eq!(
+        MidiMessage::note_off(0, 128, 0),
+        Err(MidiMessageError::DataOutOfRange(128))
+    );
+    assert!(MidiMessage::control_change(0, 120, 0).is_err());
+    assert!(MidiMessage::pitch_bend(0, 16383).is_ok());
+    assert!(MidiMessage::pitch_bend(0, 16384).is_err());
+    assert!(MidiMessage::sysex(vec![0x7E, 0x00]).is_ok());
+    assert!(MidiMessage::sysex(vec![0x7E, 0xF7]).is_err());
+}
+
+#[test]
+fn to_bytes() {
+    let note_on = MidiMessage::note_on(3, 60, 100).unwrap();
+    assert_eq!(note_on.to_bytes(), vec![0x93, 60, 100]);
+    let bend = MidiMessage::pitch_bend(0, 8192).unwrap();
+    assert_eq!(bend.to_bytes(), vec![0xE0, 0x00, 0x40]);
+    let notes_off = MidiMessage::all_notes_off(1).unwrap();
+    assert_eq!(notes_off.to_bytes(), vec![0xB1, 123, 0]);
+    let sysex = MidiMessage::sysex(vec![1, 2, 3]).unwrap();
+    assert_eq!(sysex.to_bytes(), vec![0xF0, 1, 2, 3, 0xF7]);
+    assert_eq!(MidiMessage::TimingClock.to_bytes(), vec![0xF8]);
+}
+
+fn every_kind() -> Vec<MidiMessage> {
+    vec![
+        MidiMessage::note_off(0, 60, 64).unwrap(),
+        MidiMessage::note_on(1, 61, 100).unwrap(),
+        MidiMessage::poly_pressure(2, 62, 30).unwrap(),
+        MidiMessage::control_change(3, 7, 90).unwrap(),
+        MidiMessage::program_change(4, 12).unwrap(),
+        MidiMessage::channel_pressure(5, 40).unwrap(),
+        MidiMessage::pitch_bend(6, 12345).unwrap(),
+        MidiMessage::all_sound_off(7).unwrap(),
+        MidiMessage::reset_all_controllers(8).unwrap(),
+        MidiMessage::local_control(9, true).unwrap(),
+        MidiMessage::all_notes_off(10).unwrap(),
+        MidiMessage::omni_off(11).unwrap(),
+        MidiMessage::omni_on(12).unwrap(),
+        MidiMessage::mono_on(13, 4).unwrap(),
+        MidiMessage::poly_on(14).unwrap(),
+        MidiMessage::sysex(vec![0x7D, 1, 2]).unwrap(),
+        MidiMessage::time_code_quarter_frame(0x35).unwrap(),
+        MidiMessage::song_position(1000).unwrap(),
+        MidiMessage::song_select(3).unwrap(),
+        MidiMessage::TuneRequest,
+        MidiMessage::TimingClock,
+        MidiMessage::Start,
+        MidiMessage::Continue,
+        MidiMessage::Stop,
+        MidiMessage::ActiveSensing,
+        MidiMessage::Reset,
+    ]
+}
+
+#[test]
+fn round_trips() {
+    for message in every_kind() {
+        assert_eq!(MidiMessage::from_bytes(&message.to_bytes()), Ok(message));
+    }
+}
+
+#[test]
+fn sysex_data_is_masked_to_seven_bits() {
+    let sysex = MidiMessage::SysEx(vec![0x01, 0x80, 0xF7, 0xF8]);
+    let bytes = sysex.to_bytes();
+    assert_eq!(bytes, vec![0xF0, 0x01, 0x00, 0x77, 0x78, 0xF7]);
+    let masked = MidiMessage::sysex(vec![0x01, 0x00, 0x77, 0x78]).unwrap();
+    assert_eq!(MidiMessage::from_bytes(&bytes), Ok(masked));
+}
+
+#[test]
+fn stream_round_trips_with_running_status() {
+    let messages = every_kind();
+    let bytes = MidiMessage::stream_to_bytes(&messages);
+    assert_eq!(MidiMessage::stream_from_bytes(&bytes), Ok(messages));
+}
+
+#[test]
+fn running_status_drops_repeated_status_bytes() {
+    let messages = vec![
+        MidiMessage::note_on(0, 60, 100).unwrap(),
+        MidiMessage::note_on(0, 64, 100).unwrap(),
+        MidiMessage::TimingClock,
+        MidiMessage::note_on(0, 67, 100).unwrap(),
+        MidiMessage::note_on(1, 60, 100).unwrap(),
+    ];
+    let bytes = MidiMessage::stream_to_bytes(&messages);
+    assert_eq!(
+        bytes,
+        vec![0x90, 60, 100, 64, 100, 0xF8, 67, 100, 0x91, 60, 100]
+    );
+    assert_eq!(MidiMessage::stream_from_bytes(&bytes), Ok(messages));
+}
+
+#[test]
+fn realtime_messages_interrupt_others() {
+    let bytes = [0x90, 60, 0xF8, 100, 0xF0, 1, 0xFA, 2, 0xF7];
+    assert_eq!(
+        MidiMessage::stream_from_bytes(&bytes),
+        Ok(vec![
+            MidiMessage::TimingClock,
+            MidiMessage::note_on(0, 60, 100).unwrap(),
+            MidiMessage::Start,
+            MidiMessage::sysex(vec![1, 2]).unwrap(),
+        ])
+    );
+}
+
+#[test]
+fn parse_errors() {
+    assert_eq!(MidiMessage::from_bytes(&[]), Err(MidiMessageError::Empty));
+    assert_eq!(
+        MidiMessage::from_bytes(&[0x90, 60]),
+        Err(MidiMessageError::Incomplete(0x90))
+    );
+    assert_eq!(
+        MidiMessage::from_bytes(&[60, 100]),
+        Err(MidiMessageError::MissingStatus(60))
+    );
+    assert_eq!(
+        MidiMessage::from_bytes(&[0xF4]),
+        Err(MidiMessageError::UndefinedStatus(0xF4))
+    );
+    assert_eq!(
+        MidiMessage::from_bytes(&[0xC0, 1, 2]),
+        Err(MidiMessageError::TrailingBytes(1))
+    );
+    // System common messages cancel running status
+    assert_eq!(
+        MidiMessage::stream_from_bytes(&[0x90, 60, 100, 0xF6, 61, 100]),
+        Err(MidiMessageError::MissingStatus(61))
+    );
+}
+
+#[test]
+fn parser_recovers_after_errors() {
+    let mut parser = MidiParser::new();
+    assert_eq!(parser.push(0x90), Ok(None));
+    assert_eq!(parser.push(60), Ok(None));
+    assert_eq!(parser.push(0x80), Err(MidiMessageError::Incomplete(0x90)));
+    assert_eq!(parser.push(60), Ok(None));
+    assert_eq!(
+        parser.push(0),
+        Ok(Some(MidiMessage::note_off(0, 60, 0).unwrap()))
+    );
+}
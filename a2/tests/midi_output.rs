@@ -0,0 +1,673 @@
+mod common;
+
+use a2::bevy_midi::message::{MidiMessage, MidiMessageError};
+use a2::bevy_midi::output::*;
+use a2::bevy_midi::schedule::BeatClock;
+use bevy::prelude::*;
+use common::*;
+use midir::{ConnectErrorKind, SendError};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+fn polling_app(loopback: &Loopback, reconnect_buffer: usize) -> App {
+    app_with(MidiOutputSettings {
+        port_name: "test",
+        backend: MidiOutputBackend::Loopback(loopback.clone()),
+        poll_interval: Some(Duration::from_millis(5)),
+        reconnect_buffer,
+        virtual_port: None,
+    })
+}
+
+#[test]
+fn lists_ports_on_startup() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    loopback.add_port("b");
+    let mut app = app(&loopback);
+    update_until(&mut app, |app| !port_names(app).is_empty());
+    assert_eq!(port_names(&app), vec!["a", "b"]);
+    assert!(!is_connected(&app));
+}
+
+#[test]
+fn connects_and_sends() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    loopback.add_port("b");
+    let mut app = connected_app(&loopback, "b");
+    assert_eq!(loopback.connected_ports(), vec!["b"]);
+
+    output(&app).send([0x90, 60, 100]).unwrap();
+    output(&app).send_message(MidiMessage::note_off(0, 60, 0).unwrap()).unwrap();
+    update_until(&mut app, |_| loopback.sent().len() == 2);
+    assert_eq!(loopback.sent(), vec![vec![0x90, 60, 100], vec![0x80, 60, 0]]);
+
+    output(&app).disconnect("b").unwrap();
+    update_until(&mut app, |app| !is_connected(app));
+    assert!(loopback.connected_ports().is_empty());
+    assert!(errors(&app).is_empty());
+}
+
+#[test]
+fn refreshing_finds_new_and_removed_ports() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    let mut app = connected_app(&loopback, "a");
+
+    loopback.add_port("b");
+    output(&app).refresh_ports().unwrap();
+    update_until(&mut app, |app| port_names(app).len() == 2);
+    assert!(is_connected(&app));
+
+    loopback.remove_port("b");
+    output(&app).refresh_ports().unwrap();
+    update_until(&mut app, |app| port_names(app).len() == 1);
+    assert!(is_connected(&app));
+    assert!(errors(&app).is_empty());
+}
+
+#[test]
+fn failed_connect_reports_an_error() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    let mut app = app(&loopback);
+    update_until(&mut app, |app| !port_names(app).is_empty());
+
+    loopback.fail_next_connect(ConnectErrorKind::Other("busy"));
+    let (_, port) = output(&app).ports()[0].clone();
+    output(&app).connect(port).unwrap();
+    update_until(&mut app, |app| !errors(app).is_empty());
+    assert!(matches!(
+        errors(&app),
+        [MidiOutputError::ConnectionError(ConnectErrorKind::Other("busy"))]
+    ));
+    assert!(!is_connected(&app));
+}
+
+#[test]
+fn failed_connect_while_connected_disconnects() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    let mut app = connected_app(&loopback, "a");
+
+    loopback.fail_next_connect(ConnectErrorKind::Other("busy"));
+    let (_, port) = output(&app).ports()[0].clone();
+    output(&app).connect(port).unwrap();
+    update_until(&mut app, |app| !is_connected(app) && !errors(app).is_empty());
+    assert_eq!(errors(&app).len(), 1);
+    assert!(loopback.connected_ports().is_empty());
+}
+
+#[test]
+fn removed_port_disconnects_on_refresh() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    let mut app = connected_app(&loopback, "a");
+
+    loopback.remove_port("a");
+    output(&app).refresh_ports().unwrap();
+    update_until(&mut app, |app| !is_connected(app) && !events(app).is_empty());
+    assert!(port_names(&app).is_empty());
+    assert_eq!(events(&app), [MidiOutputEvent::PortRemoved("a".into())]);
+    assert!(errors(&app).is_empty());
+}
+
+#[test]
+fn sending_to_a_removed_port_disconnects() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    let mut app = connected_app(&loopback, "a");
+
+    loopback.remove_port("a");
+    output(&app).send([0x90, 60, 100]).unwrap();
+    update_until(&mut app, |app| !is_connected(app) && !errors(app).is_empty());
+    assert_eq!(events(&app), [MidiOutputEvent::PortRemoved("a".into())]);
+    assert!(matches!(
+        errors(&app),
+        [MidiOutputError::SendDisconnectedError(_)]
+    ));
+}
+
+#[test]
+fn polling_finds_ports_coming_and_going() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    let mut app = polling_app(&loopback, 0);
+    update_until(&mut app, |app| !port_names(app).is_empty());
+
+    loopback.add_port("b");
+    update_until(&mut app, |app| port_names(app).len() == 2);
+    loopback.remove_port("a");
+    update_until(&mut app, |app| port_names(app) == ["b"] && events(app).len() == 2);
+    assert_eq!(
+        events(&app),
+        [
+            MidiOutputEvent::PortAdded("b".into()),
+            MidiOutputEvent::PortRemoved("a".into())
+        ]
+    );
+}
+
+#[test]
+fn reconnects_by_name() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    let mut app = connect(polling_app(&loopback, 0), "a");
+
+    loopback.remove_port("a");
+    update_until(&mut app, |app| !is_connected(app));
+    loopback.add_port("b");
+    loopback.add_port("a");
+    update_until(&mut app, is_connected);
+    update_until(&mut app, |app| events(app).len() == 4);
+    assert_eq!(
+        events(&app)[3],
+        MidiOutputEvent::Reconnected("a".into())
+    );
+    assert_eq!(loopback.connected_ports(), vec!["a"]);
+
+    // Disconnecting on purpose stops reconnecting
+    output(&app).disconnect("a").unwrap();
+    update_until(&mut app, |app| !is_connected(app));
+    loopback.remove_port("a");
+    update_until(&mut app, |app| events(app).len() == 5);
+    loopback.add_port("a");
+    update_until(&mut app, |app| events(app).len() == 6);
+    sleep(Duration::from_millis(20));
+    app.update();
+    assert!(!is_connected(&app));
+}
+
+#[test]
+fn buffers_messages_until_reconnected() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    let mut app = connect(polling_app(&loopback, 2), "a");
+
+    loopback.remove_port("a");
+    update_until(&mut app, |app| !is_connected(app));
+    output(&app).send([0x90, 60, 100]).unwrap();
+    output(&app).send([0x90, 61, 100]).unwrap();
+    output(&app).send([0x90, 62, 100]).unwrap();
+    update_until(&mut app, |app| !errors(app).is_empty());
+    match &errors(&app)[0] {
+        MidiOutputError::SendDisconnectedError(msg) => assert_eq!(msg, &vec![0x90, 60, 100]),
+        e => panic!("unexpected error {}", e),
+    }
+    assert!(loopback.sent().is_empty());
+
+    loopback.add_port("a");
+    update_until(&mut app, |_| loopback.sent().len() == 2);
+    assert_eq!(
+        loopback.sent(),
+        vec![vec![0x90, 61, 100], vec![0x90, 62, 100]]
+    );
+}
+
+#[test]
+fn send_errors_are_reported() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    let mut app = connected_app(&loopback, "a");
+
+    loopback.fail_next_send(SendError::InvalidData("bad"));
+    output(&app).send([0x90, 60, 100]).unwrap();
+    output(&app).send([0x80, 60, 0]).unwrap();
+    update_until(&mut app, |app| {
+        loopback.sent().len() == 1 && !errors(app).is_empty()
+    });
+    assert_eq!(loopback.sent(), vec![vec![0x80, 60, 0]]);
+    assert!(matches!(
+        errors(&app),
+        [MidiOutputError::SendError(SendError::InvalidData("bad"))]
+    ));
+    assert!(is_connected(&app));
+}
+
+#[test]
+fn sending_while_disconnected_is_an_error() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    let mut app = app(&loopback);
+    app.update();
+    output(&app).send([0x90, 60, 100]).unwrap();
+    update_until(&mut app, |app| !errors(app).is_empty());
+    match &errors(&app)[0] {
+        MidiOutputError::SendDisconnectedError(msg) => assert_eq!(msg, &vec![0x90, 60, 100]),
+        e => panic!("unexpected error {}", e),
+    }
+}
+
+#[test]
+fn scheduled_messages_are_sent_in_order() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    let mut app = connected_app(&loopback, "a");
+
+    let now = Instant::now();
+    let output = output(&app);
+    output.schedule(MidiMessage::Stop, now + Duration::from_millis(30)).unwrap();
+    output.schedule(MidiMessage::Start, now + Duration::from_millis(10)).unwrap();
+    let cancelled = output
+        .schedule(MidiMessage::Reset, now + Duration::from_millis(20))
+        .unwrap();
+    output.schedule(MidiMessage::Continue, now + Duration::from_millis(30)).unwrap();
+    output.cancel(cancelled).unwrap();
+    update_until(&mut app, |_| loopback.sent().len() == 3);
+    assert!(now.elapsed() >= Duration::from_millis(30));
+    assert_eq!(loopback.sent(), vec![vec![0xFA], vec![0xFC], vec![0xFB]]);
+}
+
+#[test]
+fn many_scheduled_messages_keep_their_order() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    let mut app = connected_app(&loopback, "a");
+
+    // Scheduled latest first, with every third one cancelled
+    let start = Instant::now() + Duration::from_millis(20);
+    let output = output(&app);
+    for note in 0..120 {
+        let at = start + Duration::from_micros(10 * (119 - note as u64));
+        let id = output.schedule(MidiMessage::note_on(0, note, 100).unwrap(), at);
+        if note % 3 == 0 {
+            output.cancel(id.unwrap()).unwrap();
+        }
+    }
+    update_until(&mut app, |_| loopback.sent().len() == 80);
+    let expected: Vec<_> = (0..120)
+        .rev()
+        .filter(|note| note % 3 != 0)
+        .map(|note| vec![0x90, note, 100])
+        .collect();
+    assert_eq!(loopback.sent(), expected);
+}
+
+#[test]
+fn scheduled_beats_follow_the_clock() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    let mut app = connected_app(&loopback, "a");
+
+    output(&app).schedule_beat(MidiMessage::Stop, 2.0).unwrap();
+    output(&app).schedule_beat(MidiMessage::Start, 1.0).unwrap();
+    // Nothing is sent while the clock puts the beats an hour off
+    output(&app)
+        .set_clock(BeatClock::starting_at(Instant::now() + Duration::from_secs(3600), 60.0))
+        .unwrap();
+    sleep(Duration::from_millis(20));
+    app.update();
+    assert!(loopback.sent().is_empty());
+
+    // A beat every 10ms
+    let start = Instant::now();
+    output(&app)
+        .set_clock(BeatClock::starting_at(start, 6000.0))
+        .unwrap();
+    update_until(&mut app, |_| loopback.sent().len() == 2);
+    assert!(start.elapsed() >= Duration::from_millis(20));
+    assert_eq!(loopback.sent(), vec![vec![0xFA], vec![0xFC]]);
+}
+
+#[test]
+fn all_notes_off_cancels_and_silences() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    let mut app = connected_app(&loopback, "a");
+
+    let output = output(&app);
+    output.send_message(MidiMessage::note_on(2, 64, 100).unwrap()).unwrap();
+    output.schedule(MidiMessage::Start, Instant::now() + Duration::from_secs(60)).unwrap();
+    output.all_notes_off().unwrap();
+    update_until(&mut app, |_| loopback.sent().len() == 18);
+
+    let sent = loopback.take_sent();
+    assert_eq!(sent[1], vec![0x82, 64, 0]);
+    for channel in 0..16 {
+        assert_eq!(sent[2 + channel as usize], vec![0xB0 | channel, 123, 0]);
+    }
+}
+
+fn status(app: &App) -> MidiOutputStatus {
+    *app.world.resource::<MidiOutputStatus>()
+}
+
+#[test]
+fn runs_once_started() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    let mut app = app(&loopback);
+    assert_eq!(status(&app), MidiOutputStatus::Starting);
+    update_until(&mut app, |app| status(app) == MidiOutputStatus::Running);
+}
+
+#[test]
+fn init_failure_stops_the_thread() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.fail_next_open();
+    let mut app = app(&loopback);
+    update_until(&mut app, |app| status(app) == MidiOutputStatus::Stopped);
+    update_until(&mut app, |app| errors(app).len() == 2);
+    assert!(matches!(
+        errors(&app),
+        [MidiOutputError::InitError, MidiOutputError::ThreadStopped]
+    ));
+    assert!(matches!(
+        output(&app).send([0x90, 60, 100]),
+        Err(MidiOutputError::ChannelClosed)
+    ));
+}
+
+#[test]
+fn failing_to_open_a_connection_is_reported() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    let mut app = app(&loopback);
+    update_until(&mut app, |app| !port_names(app).is_empty());
+
+    loopback.fail_next_open();
+    let (_, port) = output(&app).ports()[0].clone();
+    output(&app).connect(port).unwrap();
+    update_until(&mut app, |app| !errors(app).is_empty());
+    assert!(matches!(errors(&app), [MidiOutputError::InitError]));
+    assert_eq!(status(&app), MidiOutputStatus::Running);
+    assert!(!is_connected(&app));
+}
+
+#[test]
+fn thread_death_is_reported() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    let mut app = connected_app(&loopback, "a");
+
+    loopback.panic_next_send();
+    output(&app).send([0x90, 60, 100]).unwrap();
+    update_until(&mut app, |app| status(app) == MidiOutputStatus::Stopped);
+    update_until(&mut app, |app| !errors(app).is_empty());
+    assert!(matches!(errors(&app), [MidiOutputError::ThreadStopped]));
+    assert!(!is_connected(&app));
+    assert!(matches!(
+        output(&app).refresh_ports(),
+        Err(MidiOutputError::ChannelClosed)
+    ));
+}
+
+fn virtual_app(loopback: &Loopback) -> App {
+    app_with(MidiOutputSettings {
+        port_name: "test",
+        backend: MidiOutputBackend::Loopback(loopback.clone()),
+        poll_interval: None,
+        reconnect_buffer: 0,
+        virtual_port: Some("virtual"),
+    })
+}
+
+#[test]
+fn virtual_port_receives_everything_sent() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    let mut app = virtual_app(&loopback);
+    update_until(&mut app, |app| !port_names(app).is_empty());
+    assert_eq!(loopback.virtual_ports(), vec!["virtual"]);
+    assert_eq!(port_names(&app), vec!["a"]);
+
+    output(&app).send([0x90, 60, 100]).unwrap();
+    update_until(&mut app, |_| loopback.sent().len() == 1);
+    assert_eq!(loopback.sent_to("virtual"), vec![vec![0x90, 60, 100]]);
+
+    let mut app = connect(app, "a");
+    output(&app).send([0x80, 60, 0]).unwrap();
+    update_until(&mut app, |_| loopback.sent().len() == 3);
+    assert_eq!(loopback.sent_to("a"), vec![vec![0x80, 60, 0]]);
+    assert_eq!(
+        loopback.sent_to("virtual"),
+        vec![vec![0x90, 60, 100], vec![0x80, 60, 0]]
+    );
+    assert!(errors(&app).is_empty());
+}
+
+#[test]
+fn failing_to_create_a_virtual_port_is_reported() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.fail_next_connect(ConnectErrorKind::Other("no virtual ports"));
+    let mut app = virtual_app(&loopback);
+    update_until(&mut app, |app| !errors(app).is_empty());
+    assert!(matches!(
+        errors(&app),
+        [MidiOutputError::ConnectionError(ConnectErrorKind::Other(
+            "no virtual ports"
+        ))]
+    ));
+    assert!(loopback.virtual_ports().is_empty());
+
+    output(&app).send([0x90, 60, 100]).unwrap();
+    update_until(&mut app, |app| errors(app).len() == 2);
+    assert!(matches!(
+        errors(&app)[1],
+        MidiOutputError::SendDisconnectedError(_)
+    ));
+}
+
+#[test]
+fn connects_to_several_ports() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    loopback.add_port("b");
+    loopback.add_port("c");
+    let mut app = connect(connected_app(&loopback, "a"), "b");
+    assert_eq!(loopback.connected_ports(), vec!["a", "b"]);
+    let names: Vec<_> = app.world.resource::<MidiOutputConnections>().ports().to_vec();
+    assert_eq!(
+        names,
+        vec![
+            ("a".to_string(), MidiConnectionStatus::Connected),
+            ("b".to_string(), MidiConnectionStatus::Connected)
+        ]
+    );
+
+    output(&app).send([0x90, 60, 100]).unwrap();
+    update_until(&mut app, |_| loopback.sent().len() == 2);
+    assert_eq!(loopback.sent_to("a"), vec![vec![0x90, 60, 100]]);
+    assert_eq!(loopback.sent_to("b"), vec![vec![0x90, 60, 100]]);
+
+    output(&app).disconnect("a").unwrap();
+    update_until(&mut app, |app| connection_status(app, "a").is_none());
+    assert_eq!(connection_status(&app, "b"), Some(MidiConnectionStatus::Connected));
+    assert_eq!(loopback.connected_ports(), vec!["b"]);
+    assert!(errors(&app).is_empty());
+}
+
+#[test]
+fn routes_to_one_port() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    loopback.add_port("b");
+    let mut app = connect(connected_app(&loopback, "a"), "b");
+
+    let output = output(&app);
+    output
+        .send_message_to(MidiMessage::Start, MidiRoute::Port("b".into()))
+        .unwrap();
+    output
+        .send_message_to(MidiMessage::Stop, MidiRoute::Port("c".into()))
+        .unwrap();
+    update_until(&mut app, |app| !errors(app).is_empty());
+    assert!(loopback.sent_to("a").is_empty());
+    assert_eq!(loopback.sent_to("b"), vec![vec![0xFA]]);
+    assert!(matches!(
+        errors(&app),
+        [MidiOutputError::SendDisconnectedError(msg)] if msg == &[0xFC]
+    ));
+}
+
+#[test]
+fn routes_by_channel() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    loopback.add_port("b");
+    let mut app = connect(connected_app(&loopback, "a"), "b");
+
+    let output = output(&app);
+    output.map_channel(1, &["b"]).unwrap();
+    output.send_message(MidiMessage::note_on(1, 60, 100).unwrap()).unwrap();
+    output.send_message(MidiMessage::note_on(0, 60, 100).unwrap()).unwrap();
+    output.send_message(MidiMessage::TimingClock).unwrap();
+    output.map_channel(1, &[]).unwrap();
+    assert!(matches!(
+        output.map_channel(16, &["b"]),
+        Err(MidiOutputError::InvalidMessage(
+            MidiMessageError::ChannelOutOfRange(16)
+        ))
+    ));
+    output.send_message(MidiMessage::note_off(1, 60, 0).unwrap()).unwrap();
+    update_until(&mut app, |_| loopback.sent().len() == 7);
+    assert_eq!(
+        loopback.sent_to("a"),
+        vec![vec![0x90, 60, 100], vec![0xF8], vec![0x81, 60, 0]]
+    );
+    assert_eq!(
+        loopback.sent_to("b"),
+        vec![
+            vec![0x91, 60, 100],
+            vec![0x90, 60, 100],
+            vec![0xF8],
+            vec![0x81, 60, 0]
+        ]
+    );
+}
+
+#[test]
+fn tracks_lost_ports_separately() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    loopback.add_port("b");
+    let mut app = connect(connect(polling_app(&loopback, 0), "a"), "b");
+
+    loopback.remove_port("a");
+    update_until(&mut app, |app| {
+        connection_status(app, "a") == Some(MidiConnectionStatus::Lost)
+    });
+    assert_eq!(
+        connection_status(&app, "b"),
+        Some(MidiConnectionStatus::Connected)
+    );
+    assert!(is_connected(&app));
+
+    output(&app).send([0x90, 60, 100]).unwrap();
+    update_until(&mut app, |app| !errors(app).is_empty());
+    assert_eq!(loopback.sent_to("b"), vec![vec![0x90, 60, 100]]);
+
+    loopback.add_port("a");
+    update_until(&mut app, |app| {
+        connection_status(app, "a") == Some(MidiConnectionStatus::Connected)
+    });
+    assert_eq!(loopback.connected_ports(), vec!["b", "a"]);
+}
+
+#[test]
+fn clock_master_sends_timing_clocks() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    let mut app = connected_app(&loopback, "a");
+
+    // At 600 bpm a beat is 100ms
+    let start = Instant::now() + Duration::from_millis(20);
+    output(&app)
+        .set_clock(BeatClock::starting_at(start, 600.0))
+        .unwrap();
+    output(&app).start_clock().unwrap();
+    // The start, then a beat and a clock of timing clocks
+    update_until(&mut app, |_| loopback.sent().len() > 25);
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    let sent = loopback.sent();
+    assert_eq!(sent[0], vec![0xFA]);
+    assert!(sent[1..].iter().all(|msg| msg == &[0xF8]));
+
+    output(&app).stop_clock().unwrap();
+    update_until(&mut app, |_| loopback.sent().last() == Some(&vec![0xFC]));
+    let stopped = loopback.sent().len();
+    sleep(Duration::from_millis(20));
+    app.update();
+    assert_eq!(loopback.sent().len(), stopped);
+}
+
+#[test]
+fn clock_continues_from_the_next_sixteenth() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    let mut app = connected_app(&loopback, "a");
+
+    // A bit past beat 1.1, so the next sixteenth is the sixth
+    let origin = Instant::now() - Duration::from_millis(1100);
+    output(&app)
+        .set_clock(BeatClock::starting_at(origin, 60.0))
+        .unwrap();
+    output(&app).continue_clock().unwrap();
+    update_until(&mut app, |_| loopback.sent().len() >= 3);
+    assert!(origin.elapsed() >= Duration::from_millis(1250));
+    assert_eq!(
+        loopback.sent()[..3],
+        [vec![0xF2, 5, 0], vec![0xFB], vec![0xF8]]
+    );
+}
+
+#[test]
+fn lost_ports_miss_timing_clocks() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    loopback.add_port("a");
+    let mut app = connect(polling_app(&loopback, 2), "a");
+
+    output(&app)
+        .set_clock(BeatClock::starting_at(Instant::now(), 600.0))
+        .unwrap();
+    output(&app).start_clock().unwrap();
+    update_until(&mut app, |_| loopback.sent().len() > 5);
+    loopback.remove_port("a");
+    update_until(&mut app, |app| {
+        connection_status(app, "a") == Some(MidiConnectionStatus::Lost)
+    });
+    // Timing clocks aren't held or reported while the port is gone, leaving room for
+    // the note
+    output(&app).send([0x90, 60, 100]).unwrap();
+    let lost = Instant::now();
+    while lost.elapsed() < Duration::from_millis(50) {
+        app.update();
+        sleep(Duration::from_millis(1));
+    }
+    assert!(errors(&app).is_empty(), "{:?}", errors(&app));
+
+    loopback.take_sent();
+    loopback.add_port("a");
+    update_until(&mut app, |_| loopback.sent().len() > 2);
+    let sent = loopback.sent();
+    assert_eq!(sent[0], vec![0x90, 60, 100]);
+    assert!(sent[1..].iter().all(|msg| msg == &[0xF8]));
+}
@@ -0,0 +1,208 @@
+mod common;
+
+use a2::bevy_midi::input::MidiData;
+use a2::bevy_midi::message::MidiMessage;
+use a2::bevy_midi::output::*;
+use a2::bevy_midi::player::{MidiPlayer, MidiPlayerPlugin};
+use a2::bevy_midi::recorder::{MidiRecorder, MidiRecorderPlugin, MidiSource, TICKS_PER_BEAT};
+use a2::bevy_midi::smf::{Smf, TrackEvent, TrackEventKind};
+use bevy::prelude::*;
+use common::{connect, output, serial, update_until};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+// Starts an app which records and plays, connected to the loopback port "a"
+fn app(loopback: &Loopback) -> App {
+    loopback.add_port("a");
+    let mut app = common::app(loopback);
+    // Stands in for MidiInputPlugin, which needs real midi ports
+    app.add_event::<MidiData>()
+        .add_plugin(MidiRecorderPlugin)
+        .add_plugin(MidiPlayerPlugin);
+    connect(app, "a")
+}
+
+fn recorder(app: &App) -> &MidiRecorder {
+    app.world.resource::<MidiRecorder>()
+}
+
+fn note_on(note: u8) -> MidiMessage {
+    MidiMessage::note_on(0, note, 100).unwrap()
+}
+
+fn note_off(note: u8) -> MidiMessage {
+    MidiMessage::note_off(0, note, 0).unwrap()
+}
+
+fn event(tick: u64, msg: MidiMessage) -> TrackEvent {
+    TrackEvent {
+        tick,
+        kind: TrackEventKind::Midi(msg),
+    }
+}
+
+fn play_at(app: &mut App, smf: &Smf, start: Instant) {
+    app.world
+        .resource_scope(|world, mut player: Mut<MidiPlayer>| {
+            player.play_at(smf, start, world.resource::<MidiOutput>())
+        })
+        .unwrap();
+}
+
+// Starts recording, and waits for MidiOutput to be tapped
+fn start_recording(app: &mut App) {
+    app.world.resource_mut::<MidiRecorder>().start();
+    app.update();
+}
+
+#[test]
+fn records_messages_sent_and_received() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    let mut app = app(&loopback);
+    output(&app).send_message(note_on(59)).unwrap();
+    start_recording(&mut app);
+
+    sleep(Duration::from_millis(20));
+    output(&app).send_message(note_on(60)).unwrap();
+    output(&app).send_message(MidiMessage::TimingClock).unwrap();
+    update_until(&mut app, |app| recorder(app).recorded().len() == 2);
+    app.world.send_event(MidiData {
+        stamp: 0,
+        received: Instant::now(),
+        message: note_on(61).to_bytes(),
+    });
+    app.update();
+
+    let recorded = recorder(&app).recorded();
+    let sources: Vec<_> = recorded.iter().map(|r| r.source).collect();
+    assert_eq!(
+        sources,
+        vec![MidiSource::Output, MidiSource::Output, MidiSource::Input]
+    );
+    assert_eq!(recorded[0].message, note_on(60).to_bytes());
+    assert!(recorded[0].at >= Duration::from_millis(20));
+    assert!(recorded[2].at >= recorded[0].at);
+
+    app.world.resource_mut::<MidiRecorder>().stop();
+    app.update();
+    output(&app).send_message(note_on(62)).unwrap();
+    update_until(&mut app, |_| loopback.sent().len() == 4);
+    sleep(Duration::from_millis(20));
+    app.update();
+    assert_eq!(recorder(&app).recorded().len(), 3);
+}
+
+#[test]
+fn saves_recordings_as_midi_files() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    let mut app = app(&loopback);
+    start_recording(&mut app);
+    sleep(Duration::from_millis(50));
+    output(&app).send_message(note_on(60)).unwrap();
+    output(&app).send_message(MidiMessage::Start).unwrap();
+    update_until(&mut app, |app| recorder(app).recorded().len() == 2);
+
+    let smf = recorder(&app).to_smf(60.0);
+    assert_eq!(smf.tracks.len(), 3);
+    assert_eq!(smf.tracks[0][0].kind, TrackEventKind::Tempo(1_000_000));
+    // The track name, then the note on without the start
+    let output_track = &smf.tracks[1];
+    assert_eq!(output_track.len(), 2);
+    assert_eq!(output_track[1].kind, TrackEventKind::Midi(note_on(60)));
+    let at = recorder(&app).recorded()[0].at.as_secs_f64();
+    let tick = (at * TICKS_PER_BEAT as f64).round() as u64;
+    assert_eq!(output_track[1].tick, tick);
+    assert!(tick >= TICKS_PER_BEAT as u64 / 20);
+    assert_eq!(smf.tracks[2].len(), 1);
+
+    let path = std::env::temp_dir().join("a2_midi_recording_test.mid");
+    recorder(&app).save(&path, 60.0).unwrap();
+    let read = Smf::read(&path);
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(read, Ok(smf));
+}
+
+#[test]
+fn plays_files_by_their_tempo_map() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    let mut app = app(&loopback);
+    // A beat of 100ms, then of 200ms from the first beat on
+    let mut smf = Smf::new(10);
+    smf.tracks.push(vec![
+        TrackEvent {
+            tick: 0,
+            kind: TrackEventKind::Tempo(100_000),
+        },
+        TrackEvent {
+            tick: 10,
+            kind: TrackEventKind::Tempo(200_000),
+        },
+    ]);
+    smf.tracks.push(vec![
+        event(0, note_on(60)),
+        event(10, note_off(60)),
+        event(10, note_on(62)),
+        event(20, note_off(62)),
+    ]);
+
+    let start = Instant::now();
+    play_at(&mut app, &smf, start);
+    let mut times = Vec::new();
+    for count in [1, 3, 4] {
+        update_until(&mut app, |_| loopback.sent().len() >= count);
+        times.push(start.elapsed());
+    }
+    assert!(times[1] >= Duration::from_millis(100), "{:?}", times);
+    assert!(times[2] >= Duration::from_millis(300), "{:?}", times);
+    assert!(times[2] < Duration::from_millis(400), "{:?}", times);
+    let expected: Vec<_> = [note_on(60), note_off(60), note_on(62), note_off(62)]
+        .iter()
+        .map(|msg| msg.to_bytes())
+        .collect();
+    assert_eq!(loopback.sent(), expected);
+
+    update_until(&mut app, |app| {
+        !app.world.resource::<MidiPlayer>().is_playing()
+    });
+}
+
+#[test]
+fn stopping_cancels_and_turns_notes_off() {
+    let _serial = serial();
+    let loopback = Loopback::new();
+    let mut app = app(&loopback);
+    let mut smf = Smf::new(1);
+    smf.tracks.push(vec![
+        event(0, note_on(60)),
+        event(0, note_on(64)),
+        event(0, note_off(64)),
+        event(1, note_off(60)),
+    ]);
+
+    // The last note off is due in 400ms, well within the lookahead
+    app.world.resource_mut::<MidiPlayer>().lookahead = Duration::from_secs(1);
+    play_at(&mut app, &smf, Instant::now() - Duration::from_millis(100));
+    update_until(&mut app, |_| loopback.sent().len() == 3);
+    app.world
+        .resource_scope(|app, mut player: Mut<MidiPlayer>| {
+            assert!(player.is_playing());
+            player.stop(app.resource::<MidiOutput>()).unwrap();
+            assert!(!player.is_playing());
+        });
+    update_until(&mut app, |_| loopback.sent().len() == 4);
+    assert_eq!(
+        loopback.sent()[3],
+        MidiMessage::NoteOff {
+            channel: 0,
+            note: 60,
+            velocity: 0
+        }
+        .to_bytes()
+    );
+    sleep(Duration::from_millis(450));
+    app.update();
+    assert_eq!(loopback.sent().len(), 4);
+}
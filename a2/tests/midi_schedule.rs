@@ -0,0 +1,43 @@
+use a2::bevy_midi::schedule::BeatClock;
+use std::time::{Duration, Instant};
+
+#[test]
+fn beats_follow_tempo() {
+    let origin = Instant::now();
+    let clock = BeatClock::starting_at(origin, 120.0);
+    assert_eq!(clock.beat_duration(), Duration::from_millis(500));
+    assert_eq!(clock.instant_at(4.0), Some(origin + Duration::from_secs(2)));
+    assert!((clock.beat_at(origin + Duration::from_millis(750)) - 1.5).abs() < 1e-9);
+}
+
+#[test]
+fn beats_before_origin_are_negative() {
+    let origin = Instant::now() + Duration::from_secs(10);
+    let clock = BeatClock::starting_at(origin, 60.0);
+    assert!((clock.beat_at(origin - Duration::from_secs(3)) + 3.0).abs() < 1e-9);
+    assert_eq!(clock.instant_at(-3.0), Some(origin - Duration::from_secs(3)));
+}
+
+#[test]
+fn tempo_changes_keep_the_current_beat() {
+    let origin = Instant::now();
+    let change = origin + Duration::from_secs(2);
+    let clock = BeatClock::starting_at(origin, 120.0).with_tempo(60.0, change);
+    assert_eq!(clock.tempo(), 60.0);
+    assert!((clock.beat_at(change) - 4.0).abs() < 1e-9);
+    assert!((clock.beat_at(change + Duration::from_secs(1)) - 5.0).abs() < 1e-9);
+}
+
+#[test]
+fn unrepresentable_beats_have_no_instant() {
+    let clock = BeatClock::new(120.0);
+    assert_eq!(clock.instant_at(f64::NAN), None);
+    assert_eq!(clock.instant_at(f64::INFINITY), None);
+    assert_eq!(clock.instant_at(1e300), None);
+}
+
+#[test]
+#[should_panic]
+fn tempo_must_be_positive() {
+    let _ = BeatClock::new(0.0);
+}
@@ -0,0 +1,179 @@
+use a2::bevy_midi::message::{MidiMessage, MidiMessageError};
+use a2::bevy_midi::smf::*;
+
+fn event(tick: u64, kind: TrackEventKind) -> TrackEvent {
+    TrackEvent { tick, kind }
+}
+
+fn note_on(tick: u64, note: u8) -> TrackEvent {
+    let msg = MidiMessage::note_on(0, note, 100).unwrap();
+    event(tick, TrackEventKind::Midi(msg))
+}
+
+// A file with a header and one track chunk holding `track`
+fn file(division: [u8; 2], track: &[u8]) -> Vec<u8> {
+    let mut bytes = b"MThd\0\0\0\x06\0\0\0\x01".to_vec();
+    bytes.extend(division);
+    bytes.extend(b"MTrk");
+    bytes.extend((track.len() as u32).to_be_bytes());
+    bytes.extend(track);
+    bytes
+}
+
+#[test]
+fn parses_running_status_and_meta_events() {
+    let track = [
+        0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, // tempo of 500000
+        0x00, 0x90, 60, 100, // note on
+        0x81, 0x40, 64, 100, // note on at 192, with running status
+        0x60, 0xFF, 0x01, 0x02, b'h', b'i', // text
+        0x00, 0xF0, 0x03, 0x7E, 0x01, 0xF7, // sysex
+        0x00, 0xFF, 0x2F, 0x00, // end of track
+    ];
+    let smf = Smf::from_bytes(&file([0x01, 0xE0], &track)).unwrap();
+    assert_eq!(smf.format, SmfFormat::SingleTrack);
+    assert_eq!(smf.timing, Timing::TicksPerBeat(480));
+    let sysex = MidiMessage::sysex([0x7E, 0x01]).unwrap();
+    assert_eq!(
+        smf.tracks,
+        vec![vec![
+            event(0, TrackEventKind::Tempo(500_000)),
+            note_on(0, 60),
+            note_on(192, 64),
+            event(288, TrackEventKind::Meta(0x01, b"hi".to_vec())),
+            event(288, TrackEventKind::Midi(sysex)),
+        ]]
+    );
+}
+
+#[test]
+fn round_trips_through_bytes() {
+    let mut smf = Smf::new(96);
+    smf.tracks.push(vec![
+        event(0, TrackEventKind::Tempo(400_000)),
+        event(0, TrackEventKind::Meta(0x03, b"tempo".to_vec())),
+    ]);
+    smf.tracks.push(vec![
+        note_on(0, 60),
+        note_on(0, 64),
+        event(1 << 20, TrackEventKind::Escape(vec![0xF3, 0x01])),
+        note_on(1 << 20, 67),
+        event(
+            (1 << 20) + 5,
+            TrackEventKind::Midi(MidiMessage::pitch_bend(3, 1000).unwrap()),
+        ),
+    ]);
+    let bytes = smf.to_bytes().unwrap();
+    assert_eq!(Smf::from_bytes(&bytes), Ok(smf));
+}
+
+#[test]
+fn masks_sysex_data_when_writing() {
+    let mut smf = Smf::new(96);
+    let sysex = MidiMessage::SysEx(vec![0x01, 0x80, 0xF8]);
+    smf.tracks.push(vec![event(0, TrackEventKind::Midi(sysex))]);
+    let read = Smf::from_bytes(&smf.to_bytes().unwrap()).unwrap();
+    let masked = MidiMessage::sysex(vec![0x01, 0x00, 0x78]).unwrap();
+    assert_eq!(read.tracks[0], vec![event(0, TrackEventKind::Midi(masked))]);
+}
+
+#[test]
+fn writes_system_messages_as_escapes() {
+    let mut smf = Smf::new(96);
+    let position = MidiMessage::song_position(8).unwrap();
+    smf.tracks
+        .push(vec![event(0, TrackEventKind::Midi(position))]);
+    let read = Smf::from_bytes(&smf.to_bytes().unwrap()).unwrap();
+    assert_eq!(
+        read.tracks[0],
+        vec![event(0, TrackEventKind::Escape(vec![0xF2, 0x08, 0x00]))]
+    );
+}
+
+#[test]
+fn reads_timecode_timing() {
+    let smf = Smf::from_bytes(&file([0xE7, 40], &[0x00, 0xFF, 0x2F, 0x00])).unwrap();
+    let timing = Timing::Timecode {
+        fps: 25,
+        ticks_per_frame: 40,
+    };
+    assert_eq!(smf.timing, timing);
+    assert!(smf.tracks[0].is_empty());
+    assert_eq!(Smf::from_bytes(&smf.to_bytes().unwrap()).unwrap().timing, timing);
+    // A thousand ticks a second
+    assert!((TempoMap::new(&smf).seconds_at(1500) - 1.5).abs() < 1e-9);
+}
+
+#[test]
+fn rejects_unsupported_frame_rates() {
+    let empty = [0x00, 0xFF, 0x2F, 0x00];
+    assert_eq!(Smf::from_bytes(&file([0xE0, 40], &empty)), Err(SmfError::UnsupportedFps(32)));
+    for fps in [0, 31, 128, 255] {
+        let mut smf = Smf::new(96);
+        smf.timing = Timing::Timecode {
+            fps,
+            ticks_per_frame: 40,
+        };
+        assert_eq!(smf.to_bytes(), Err(SmfError::UnsupportedFps(fps)));
+    }
+}
+
+#[test]
+fn skips_unknown_chunks() {
+    let mut bytes = file([0x00, 0x60], &[0x00, 0x90, 60, 100]);
+    bytes.extend(b"XFIH\0\0\0\x02ab");
+    let smf = Smf::from_bytes(&bytes).unwrap();
+    assert_eq!(smf.tracks, vec![vec![note_on(0, 60)]]);
+}
+
+#[test]
+fn rejects_broken_files() {
+    assert_eq!(
+        Smf::from_bytes(b"RIFF\0\0\0\0"),
+        Err(SmfError::MissingHeader)
+    );
+    let bytes = file([0x00, 0x60], &[0x00, 0x90, 60]);
+    assert_eq!(Smf::from_bytes(&bytes), Err(SmfError::UnexpectedEnd));
+    let bytes = file([0x00, 0x60], &[0x00, 60, 100]);
+    let missing_status = MidiMessageError::MissingStatus(60);
+    assert_eq!(
+        Smf::from_bytes(&bytes),
+        Err(SmfError::InvalidMessage(missing_status))
+    );
+    let bytes = file([0x00, 0x60], &[0xFF, 0xFF, 0xFF, 0xFF, 0x00]);
+    assert_eq!(Smf::from_bytes(&bytes), Err(SmfError::InvalidVarLen));
+    let mut bytes = file([0x00, 0x60], &[]);
+    bytes[9] = 3;
+    assert_eq!(Smf::from_bytes(&bytes), Err(SmfError::UnsupportedFormat(3)));
+}
+
+#[test]
+fn tempo_map_follows_tempo_changes() {
+    let mut smf = Smf::new(100);
+    smf.tracks
+        .push(vec![event(200, TrackEventKind::Tempo(1_000_000))]);
+    smf.tracks.push(vec![note_on(300, 60)]);
+    let map = TempoMap::new(&smf);
+    // Two beats at the default 120 bpm, then 60 bpm
+    assert!((map.seconds_at(100) - 0.5).abs() < 1e-9);
+    assert!((map.seconds_at(200) - 1.0).abs() < 1e-9);
+    assert!((map.seconds_at(350) - 2.5).abs() < 1e-9);
+    assert!((map.tempo_at(199) - 120.0).abs() < 1e-9);
+    assert!((map.tempo_at(200) - 60.0).abs() < 1e-9);
+}
+
+#[test]
+fn timeline_merges_tracks_in_order() {
+    let mut smf = Smf::new(96);
+    smf.tracks.push(vec![note_on(10, 1), note_on(20, 2)]);
+    smf.tracks.push(vec![note_on(0, 3), note_on(10, 4)]);
+    let notes: Vec<_> = smf
+        .timeline()
+        .into_iter()
+        .map(|(tick, kind)| match kind {
+            TrackEventKind::Midi(MidiMessage::NoteOn { note, .. }) => (tick, *note),
+            _ => panic!("not a note on"),
+        })
+        .collect();
+    assert_eq!(notes, vec![(0, 3), (10, 1), (10, 4), (20, 2)]);
+}
@@ -0,0 +1,125 @@
+use a2::prelude::*;
+use rodio::buffer::SamplesBuffer;
+
+fn track_wave(wave: fn(f32) -> f32, frequency: f32) -> Vec<PitchEstimate> {
+    let source = wave.wavetable(1024).source(44100).with_frequency(frequency);
+    PitchTracker::new(2048, 512)
+        .track(source)
+        .take(20)
+        .collect()
+}
+
+#[test]
+fn tracks_sine_waves() {
+    for frequency in [55.0, 110.0, 261.63, 440.0, 1000.0, 3000.0] {
+        let estimates = track_wave(wave::sin, frequency);
+        assert_eq!(estimates.len(), 20);
+        for estimate in estimates {
+            let error = (estimate.frequency - frequency).abs() / frequency;
+            assert!(error < 2e-3, "{frequency}Hz: {estimate:?}");
+            assert!(estimate.confidence > 0.95, "{frequency}Hz: {estimate:?}");
+        }
+    }
+}
+
+#[test]
+fn tracks_harmonic_waves_at_their_fundamental() {
+    for wave in [wave::sawtooth, wave::square, wave::triangle] {
+        for estimate in track_wave(wave, 196.0) {
+            assert!((estimate.frequency - 196.0).abs() < 0.5, "{estimate:?}");
+        }
+    }
+}
+
+#[test]
+fn times_are_frame_centers() {
+    let times: Vec<f32> = track_wave(wave::sin, 440.0)
+        .iter()
+        .take(3)
+        .map(|estimate| estimate.time)
+        .collect();
+    assert_eq!(
+        times,
+        [1024.0 / 44100.0, 1536.0 / 44100.0, 2048.0 / 44100.0]
+    );
+}
+
+#[test]
+fn finite_sources_give_whole_frames() {
+    let samples: Vec<f32> = wave::sin
+        .source(8000)
+        .with_frequency(200.0)
+        .take(4000)
+        .collect();
+    let source = SamplesBuffer::new(1, 8000, samples);
+    // Frames start at every multiple of 300 up to 4000 - 1000
+    assert_eq!(PitchTracker::new(1000, 300).track(source).count(), 11);
+
+    let source = SamplesBuffer::new(1, 8000, vec![0.0; 999]);
+    assert_eq!(PitchTracker::new(1000, 300).track(source).count(), 0);
+}
+
+#[test]
+fn mixes_channels_down() {
+    let samples: Vec<f32> = wave::sin
+        .source(44100)
+        .with_frequency(330.0)
+        .take(8192)
+        .flat_map(|x| [x, 0.5 * x])
+        .collect();
+    let source = SamplesBuffer::new(2, 44100, samples);
+    for estimate in PitchTracker::new(2048, 1024).track(source) {
+        assert!((estimate.frequency - 330.0).abs() < 0.5, "{estimate:?}");
+    }
+}
+
+#[test]
+fn silence_and_noise_have_low_confidence() {
+    let silence = SamplesBuffer::new(1, 44100, vec![0.0; 4096]);
+    for estimate in PitchTracker::new(2048, 1024).track(silence) {
+        assert_eq!(estimate.confidence, 0.0);
+    }
+
+    let noise = wave::noise.source(44100);
+    let estimates: Vec<PitchEstimate> = PitchTracker::new(2048, 1024)
+        .track(noise)
+        .take(10)
+        .collect();
+    let mean_confidence = estimates.iter().map(|e| e.confidence).sum::<f32>() / 10.0;
+    assert!(mean_confidence < 0.5, "{mean_confidence}");
+}
+
+#[test]
+fn nearest_notes() {
+    let estimate = |frequency| PitchEstimate {
+        time: 0.0,
+        frequency,
+        confidence: 1.0,
+    };
+    let (note, cents) = estimate(440.0).nearest_note().unwrap();
+    assert_eq!(note, 69);
+    assert!(cents.abs() < 1e-3);
+
+    let (note, cents) = estimate(midi_to_frequency(60.3)).nearest_note().unwrap();
+    assert_eq!(note, 60);
+    assert!((cents - 30.0).abs() < 1e-2);
+
+    let (note, cents) = estimate(midi_to_frequency(71.6)).nearest_note().unwrap();
+    assert_eq!(note, 72);
+    assert!((cents + 40.0).abs() < 1e-2);
+
+    assert_eq!(estimate(1.0).nearest_note(), None);
+    assert_eq!(estimate(20000.0).nearest_note(), None);
+}
+
+#[test]
+fn tracked_notes_match_the_wavetable() {
+    for note in [45u8, 57, 64, 76] {
+        let estimates = track_wave(wave::sawtooth, midi_to_frequency(note as f32));
+        for estimate in estimates {
+            let (found, cents) = estimate.nearest_note().unwrap();
+            assert_eq!(found, note);
+            assert!(cents.abs() < 5.0, "{cents}");
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use a2::complex::Complex;
+use a2::plot::*;
+use a2::prelude::*;
+use plotters::element::PointCollection;
+use plotters::style::BLACK;
+
+fn points(element: &plotters::element::PathElement<(f32, f32)>) -> Vec<(f32, f32)> {
+    element.point_iter().to_vec()
+}
+
+fn assert_near(a: (f32, f32), b: (f32, f32)) {
+    assert!(
+        (a.0 - b.0).abs() < 1e-5 && (a.1 - b.1).abs() < 1e-5,
+        "{a:?} != {b:?}"
+    );
+}
+
+#[test]
+fn vectors_start_at_the_origin() {
+    let points = points(&vector(Complex::new(2.0, 1.0), BLACK));
+    assert_eq!(points, [(0.0, 0.0), (2.0, 1.0)]);
+}
+
+#[test]
+fn arrow_heads_point_back_along_the_arrow() {
+    let points = points(&arrow(
+        Complex::new(1.0, 1.0),
+        Complex::new(1.0, 6.0),
+        BLACK,
+    ));
+    assert_near(points[0], (1.0, 1.0));
+    assert_near(points[1], (1.0, 6.0));
+    assert_near(points[3], (1.0, 6.0));
+    // Both sides of the head are below the tip, mirrored around the shaft
+    for side in [points[2], points[4]] {
+        let length = ((side.0 - 1.0).powi(2) + (side.1 - 6.0).powi(2)).sqrt();
+        assert!((length - 1.0).abs() < 1e-5);
+        assert!(side.1 < 6.0);
+    }
+    assert!((points[2].0 - 1.0 + points[4].0 - 1.0).abs() < 1e-5);
+}
+
+#[test]
+fn circles_are_closed() {
+    let points = points(&circle(Complex::new(1.0, -1.0), 2.0, BLACK));
+    assert_near(points[0], *points.last().unwrap());
+    for (x, y) in points {
+        let radius = ((x - 1.0).powi(2) + (y + 1.0).powi(2)).sqrt();
+        assert!((radius - 2.0).abs() < 1e-5);
+    }
+}
+
+#[test]
+fn phasor_sums_go_head_to_tail() {
+    let phasors = [
+        Complex::new(1.0, 0.0),
+        Complex::new(0.0, 2.0),
+        Complex::new(-3.0, 1.0),
+    ];
+    let arrows = phasor_sum(&phasors, BLACK);
+    assert_eq!(arrows.len(), 3);
+    let ends: Vec<_> = arrows
+        .iter()
+        .map(|a| (points(a)[0], points(a)[1]))
+        .collect();
+    assert_near(ends[0].0, (0.0, 0.0));
+    assert_near(ends[0].1, (1.0, 0.0));
+    assert_near(ends[1].0, (1.0, 0.0));
+    assert_near(ends[1].1, (1.0, 2.0));
+    assert_near(ends[2].1, (-2.0, 3.0));
+}
+
+#[test]
+fn phasor_traces_follow_the_partials() {
+    let partials = [
+        Partial {
+            frequency: 1.0,
+            amplitude: 1.0,
+            phase: 0.3,
+        },
+        Partial {
+            frequency: 3.0,
+            amplitude: 0.5,
+            phase: 2.0,
+        },
+    ];
+    let times: Vec<f32> = (0..50).map(|i| i as f32 / 50.0).collect();
+    let trace = points(&phasor_trace(&partials, times.iter().copied(), BLACK));
+    for ((_, im), t) in trace.iter().zip(times) {
+        let value: f32 = partials.iter().map(|p| p.at(t)).sum();
+        assert!((im - value).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,98 @@
+use a2::prelude::*;
+use std::f32::consts::TAU;
+
+// A wave with partials at the given (multiple of the fundamental, amplitude, phase)
+fn sum_of_partials(partials: &'static [(f32, f32, f32)]) -> impl Fn(f32) -> f32 {
+    move |t| {
+        partials
+            .iter()
+            .map(|(f, a, p)| a * (TAU * f * t + p).sin())
+            .sum()
+    }
+}
+
+fn assert_phase_close(a: f32, b: f32, tolerance: f32) {
+    let difference = (a - b).rem_euclid(TAU);
+    assert!(difference.min(TAU - difference) < tolerance, "{a} != {b}");
+}
+
+#[test]
+fn goertzel_matches_dft_bin() {
+    let samples: Vec<f32> = (0..64).map(|n| ((n * n) % 7) as f32 - 3.0).collect();
+    let spectrum = RealFftPlan::new(64).forward(&samples);
+    for bin in [0, 1, 5, 31] {
+        let correlation = goertzel(&samples, bin as f64 / 64.0);
+        assert!((correlation.real - spectrum[bin].real as f64).abs() < 1e-3);
+        assert!((correlation.im - spectrum[bin].im as f64).abs() < 1e-3);
+    }
+}
+
+#[test]
+fn estimates_known_harmonics() {
+    const PARTIALS: [(f32, f32, f32); 3] = [(1.0, 0.8, 0.3), (2.0, 0.4, 4.0), (3.0, 0.2, 2.0)];
+    let source = sum_of_partials(&PARTIALS)
+        .wavetable(4096)
+        .source(44100)
+        .with_frequency(100.0);
+
+    let estimates = SinusoidalAnalysis::new(8820).estimate(source, &[100.0, 200.0, 300.0]);
+    for ((_, amplitude, phase), estimate) in PARTIALS.iter().zip(&estimates) {
+        assert!(
+            (estimate.amplitude - amplitude).abs() < 1e-2,
+            "{estimate:?}"
+        );
+        assert_phase_close(estimate.phase, *phase, 1e-2);
+    }
+}
+
+#[test]
+fn estimates_inharmonic_partials() {
+    // Neither frequency completes a whole number of cycles over the block
+    let source = (|t: f32| 0.5 * (TAU * t).sin())
+        .source(8000)
+        .with_frequency(437.3);
+    let estimates = SinusoidalAnalysis::new(3001)
+        .with_window(Window::BlackmanHarris)
+        .estimate(source, &[437.3, 1000.0]);
+
+    assert!((estimates[0].amplitude - 0.5).abs() < 1e-3);
+    assert_phase_close(estimates[0].phase, 0.0, 1e-2);
+    assert!(estimates[1].amplitude < 1e-3);
+}
+
+#[test]
+fn finds_unknown_partials() {
+    const PARTIALS: [(f32, f32, f32); 3] = [(1.0, 1.0, 0.0), (2.5, 0.5, 1.0), (4.2, 0.25, 5.0)];
+    // The partials don't repeat over a period of 100Hz, so the wave runs over one second
+    let wave = sum_of_partials(&PARTIALS);
+    let source = (move |t| wave(100.0 * t)).source(44100).with_frequency(1.0);
+
+    let found = SinusoidalAnalysis::new(8192)
+        .with_window(Window::BlackmanHarris)
+        .find_partials(source, 10, 40.0);
+
+    assert_eq!(found.len(), 3, "{found:?}");
+    for ((multiplier, amplitude, phase), partial) in PARTIALS.iter().zip(&found) {
+        assert!(
+            (partial.frequency - multiplier * 100.0).abs() < 0.5,
+            "{partial:?}"
+        );
+        assert!((partial.amplitude - amplitude).abs() < 0.02, "{partial:?}");
+        assert_phase_close(partial.phase, *phase, 0.05);
+    }
+}
+
+#[test]
+fn found_partials_resynthesize_the_wave() {
+    let wave = |t: f32| 0.6 * (TAU * t).sin() + 0.3 * (3.0 * TAU * t + 1.0).sin();
+    let source = wave.source(44100).with_frequency(220.0);
+    let partials = SinusoidalAnalysis::new(8192)
+        .with_window(Window::BlackmanHarris)
+        .find_partials(source, 4, 60.0);
+
+    let table = WaveTable::from_function(1024, resynthesize(partials, 220.0));
+    for (i, sample) in table.data().iter().enumerate() {
+        let expected = wave(i as f32 / 1024.0);
+        assert!((sample - expected).abs() < 0.02, "{sample} != {expected}");
+    }
+}
@@ -0,0 +1,94 @@
+use a2::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rodio::{buffer::SamplesBuffer, Source};
+use std::time::Duration;
+
+#[test]
+fn windows_are_shaped_correctly() {
+    for window in [
+        Window::Hann,
+        Window::Hamming,
+        Window::BlackmanHarris,
+        Window::Kaiser(8.0),
+    ] {
+        let coefficients = window.symmetric(65);
+        assert!((coefficients[32] - 1.0).abs() < 1e-3, "{window:?}");
+        for i in 0..32 {
+            assert!((coefficients[i] - coefficients[64 - i]).abs() < 1e-6);
+            assert!(coefficients[i] <= coefficients[i + 1] + 1e-6, "{window:?}");
+        }
+    }
+    assert_eq!(Window::Kaiser(0.0).symmetric(8), vec![1.0; 8]);
+
+    // Periodic Hann windows overlapping by half add up to a constant
+    let hann = Window::Hann.periodic(64);
+    for i in 0..32 {
+        assert!((hann[i] + hann[i + 32] - 1.0).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn stft_finds_sine_frequency() {
+    let source = wave::sin
+        .wavetable(4096)
+        .source(8000)
+        .with_frequency(1000.0)
+        .take_duration(Duration::from_secs(1));
+    let spectrogram = Stft::new(256, 64)
+        .with_window(Window::BlackmanHarris)
+        .with_fft_len(1024)
+        .analyze(source);
+
+    assert_eq!(spectrogram.frames.len(), 127);
+    assert_eq!(spectrogram.frames[0].len(), 513);
+
+    let magnitudes = spectrogram.magnitudes_db(-120.0);
+    for frame in &magnitudes[2..123] {
+        let peak = (0..frame.len())
+            .max_by(|a, b| frame[*a].total_cmp(&frame[*b]))
+            .unwrap();
+        assert_eq!(spectrogram.bin_frequency(peak), 1000.0);
+        // A full scale sine comes out at 0 dB
+        assert!(frame[peak].abs() < 0.5, "peak at {} dB", frame[peak]);
+        assert!(frame[10] < -80.0);
+    }
+}
+
+#[test]
+fn inverse_stft_reconstructs_signal() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let signal: Vec<f32> = (0..5000).map(|_| rng.gen_range(-1.0..1.0)).collect();
+
+    let settings = [
+        Stft::new(512, 128),
+        Stft::new(256, 256).with_window(Window::Rectangular),
+        Stft::new(300, 100)
+            .with_window(Window::Hamming)
+            .with_fft_len(512),
+        Stft::new(256, 64).with_window(Window::Kaiser(6.0)),
+    ];
+    for stft in settings {
+        let spectrogram = stft.analyze(SamplesBuffer::new(1, 44100, signal.clone()));
+        let output = stft.synthesize(&spectrogram);
+        assert_eq!(output.len(), signal.len());
+        let error = output
+            .iter()
+            .zip(&signal)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f32::max);
+        assert!(error < 1e-4, "{stft:?} had an error of {error}");
+    }
+}
+
+#[test]
+fn stereo_sources_are_mixed_down() {
+    let stereo = SamplesBuffer::new(2, 1000, [1.0, -1.0].repeat(500));
+    let spectrogram = Stft::new(64, 32).analyze(stereo);
+    assert_eq!(spectrogram.len(), 500);
+    assert!(spectrogram
+        .frames
+        .iter()
+        .flatten()
+        .all(|bin| bin.magnitude() < 1e-6));
+}
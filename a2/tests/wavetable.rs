@@ -0,0 +1,61 @@
+use a2::prelude::*;
+use std::f32::consts::{PI, TAU};
+
+#[test]
+fn harmonics_round_trip() {
+    let harmonics = [(0.5, 0.0), (0.0, 0.0), (0.25, 1.0), (0.125, 4.0)];
+    let table = WaveTable::from_harmonics(64, &harmonics);
+    assert_eq!(table.data().len(), 64);
+
+    let found = table.harmonics();
+    assert_eq!(found.len(), 31);
+    for (i, (amplitude, phase)) in found.iter().enumerate() {
+        let (expected_amplitude, expected_phase) = harmonics.get(i).copied().unwrap_or_default();
+        assert!((amplitude - expected_amplitude).abs() < 1e-5);
+        if expected_amplitude > 0.0 {
+            assert!(
+                (phase - expected_phase).abs() < 1e-4,
+                "{phase} != {expected_phase}"
+            );
+        }
+    }
+}
+
+#[test]
+fn from_harmonics_matches_sum_of_sines() {
+    let harmonics = [(0.5, 0.3), (0.3, 2.0), (0.2, 5.0)];
+    let table = WaveTable::from_harmonics(256, &harmonics);
+    let expected = WaveTable::from_function(256, move |t: f32| {
+        harmonics
+            .iter()
+            .enumerate()
+            .map(|(k, (a, p))| a * (TAU * (k + 1) as f32 * t + p).sin())
+            .sum()
+    });
+    for (sample, expected) in table.data().iter().zip(expected.data().iter()) {
+        assert!((sample - expected).abs() < 1e-5);
+    }
+}
+
+#[test]
+fn harmonics_above_nyquist_are_dropped() {
+    let harmonics: Vec<(f32, f32)> = (1..=20).map(|k| (1.0 / k as f32, 0.0)).collect();
+    let table = WaveTable::from_harmonics(16, &harmonics);
+    let found = table.harmonics();
+    assert_eq!(found.len(), 7);
+    for (k, (amplitude, _)) in found.iter().enumerate() {
+        assert!((amplitude - 1.0 / (k + 1) as f32).abs() < 1e-5);
+    }
+}
+
+#[test]
+fn sawtooth_has_falling_harmonics() {
+    // A sawtooth from -1 to 1 is the sum of -2 / (PI k) sin(TAU k t), give or take the
+    // jump at the start of the table
+    let found = wave::sawtooth.wavetable(4096).harmonics();
+    for (k, (amplitude, phase)) in found.iter().take(20).enumerate() {
+        let k = (k + 1) as f32;
+        assert!((amplitude - 2.0 / (PI * k)).abs() < 1e-3, "{amplitude}");
+        assert!((phase - PI).abs() < 2e-2, "{phase}");
+    }
+}